@@ -0,0 +1,117 @@
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::hint::black_box;
+use std::net::UdpSocket;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use udp_logger_rs::{info, trace, LevelFilter, UdpLogger, WireFmt};
+
+// An allocator, counting allocations, so that the allocations of each record are reported
+// along with its throughput.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// The records logged to measure the allocations of each, once the thread's buffers have grown.
+const ALLOCATION_RECORDS: usize = 1000;
+
+// Report the mean allocations of logging a record.
+fn report_allocations(name: &str, mut log: impl FnMut()) {
+    log();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _record in 0..ALLOCATION_RECORDS {
+        log();
+    }
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+    println!(
+        "{}: {:.2} allocations per record",
+        name,
+        allocations as f64 / ALLOCATION_RECORDS as f64
+    );
+}
+
+// A socket, which the logger sends to, and which is never read, so that records are
+// discarded once its receive buffer fills.
+fn discard_socket() -> UdpSocket {
+    UdpSocket::bind("127.0.0.1:0").expect("unable to bind discard socket")
+}
+
+// A logger at the Info level, sending to the discard socket. Loggers bind the same source,
+// so each is dropped before the next is built.
+fn logger(wire_fmt: WireFmt, discard: &UdpSocket) -> UdpLogger {
+    let destination = discard.local_addr().expect("discard address").to_string();
+    UdpLogger::new()
+        .with_level(LevelFilter::Info)
+        .with_destination(&destination)
+        .with_wire_fmt(wire_fmt)
+        .build()
+        .expect("unable to build logger")
+}
+
+// Records per second, and allocations per record, of each wire format, with and without kvs.
+fn wire_formats(c: &mut Criterion) {
+    let discard = discard_socket();
+    let kvs = [
+        ("user", log::kv::Value::from("chashu")),
+        ("count", log::kv::Value::from(42)),
+        ("ratio", log::kv::Value::from(0.5)),
+        ("cached", log::kv::Value::from(true)),
+    ];
+    let mut group = c.benchmark_group("wire_fmt");
+    group.throughput(Throughput::Elements(1));
+    for (name, wire_fmt) in [
+        ("uncompressed", WireFmt::Uncompressed),
+        ("bytebuffer", WireFmt::ByteBuffer),
+    ] {
+        let logger = logger(wire_fmt, &discard);
+        report_allocations(
+            name,
+            || info!(logger: &logger, target: "bench", "record {}", black_box(1)),
+        );
+        report_allocations(
+            &format!("{}_kvs", name),
+            || info!(logger: &logger, target: "bench", kvs: &kvs, "record {}", black_box(1)),
+        );
+        group.bench_function(name, |b| {
+            b.iter(|| info!(logger: &logger, target: "bench", "record {}", black_box(1)))
+        });
+        group.bench_function(format!("{}_kvs", name), |b| {
+            b.iter(|| info!(logger: &logger, target: "bench", kvs: &kvs, "record {}", black_box(1)))
+        });
+    }
+    group.finish();
+}
+
+// The cost of a record below the logger's level, which should be rejected by enabled()
+// before any formatting.
+fn disabled(c: &mut Criterion) {
+    let discard = discard_socket();
+    let logger = logger(WireFmt::Uncompressed, &discard);
+    let mut group = c.benchmark_group("disabled");
+    group.throughput(Throughput::Elements(1));
+    group.bench_function("trace", |b| {
+        b.iter(|| trace!(logger: &logger, target: "bench", "record {}", black_box(1)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, wire_formats, disabled);
+criterion_main!(benches);
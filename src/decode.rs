@@ -0,0 +1,670 @@
+//! Decoders for the payloads of the [`WireFmt`]s, for writing collectors.
+//!
+//! # Examples
+//!
+//! ```no_run
+//! use udp_logger_rs::decode;
+//!
+//! let socket = std::net::UdpSocket::bind("127.0.0.1:4010").unwrap();
+//! let mut buf = [0; 65_536];
+//! loop {
+//!     let (len, _src) = socket.recv_from(&mut buf).unwrap();
+//!     match decode::decode_bytebuffer(&buf[..len]) {
+//!         Ok(record) => println!("{} [{}] {}", record.level, record.target, record.message),
+//!         Err(err) => eprintln!("{}", err),
+//!     }
+//! }
+//! ```
+//!
+//! [`WireFmt`]: ../enum.WireFmt.html
+
+use crate::{
+    crc32, Base64, BYTE_BUFFER_MAGIC, BYTE_BUFFER_VERSION, BYTE_BUFFER_VERSION_NO_TIMESTAMP,
+    BYTE_BUFFER_VERSION_RAW, BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP, KV_TAG_BOOL, KV_TAG_F64,
+    KV_TAG_I64, KV_TAG_STRING,
+};
+#[cfg(feature = "receiver")]
+use crate::{WireFmt, FRAGMENT_MAGIC};
+use log::Level;
+#[cfg(feature = "receiver")]
+use std::collections::VecDeque;
+#[cfg(feature = "receiver")]
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+
+/// The value of a decoded kv pair.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DecodedValue {
+    /// An integer.
+    I64(i64),
+    /// A float.
+    F64(f64),
+    /// A bool.
+    Bool(bool),
+    /// A string, or any other value.
+    String(String),
+}
+
+impl std::fmt::Display for DecodedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodedValue::I64(value) => write!(f, "{}", value),
+            DecodedValue::F64(value) => write!(f, "{}", value),
+            DecodedValue::Bool(value) => write!(f, "{}", value),
+            DecodedValue::String(value) => f.write_str(value),
+        }
+    }
+}
+
+/// A record decoded from a ByteBuffer payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecodedRecord {
+    /// The level, mapped from the level code.
+    pub level: Level,
+    /// The timestamp, in milliseconds since the Unix epoch, unless the precision was changed,
+    /// or None when the payload was sent without a timestamp.
+    pub timestamp: Option<i64>,
+    /// The target.
+    pub target: String,
+    /// The message, which is the bytes of [`raw`] base64 encoded, when they were logged by
+    /// [`log_bytes`].
+    ///
+    /// [`raw`]: #structfield.raw
+    /// [`log_bytes`]: ../fn.log_bytes.html
+    pub message: String,
+    /// The bytes logged by [`log_bytes`], or None when the message was text.
+    ///
+    /// [`log_bytes`]: ../fn.log_bytes.html
+    pub raw: Option<Vec<u8>>,
+    /// The kv pairs, including the built-in fields, such as "host", in the order sent.
+    pub kvs: Vec<(String, DecodedValue)>,
+}
+
+/// A record parsed from an Uncompressed payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextRecord {
+    /// The sequence number, when sent with sequence numbers.
+    pub sequence: Option<u64>,
+    /// The timestamp, as formatted, which is empty when sent without a timestamp.
+    pub timestamp: String,
+    /// The level.
+    pub level: Level,
+    /// The hostname, app name, process id and thread, as formatted, which may be empty.
+    pub origin: String,
+    /// The target.
+    pub target: String,
+    /// The message, followed by any source location and kv pairs, as formatted.
+    pub message: String,
+}
+
+/// The errors encountered decoding a payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The payload ended before the layout did.
+    Truncated,
+    /// The payload didn't begin with the magic bytes.
+    Magic([u8; 2]),
+    /// The layout version isn't known.
+    Version(u8),
+    /// The level code, or name, isn't known.
+    Level(String),
+    /// A kv value had an unknown type tag.
+    Tag(u8),
+    /// A string wasn't utf8.
+    Utf8,
+    /// Bytes followed the layout, such as an unverified checksum.
+    TrailingBytes(usize),
+    /// The checksum didn't match the payload.
+    Checksum {
+        /// The checksum which followed the payload.
+        expected: u32,
+        /// The checksum of the payload.
+        actual: u32,
+    },
+    /// A text payload didn't match the layout.
+    Syntax(String),
+    /// The wire format can't be decoded.
+    Unsupported,
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "the payload is truncated"),
+            DecodeError::Magic(magic) => write!(f, "unexpected magic bytes {:02x?}", magic),
+            DecodeError::Version(version) => write!(f, "unknown layout version {}", version),
+            DecodeError::Level(level) => write!(f, "unknown level {}", level),
+            DecodeError::Tag(tag) => write!(f, "unknown kv value tag {}", tag),
+            DecodeError::Utf8 => write!(f, "a string isn't utf8"),
+            DecodeError::TrailingBytes(count) => {
+                write!(f, "{} bytes follow the payload", count)
+            }
+            DecodeError::Checksum { expected, actual } => write!(
+                f,
+                "checksum mismatch, expected={:08x} actual={:08x}",
+                expected, actual
+            ),
+            DecodeError::Syntax(err) => write!(f, "unable to parse payload, err={}", err),
+            DecodeError::Unsupported => write!(f, "the wire format can't be decoded"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+// The ByteBuffer layout versions, with and without a timestamp, which carried the target in
+// the message, as "[target] message".
+const BYTE_BUFFER_VERSION_1: u8 = 1;
+const BYTE_BUFFER_VERSION_2: u8 = 2;
+
+// Reads the fields of a ByteBuffer payload, in order.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::Truncated);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn bytes(&mut self) -> Result<&'a [u8], DecodeError> {
+        let len = u32::from_be_bytes(self.array()?) as usize;
+        self.take(len)
+    }
+
+    fn string(&mut self) -> Result<String, DecodeError> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_err| DecodeError::Utf8)
+    }
+}
+
+/// Decode a ByteBuffer payload, sent with the default level codes.
+///
+/// Payloads of the earlier layout versions, which carried the target in the message, are
+/// also decoded.
+///
+/// A payload sent with a checksum, or an HMAC tag, must be verified, and the trailer removed,
+/// see [`verify_checksum`].
+///
+/// [`verify_checksum`]: fn.verify_checksum.html
+pub fn decode_bytebuffer(bytes: &[u8]) -> Result<DecodedRecord, DecodeError> {
+    decode_bytebuffer_with_codes(bytes, [1, 2, 3, 4, 5])
+}
+
+/// Decode a ByteBuffer payload, sent with the level codes provided to
+/// [`with_level_codes`], which are mapped to levels, error first.
+///
+/// Decoding is lossy when codes repeat, a repeated code decodes as the most severe level
+/// with it, so that with syslog severities, `[3, 4, 6, 7, 7]`, Trace records decode as Debug.
+///
+/// [`with_level_codes`]: ../struct.UdpLogger.html#method.with_level_codes
+pub fn decode_bytebuffer_with_codes(
+    bytes: &[u8],
+    codes: [u8; 5],
+) -> Result<DecodedRecord, DecodeError> {
+    let mut reader = Reader { bytes };
+    let magic = reader.array()?;
+    if magic != BYTE_BUFFER_MAGIC {
+        return Err(DecodeError::Magic(magic));
+    }
+    let [version] = reader.array()?;
+    let [code] = reader.array()?;
+    let levels = [
+        Level::Error,
+        Level::Warn,
+        Level::Info,
+        Level::Debug,
+        Level::Trace,
+    ];
+    let level = match codes.iter().position(|known| *known == code) {
+        Some(index) => levels[index],
+        None => return Err(DecodeError::Level(code.to_string())),
+    };
+    let timestamp = match version {
+        BYTE_BUFFER_VERSION | BYTE_BUFFER_VERSION_RAW | BYTE_BUFFER_VERSION_1 => {
+            Some(i64::from_be_bytes(reader.array()?))
+        }
+        BYTE_BUFFER_VERSION_NO_TIMESTAMP
+        | BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP
+        | BYTE_BUFFER_VERSION_2 => None,
+        _ => return Err(DecodeError::Version(version)),
+    };
+    let mut raw = None;
+    let (target, message) = match version {
+        BYTE_BUFFER_VERSION_1 | BYTE_BUFFER_VERSION_2 => {
+            let text = reader.string()?;
+            let (target, message) = text
+                .strip_prefix('[')
+                .and_then(|text| text.split_once("] "))
+                .ok_or_else(|| DecodeError::Syntax(format!("no target in {:?}", text)))?;
+            (target.to_string(), message.to_string())
+        }
+        BYTE_BUFFER_VERSION_RAW | BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP => {
+            let target = reader.string()?;
+            let bytes = reader.bytes()?;
+            raw = Some(bytes.to_vec());
+            (target, Base64(bytes).to_string())
+        }
+        _ => (reader.string()?, reader.string()?),
+    };
+    let count = u16::from_be_bytes(reader.array()?);
+    let mut kvs = Vec::with_capacity(count as usize);
+    for _pair in 0..count {
+        let key = reader.string()?;
+        let [tag] = reader.array()?;
+        let value = match tag {
+            KV_TAG_I64 => DecodedValue::I64(i64::from_be_bytes(reader.array()?)),
+            KV_TAG_F64 => DecodedValue::F64(f64::from_be_bytes(reader.array()?)),
+            KV_TAG_BOOL => DecodedValue::Bool(reader.array::<1>()?[0] != 0),
+            KV_TAG_STRING => DecodedValue::String(reader.string()?),
+            _ => return Err(DecodeError::Tag(tag)),
+        };
+        kvs.push((key, value));
+    }
+    if !reader.bytes.is_empty() {
+        return Err(DecodeError::TrailingBytes(reader.bytes.len()));
+    }
+    Ok(DecodedRecord {
+        level,
+        timestamp,
+        target,
+        message,
+        raw,
+        kvs,
+    })
+}
+
+/// Decode a line of base64 text, such as a ByteBufferBase64 payload, returning the ByteBuffer
+/// payload to decode with [`decode_bytebuffer`]. A trailing line terminator is ignored.
+///
+/// # Examples
+///
+/// ```
+/// use udp_logger_rs::decode;
+///
+/// assert_eq!(decode::decode_base64(b"VUw=\n"), Ok(b"UL".to_vec()));
+/// ```
+///
+/// [`decode_bytebuffer`]: fn.decode_bytebuffer.html
+pub fn decode_base64(line: &[u8]) -> Result<Vec<u8>, DecodeError> {
+    let line = line
+        .strip_suffix(b"\n")
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .unwrap_or(line);
+    if !line.len().is_multiple_of(4) {
+        return Err(DecodeError::Syntax(format!(
+            "base64 length {} isn't a multiple of 4",
+            line.len()
+        )));
+    }
+    let padding = line.iter().rev().take_while(|byte| **byte == b'=').count();
+    if padding > 2 {
+        return Err(DecodeError::Syntax("base64 padding".to_string()));
+    }
+    let mut bytes = Vec::with_capacity(line.len() / 4 * 3);
+    for (index, chunk) in line.chunks(4).enumerate() {
+        let last = (index + 1) * 4 == line.len();
+        let mut bits = 0u32;
+        for (position, byte) in chunk.iter().enumerate() {
+            let sextet = match byte {
+                b'A'..=b'Z' => byte - b'A',
+                b'a'..=b'z' => byte - b'a' + 26,
+                b'0'..=b'9' => byte - b'0' + 52,
+                b'+' => 62,
+                b'/' => 63,
+                b'=' if last && position >= 4 - padding => 0,
+                _ => {
+                    return Err(DecodeError::Syntax(format!(
+                        "base64 byte {:?}",
+                        char::from(*byte)
+                    )))
+                }
+            };
+            bits = bits << 6 | u32::from(sextet);
+        }
+        let len = if last { 3 - padding } else { 3 };
+        bytes.extend_from_slice(&bits.to_be_bytes()[1..1 + len]);
+    }
+    Ok(bytes)
+}
+
+/// Verify the CRC32 checksum which follows a payload sent with [`with_checksum`],
+/// returning the payload without it.
+///
+/// [`with_checksum`]: ../struct.UdpLogger.html#method.with_checksum
+pub fn verify_checksum(bytes: &[u8]) -> Result<&[u8], DecodeError> {
+    if bytes.len() < 4 {
+        return Err(DecodeError::Truncated);
+    }
+    let (payload, checksum) = bytes.split_at(bytes.len() - 4);
+    let expected = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    let actual = crc32(payload);
+    if expected != actual {
+        return Err(DecodeError::Checksum { expected, actual });
+    }
+    Ok(payload)
+}
+
+/// Parse an Uncompressed payload, formatted with the default level style, or that of
+/// [`with_level_style`].
+///
+/// The timestamp is the text preceding the level, and the target is the first bracketed
+/// text following it. As a message may contain anything, the message, source location and
+/// kv pairs can't be told apart, and are returned together. Colors are removed.
+///
+/// [`with_level_style`]: ../struct.UdpLogger.html#method.with_level_style
+pub fn parse_uncompressed(line: &str) -> Result<TextRecord, DecodeError> {
+    let mut plain = String::with_capacity(line.len());
+    let mut rest = line.trim_end_matches(['\r', '\n']);
+    while let Some(start) = rest.find("\x1b[") {
+        plain.push_str(&rest[..start]);
+        rest = match rest[start..].find('m') {
+            Some(end) => &rest[start + end + 1..],
+            None => "",
+        };
+    }
+    plain.push_str(rest);
+
+    let mut rest = plain.as_str();
+    let sequence = match rest.strip_prefix('#').and_then(|rest| rest.split_once(' ')) {
+        Some((sequence, remainder)) => {
+            rest = remainder;
+            Some(
+                sequence
+                    .parse()
+                    .map_err(|_err| DecodeError::Syntax(format!("sequence {:?}", sequence)))?,
+            )
+        }
+        None => None,
+    };
+    let mut offset = 0;
+    let (timestamp, level) = loop {
+        let word = rest[offset..].split(' ').next().unwrap_or_default();
+        if let Ok(level) = word.parse::<Level>() {
+            break (rest[..offset].trim_end(), level);
+        }
+        match rest[offset..].find(' ') {
+            Some(space) => offset += space + 1,
+            None => return Err(DecodeError::Syntax(format!("no level in {:?}", line))),
+        }
+    };
+    let rest = rest[offset..]
+        .split_once(' ')
+        .map_or("", |(_level, rest)| rest);
+    let rest = rest.trim_start();
+    let start = if rest.starts_with('[') {
+        0
+    } else {
+        rest.find(" [")
+            .map(|start| start + 1)
+            .ok_or_else(|| DecodeError::Syntax(format!("no target in {:?}", line)))?
+    };
+    let (target, message) = rest[start + 1..]
+        .split_once(']')
+        .ok_or_else(|| DecodeError::Syntax(format!("no target in {:?}", line)))?;
+    Ok(TextRecord {
+        sequence,
+        timestamp: timestamp.to_string(),
+        level,
+        origin: rest[..start].trim_end().to_string(),
+        target: target.to_string(),
+        message: message.strip_prefix(' ').unwrap_or(message).to_string(),
+    })
+}
+
+/// Parse a line of logfmt, such as a Logfmt payload, into its fields, in order.
+///
+/// Quoted values are strings, with '"' and '\\' unescaped, and unquoted values are
+/// integers, floats or bools, when they read as one, otherwise strings, so that the types
+/// of the kv pairs of a Logfmt payload are preserved.
+pub fn parse_logfmt(line: &str) -> Result<Vec<(String, DecodedValue)>, DecodeError> {
+    let mut fields = Vec::new();
+    let mut rest = line.trim_end_matches(['\r', '\n']);
+    loop {
+        rest = rest.trim_start_matches(' ');
+        if rest.is_empty() {
+            return Ok(fields);
+        }
+        let (key, remainder) = rest
+            .split_once('=')
+            .ok_or_else(|| DecodeError::Syntax(format!("no value for {:?}", rest)))?;
+        if key.contains(' ') {
+            return Err(DecodeError::Syntax(format!("no value for {:?}", key)));
+        }
+        let value = match remainder.strip_prefix('"') {
+            Some(quoted) => {
+                let mut value = String::new();
+                let mut chars = quoted.char_indices();
+                loop {
+                    match chars.next() {
+                        Some((_index, '\\')) => match chars.next() {
+                            Some((_index, c)) => value.push(c),
+                            None => break None,
+                        },
+                        Some((index, '"')) => {
+                            rest = &quoted[index + 1..];
+                            break Some(DecodedValue::String(value));
+                        }
+                        Some((_index, c)) => value.push(c),
+                        None => break None,
+                    }
+                }
+                .ok_or_else(|| DecodeError::Syntax(format!("unterminated value of {:?}", key)))?
+            }
+            None => {
+                let (value, remainder) = remainder.split_once(' ').unwrap_or((remainder, ""));
+                rest = remainder;
+                if let Ok(value) = value.parse() {
+                    DecodedValue::I64(value)
+                } else if let Ok(value) = value.parse() {
+                    DecodedValue::F64(value)
+                } else if let Ok(value) = value.parse() {
+                    DecodedValue::Bool(value)
+                } else {
+                    DecodedValue::String(value.to_string())
+                }
+            }
+        };
+        fields.push((key.to_string(), value));
+    }
+}
+
+/// A payload decoded by a [`UdpLogReceiver`].
+///
+/// [`UdpLogReceiver`]: struct.UdpLogReceiver.html
+#[cfg(feature = "receiver")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Received {
+    /// A ByteBuffer, or ByteBufferBase64, payload.
+    Record(DecodedRecord),
+    /// An Uncompressed, or GzipUncompressed, payload.
+    Line(TextRecord),
+    /// A Logfmt payload.
+    Fields(Vec<(String, DecodedValue)>),
+}
+
+// The most payloads a UdpLogReceiver holds fragments of, after which the fragments of the
+// oldest payload are discarded.
+#[cfg(feature = "receiver")]
+const MAX_PARTIAL_PAYLOADS: usize = 16;
+
+// The fragments, received so far, of a payload from a source.
+#[cfg(feature = "receiver")]
+#[derive(Debug)]
+struct PartialPayload {
+    source: SocketAddr,
+    message_id: u32,
+    fragments: Vec<Option<Vec<u8>>>,
+}
+
+/// A receiver of the payloads sent by a UdpLogger, decoding them as they arrive, which is
+/// useful for tests, and as a local log tap. Requires the `receiver` feature.
+///
+/// The receiver decodes payloads of the wire format the logger sends, reassembling
+/// fragmented payloads. Payloads of the Syslog5424 and Protobuf wire formats, and HMAC
+/// tags, aren't decoded.
+///
+/// # Examples
+///
+/// ```no_run
+/// use udp_logger_rs::decode::{Received, UdpLogReceiver};
+/// use udp_logger_rs::WireFmt;
+///
+/// let mut receiver = UdpLogReceiver::bind("127.0.0.1:4010", WireFmt::ByteBuffer).unwrap();
+/// for received in receiver.records() {
+///     if let Ok(Received::Record(record)) = received {
+///         println!("{} [{}] {}", record.level, record.target, record.message);
+///     }
+/// }
+/// ```
+#[cfg(feature = "receiver")]
+#[derive(Debug)]
+pub struct UdpLogReceiver {
+    socket: UdpSocket,
+    wire_fmt: WireFmt,
+    checksum: bool,
+    partial: VecDeque<PartialPayload>,
+    buffer: Vec<u8>,
+}
+
+#[cfg(feature = "receiver")]
+impl UdpLogReceiver {
+    /// Bind a receiver to the address, decoding payloads of the wire format.
+    pub fn bind<A: ToSocketAddrs>(addr: A, wire_fmt: WireFmt) -> std::io::Result<Self> {
+        Ok(UdpLogReceiver {
+            socket: UdpSocket::bind(addr)?,
+            wire_fmt,
+            checksum: false,
+            partial: VecDeque::new(),
+            buffer: vec![0; 65_536],
+        })
+    }
+
+    /// Verify, and remove, the checksum of ByteBuffer payloads, sent with
+    /// [`with_checksum`].
+    ///
+    /// [`with_checksum`]: ../struct.UdpLogger.html#method.with_checksum
+    #[must_use]
+    pub fn with_checksum(mut self) -> Self {
+        self.checksum = true;
+
+        self
+    }
+
+    /// The address the receiver is bound to.
+    pub fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Limit how long [`recv`] waits for a datagram, which otherwise is forever.
+    ///
+    /// [`recv`]: #method.recv
+    pub fn set_read_timeout(&self, timeout: Option<std::time::Duration>) -> std::io::Result<()> {
+        self.socket.set_read_timeout(timeout)
+    }
+
+    /// Receive the next payload, waiting for each of its fragments, and decode it.
+    pub fn recv(&mut self) -> std::io::Result<Result<Received, DecodeError>> {
+        loop {
+            let (len, source) = self.socket.recv_from(&mut self.buffer)?;
+            let datagram = &self.buffer[..len];
+            if len < 10 || datagram[..2] != FRAGMENT_MAGIC {
+                let payload = datagram.to_vec();
+                return Ok(self.decode(&payload));
+            }
+            let message_id =
+                u32::from_be_bytes([datagram[2], datagram[3], datagram[4], datagram[5]]);
+            let index = usize::from(u16::from_be_bytes([datagram[6], datagram[7]]));
+            let count = usize::from(u16::from_be_bytes([datagram[8], datagram[9]]));
+            if index >= count {
+                return Ok(Err(DecodeError::Syntax(format!(
+                    "fragment {} of {}",
+                    index, count
+                ))));
+            }
+            let position = self
+                .partial
+                .iter()
+                .position(|partial| partial.source == source && partial.message_id == message_id);
+            let position = match position {
+                Some(position) => position,
+                None => {
+                    if self.partial.len() == MAX_PARTIAL_PAYLOADS {
+                        self.partial.pop_front();
+                    }
+                    self.partial.push_back(PartialPayload {
+                        source,
+                        message_id,
+                        fragments: vec![None; count],
+                    });
+                    self.partial.len() - 1
+                }
+            };
+            let partial = &mut self.partial[position];
+            if let Some(fragment) = partial.fragments.get_mut(index) {
+                *fragment = Some(datagram[10..].to_vec());
+            }
+            if partial.fragments.iter().all(Option::is_some) {
+                let partial = self.partial.remove(position).expect("a partial payload");
+                let payload: Vec<u8> = partial.fragments.into_iter().flatten().flatten().collect();
+                return Ok(self.decode(&payload));
+            }
+        }
+    }
+
+    /// Iterate over the payloads received, ending when receiving fails, such as when the
+    /// read timeout elapses.
+    pub fn records(&mut self) -> impl Iterator<Item = Result<Received, DecodeError>> + '_ {
+        std::iter::from_fn(move || self.recv().ok())
+    }
+
+    fn decode(&self, payload: &[u8]) -> Result<Received, DecodeError> {
+        match self.wire_fmt {
+            WireFmt::ByteBufferBase64 => {
+                let payload = decode_base64(payload)?;
+                let payload = if self.checksum {
+                    verify_checksum(&payload)?
+                } else {
+                    &payload
+                };
+                decode_bytebuffer(payload).map(Received::Record)
+            }
+            WireFmt::ByteBuffer => {
+                let payload = if self.checksum {
+                    verify_checksum(payload)?
+                } else {
+                    payload
+                };
+                decode_bytebuffer(payload).map(Received::Record)
+            }
+            WireFmt::Uncompressed => std::str::from_utf8(payload)
+                .map_err(|_err| DecodeError::Utf8)
+                .and_then(parse_uncompressed)
+                .map(Received::Line),
+            WireFmt::Logfmt => std::str::from_utf8(payload)
+                .map_err(|_err| DecodeError::Utf8)
+                .and_then(parse_logfmt)
+                .map(Received::Fields),
+            #[cfg(feature = "gzip")]
+            WireFmt::GzipUncompressed(_level) => {
+                use std::io::Read;
+                let mut line = String::new();
+                flate2::read::GzDecoder::new(payload)
+                    .read_to_string(&mut line)
+                    .map_err(|err| DecodeError::Syntax(err.to_string()))?;
+                parse_uncompressed(&line).map(Received::Line)
+            }
+            #[allow(unreachable_patterns)]
+            _ => Err(DecodeError::Unsupported),
+        }
+    }
+}
@@ -1,5 +1,5 @@
-#![forbid(unsafe_code, future_incompatible, rust_2018_idioms)]
-#![deny(missing_debug_implementations, nonstandard_style)]
+#![forbid(unsafe_code, future_incompatible)]
+#![deny(missing_debug_implementations, nonstandard_style, rust_2018_idioms)]
 #![warn(missing_docs, missing_doc_code_examples, unreachable_pub)]
 #![allow(dead_code)]
 
@@ -20,8 +20,19 @@
 //! ```
 use log::kv::{Error, Key, Value, Visitor};
 use log::{Log, Metadata, Record, SetLoggerError};
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Write as _;
 use std::io::Write;
-use std::net::UdpSocket;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+#[cfg(unix)]
+use std::os::unix::net::UnixDatagram;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+pub mod decode;
 
 // publicly exporting so $crate::Level works.
 pub use log::Level;
@@ -38,6 +49,69 @@ pub fn max_level() -> LevelFilter {
     log::max_level()
 }
 
+/// Determines if a message logged at the level, with the target, will be logged by the
+/// global logger, as [`log_enabled!`] does for a module.
+///
+/// This allows guarding expensive work, such as building kv pairs, outside of a macro.
+///
+/// # Examples
+///
+/// ```no_run
+/// use udp_logger_rs::{debug, level_enabled, Level};
+///
+/// if level_enabled(Level::Debug, "my::target") {
+///     let kvs: Vec<(String, String)> = vec![("state".into(), "expensive".into())];
+///     debug!(target: "my::target", kvs: &kvs, "state dump");
+/// }
+/// ```
+///
+/// [`log_enabled!`]: macro.log_enabled.html
+pub fn level_enabled(level: Level, target: &str) -> bool {
+    level <= STATIC_MAX_LEVEL
+        && level <= max_level()
+        && log::logger().enabled(&Metadata::builder().level(level).target(target).build())
+}
+
+/// Log some bytes, such as an encoded frame, at the level, with the target, to the global
+/// logger, without formatting them as a message.
+///
+/// A [`ByteBuffer`] payload carries the bytes as its message field, with the layout version
+/// [`BYTE_BUFFER_VERSION_RAW`], or [`BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP`]. Every other wire
+/// format, and a custom formatter, carries the bytes base64 encoded as the message, so that
+/// the payload remains text.
+///
+/// # Examples
+///
+/// ```no_run
+/// use udp_logger_rs::{log_bytes, Level, UdpLogger, WireFmt};
+///
+/// UdpLogger::new().with_wire_fmt(WireFmt::ByteBuffer).init().unwrap();
+/// log_bytes(Level::Info, "frames", &[0x01, 0x02, 0xFF]);
+/// ```
+///
+/// [`ByteBuffer`]: enum.WireFmt.html#variant.ByteBuffer
+/// [`BYTE_BUFFER_VERSION_RAW`]: constant.BYTE_BUFFER_VERSION_RAW.html
+/// [`BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP`]: constant.BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP.html
+pub fn log_bytes(level: Level, target: &str, bytes: &[u8]) {
+    if level <= STATIC_MAX_LEVEL && level <= max_level() {
+        log_raw(log::logger(), level, target, bytes);
+    }
+}
+
+// Log some bytes to a logger, as a record whose message is the bytes base64 encoded, while
+// the thread's raw message is the bytes themselves.
+fn log_raw(logger: &dyn Log, level: Level, target: &str, bytes: &[u8]) {
+    let previous = RAW_MESSAGE.with(|raw| raw.replace(Some(bytes.to_vec())));
+    logger.log(
+        &Record::builder()
+            .args(format_args!("{}", Base64(bytes)))
+            .level(level)
+            .target(target)
+            .build(),
+    );
+    RAW_MESSAGE.with(|raw| raw.set(previous));
+}
+
 /// The standard logging macro.
 ///
 /// # Examples
@@ -73,9 +147,92 @@ pub fn max_level() -> LevelFilter {
 /// info!(target: "MyApp", kvs: &ctx, "hello",);
 /// info!(target: "MyApp", kvs: &ctx, "hello {}", "cats");
 /// info!(target: "MyApp", kvs: &ctx, "hello {}", "cats",);
+///
+/// // to a logger, rather than the global logger, see UdpLogger::build()
+/// let audit = udp_logger_rs::UdpLogger::new().build().unwrap();
+/// info!(logger: &audit, "hello");
+/// info!(logger: &audit, target: "MyApp", kvs: &ctx, "hello {}", "cats");
+///
+/// // with a category, following any target, and preceding any kvs
+/// info!(category: "billing", "charged");
+/// info!(target: "MyApp", category: "billing", kvs: &ctx, "charged {}", "cats");
+/// info!(logger: &audit, category: "auth", "signed in");
+///
+/// // with the time of the event, rather than now, preceding everything else
+/// let charged_at = chrono::Utc::now() - chrono::Duration::hours(1);
+/// info!(timestamp: charged_at, "charged");
+/// info!(timestamp: charged_at, logger: &audit, target: "MyApp", kvs: &ctx, "charged");
 /// ```
+///
+/// A category is a dimension of a record independent of its target, such as the business
+/// domain it concerns. It's sent as a "category" kv pair, preceding any others, so that it's
+/// a field of its own in the structured wire formats.
+///
+/// A timestamp, which is any `chrono::DateTime` which converts to UTC, replaces the time
+/// provided by the logger's [`Clock`], such as when replaying historical events. It's
+/// formatted as UTC, see [`FixedClock`].
+///
+/// [`Clock`]: trait.Clock.html
+/// [`FixedClock`]: struct.FixedClock.html
 #[macro_export(local_inner_macros)]
 macro_rules! log {
+    (timestamp: $timestamp:expr, $($arg:tt)+) => (
+        $crate::__private_api_with_timestamp($timestamp, || log!($($arg)+))
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, kvs: $kvs:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, kvs: &$crate::__private_api_category($category, Some($kvs)), $lvl, $($arg)+)
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, kvs: &$crate::__private_api_category($category, None), $lvl, $($arg)+)
+    );
+    (logger: $logger:expr, category: $category:expr, kvs: $kvs:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(logger: $logger, kvs: &$crate::__private_api_category($category, Some($kvs)), $lvl, $($arg)+)
+    );
+    (logger: $logger:expr, category: $category:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(logger: $logger, kvs: &$crate::__private_api_category($category, None), $lvl, $($arg)+)
+    );
+    (target: $target:expr, category: $category:expr, kvs: $kvs:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(target: $target, kvs: &$crate::__private_api_category($category, Some($kvs)), $lvl, $($arg)+)
+    );
+    (target: $target:expr, category: $category:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(target: $target, kvs: &$crate::__private_api_category($category, None), $lvl, $($arg)+)
+    );
+    (category: $category:expr, kvs: $kvs:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(kvs: &$crate::__private_api_category($category, Some($kvs)), $lvl, $($arg)+)
+    );
+    (category: $category:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(kvs: &$crate::__private_api_category($category, None), $lvl, $($arg)+)
+    );
+    (logger: $logger:expr, target: $target:expr, kvs: $kvs:expr, $lvl:expr, $($arg:tt)+) => ({
+        let lvl = $lvl;
+        if lvl <= $crate::STATIC_MAX_LEVEL {
+            $crate::__private_api_log_to(
+                $logger,
+                __log_format_args!($($arg)+),
+                lvl,
+                &($target, __log_module_path!(), __log_file!(), __log_line!()),
+                Some($kvs),
+            );
+        }
+    });
+    (logger: $logger:expr, target: $target:expr, $lvl:expr, $($arg:tt)+) => ({
+        let lvl = $lvl;
+        if lvl <= $crate::STATIC_MAX_LEVEL {
+            $crate::__private_api_log_to(
+                $logger,
+                __log_format_args!($($arg)+),
+                lvl,
+                &($target, __log_module_path!(), __log_file!(), __log_line!()),
+                None,
+            );
+        }
+    });
+    (logger: $logger:expr, kvs: $kvs:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: __log_module_path!(), kvs: $kvs, $lvl, $($arg)+)
+    );
+    (logger: $logger:expr, $lvl:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: __log_module_path!(), $lvl, $($arg)+)
+    );
     (target: $target:expr, kvs: $kvs:expr, $lvl:expr, $($arg:tt)+) => ({
         let lvl = $lvl;
         if lvl <= $crate::STATIC_MAX_LEVEL && lvl <= $crate::max_level() {
@@ -124,6 +281,45 @@ macro_rules! log_impl {
 /// Logs a message at the trace level.
 #[macro_export(local_inner_macros)]
 macro_rules! trace {
+    (timestamp: $timestamp:expr, $($arg:tt)+) => (
+        $crate::__private_api_with_timestamp($timestamp, || trace!($($arg)+))
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, kvs: $kvs, $crate::Level::Trace, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, $crate::Level::Trace, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, kvs: $kvs, $crate::Level::Trace, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, $crate::Level::Trace, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, kvs: $kvs, $crate::Level::Trace, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, $crate::Level::Trace, $($arg)+);
+    );
+    (category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(category: $category, kvs: $kvs, $crate::Level::Trace, $($arg)+);
+    );
+    (category: $category:expr, $($arg:tt)+) => (
+        log!(category: $category, $crate::Level::Trace, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, kvs: $kvs, $crate::Level::Trace, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, $crate::Level::Trace, $($arg)+);
+    );
+    (logger: $logger:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, kvs: $kvs, $crate::Level::Trace, $($arg)+);
+    );
+    (logger: $logger:expr, $($arg:tt)+) => (
+        log!(logger: $logger, $crate::Level::Trace, $($arg)+);
+    );
     (target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
         log!(target: $target, kvs: $kvs, $crate::Level::Trace, $($arg)+);
     );
@@ -141,6 +337,45 @@ macro_rules! trace {
 /// Logs a message at the debug level.
 #[macro_export(local_inner_macros)]
 macro_rules! debug {
+    (timestamp: $timestamp:expr, $($arg:tt)+) => (
+        $crate::__private_api_with_timestamp($timestamp, || debug!($($arg)+))
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, kvs: $kvs, $crate::Level::Debug, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, $crate::Level::Debug, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, kvs: $kvs, $crate::Level::Debug, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, $crate::Level::Debug, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, kvs: $kvs, $crate::Level::Debug, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, $crate::Level::Debug, $($arg)+);
+    );
+    (category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(category: $category, kvs: $kvs, $crate::Level::Debug, $($arg)+);
+    );
+    (category: $category:expr, $($arg:tt)+) => (
+        log!(category: $category, $crate::Level::Debug, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, kvs: $kvs, $crate::Level::Debug, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, $crate::Level::Debug, $($arg)+);
+    );
+    (logger: $logger:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, kvs: $kvs, $crate::Level::Debug, $($arg)+);
+    );
+    (logger: $logger:expr, $($arg:tt)+) => (
+        log!(logger: $logger, $crate::Level::Debug, $($arg)+);
+    );
     (target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
         log!(target: $target, kvs: $kvs, $crate::Level::Debug, $($arg)+);
     );
@@ -158,6 +393,45 @@ macro_rules! debug {
 /// Logs a message at the info level.
 #[macro_export(local_inner_macros)]
 macro_rules! info {
+    (timestamp: $timestamp:expr, $($arg:tt)+) => (
+        $crate::__private_api_with_timestamp($timestamp, || info!($($arg)+))
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, kvs: $kvs, $crate::Level::Info, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, $crate::Level::Info, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, kvs: $kvs, $crate::Level::Info, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, $crate::Level::Info, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, kvs: $kvs, $crate::Level::Info, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, $crate::Level::Info, $($arg)+);
+    );
+    (category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(category: $category, kvs: $kvs, $crate::Level::Info, $($arg)+);
+    );
+    (category: $category:expr, $($arg:tt)+) => (
+        log!(category: $category, $crate::Level::Info, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, kvs: $kvs, $crate::Level::Info, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, $crate::Level::Info, $($arg)+);
+    );
+    (logger: $logger:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, kvs: $kvs, $crate::Level::Info, $($arg)+);
+    );
+    (logger: $logger:expr, $($arg:tt)+) => (
+        log!(logger: $logger, $crate::Level::Info, $($arg)+);
+    );
     (target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
         log!(target: $target, kvs: $kvs, $crate::Level::Info, $($arg)+);
     );
@@ -175,6 +449,45 @@ macro_rules! info {
 /// Logs a message at the warn level.
 #[macro_export(local_inner_macros)]
 macro_rules! warn {
+    (timestamp: $timestamp:expr, $($arg:tt)+) => (
+        $crate::__private_api_with_timestamp($timestamp, || warn!($($arg)+))
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, kvs: $kvs, $crate::Level::Warn, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, $crate::Level::Warn, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, kvs: $kvs, $crate::Level::Warn, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, $crate::Level::Warn, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, kvs: $kvs, $crate::Level::Warn, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, $crate::Level::Warn, $($arg)+);
+    );
+    (category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(category: $category, kvs: $kvs, $crate::Level::Warn, $($arg)+);
+    );
+    (category: $category:expr, $($arg:tt)+) => (
+        log!(category: $category, $crate::Level::Warn, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, kvs: $kvs, $crate::Level::Warn, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, $crate::Level::Warn, $($arg)+);
+    );
+    (logger: $logger:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, kvs: $kvs, $crate::Level::Warn, $($arg)+);
+    );
+    (logger: $logger:expr, $($arg:tt)+) => (
+        log!(logger: $logger, $crate::Level::Warn, $($arg)+);
+    );
     (target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
         log!(target: $target, kvs: $kvs, $crate::Level::Warn, $($arg)+);
     );
@@ -192,6 +505,45 @@ macro_rules! warn {
 /// Logs a message at the error level.
 #[macro_export(local_inner_macros)]
 macro_rules! error {
+    (timestamp: $timestamp:expr, $($arg:tt)+) => (
+        $crate::__private_api_with_timestamp($timestamp, || error!($($arg)+))
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, kvs: $kvs, $crate::Level::Error, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, category: $category, $crate::Level::Error, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, kvs: $kvs, $crate::Level::Error, $($arg)+);
+    );
+    (logger: $logger:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(logger: $logger, category: $category, $crate::Level::Error, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, kvs: $kvs, $crate::Level::Error, $($arg)+);
+    );
+    (target: $target:expr, category: $category:expr, $($arg:tt)+) => (
+        log!(target: $target, category: $category, $crate::Level::Error, $($arg)+);
+    );
+    (category: $category:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(category: $category, kvs: $kvs, $crate::Level::Error, $($arg)+);
+    );
+    (category: $category:expr, $($arg:tt)+) => (
+        log!(category: $category, $crate::Level::Error, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, kvs: $kvs, $crate::Level::Error, $($arg)+);
+    );
+    (logger: $logger:expr, target: $target:expr, $($arg:tt)+) => (
+        log!(logger: $logger, target: $target, $crate::Level::Error, $($arg)+);
+    );
+    (logger: $logger:expr, kvs: $kvs:expr, $($arg:tt)+) => (
+        log!(logger: $logger, kvs: $kvs, $crate::Level::Error, $($arg)+);
+    );
+    (logger: $logger:expr, $($arg:tt)+) => (
+        log!(logger: $logger, $crate::Level::Error, $($arg)+);
+    );
     (target: $target:expr, kvs: $kvs:expr, $($arg:tt)+) => (
         log!(target: $target, kvs: $kvs, $crate::Level::Error, $($arg)+);
     );
@@ -211,10 +563,7 @@ macro_rules! error {
 #[macro_export(local_inner_macros)]
 macro_rules! log_enabled {
     (target: $target:expr, $lvl:expr) => {{
-        let lvl = $lvl;
-        lvl <= $crate::STATIC_MAX_LEVEL
-            && lvl <= $crate::max_level()
-            && $crate::__private_api_enabled(lvl, $target)
+        $crate::level_enabled($lvl, $target)
     }};
     ($lvl:expr) => {
         log_enabled!(target: __log_module_path!(), $lvl)
@@ -261,7 +610,25 @@ pub fn __private_api_log(
     &(target, module_path, file, line): &(&str, &'static str, &'static str, u32),
     kvs: Option<&dyn log::kv::Source>,
 ) {
-    log::logger().log(
+    __private_api_log_to(
+        log::logger(),
+        args,
+        level,
+        &(target, module_path, file, line),
+        kvs,
+    );
+}
+
+// WARNING: this is not part of the crate's public API and is subject to change at any time
+#[doc(hidden)]
+pub fn __private_api_log_to(
+    logger: &dyn Log,
+    args: std::fmt::Arguments<'_>,
+    level: log::Level,
+    &(target, module_path, file, line): &(&str, &'static str, &'static str, u32),
+    kvs: Option<&dyn log::kv::Source>,
+) {
+    logger.log(
         &log::Record::builder()
             .args(args)
             .level(level)
@@ -274,6 +641,52 @@ pub fn __private_api_log(
     );
 }
 
+// WARNING: this is not part of the crate's public API and is subject to change at any time
+#[doc(hidden)]
+pub fn __private_api_with_timestamp<T: Into<chrono::DateTime<chrono::Utc>>>(
+    timestamp: T,
+    log: impl FnOnce(),
+) {
+    let previous = TIMESTAMP.with(|current| current.replace(Some(timestamp.into())));
+    log();
+    TIMESTAMP.with(|current| current.set(previous));
+}
+
+// WARNING: this is not part of the crate's public API and is subject to change at any time
+#[doc(hidden)]
+pub fn __private_api_category<'a>(
+    category: &'a str,
+    kvs: Option<&'a dyn log::kv::Source>,
+) -> Categorized<'a> {
+    Categorized { category, kvs }
+}
+
+// WARNING: this is not part of the crate's public API and is subject to change at any time
+// The kv pairs of a record logged with a category, which precedes them as a "category" pair.
+#[doc(hidden)]
+pub struct Categorized<'a> {
+    category: &'a str,
+    kvs: Option<&'a dyn log::kv::Source>,
+}
+
+impl std::fmt::Debug for Categorized<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Categorized")
+            .field("category", &self.category)
+            .finish()
+    }
+}
+
+impl log::kv::Source for Categorized<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn Visitor<'kvs>) -> Result<(), Error> {
+        visitor.visit_pair(Key::from_str("category"), Value::from(self.category))?;
+        match self.kvs {
+            Some(kvs) => kvs.visit(visitor),
+            None => Ok(()),
+        }
+    }
+}
+
 // enough with the macros, on with the UDP logging
 
 /// Wire formats. Default is Uncompressed.
@@ -290,381 +703,6230 @@ pub fn __private_api_log(
 ///     record.args()
 /// );
 /// ```
+/// where the timestamp format can be changed with [`with_timestamp_format`], the case and
+/// padding of the level can be changed with [`with_level_style`], the hostname,
+/// when provided by [`with_hostname`], follows the level, as does the app name and process
+/// id, formatted as `app[pid]`, when provided by [`with_app_name`] and [`with_pid`], and
+/// the thread, formatted as `(thread)`, when enabled by [`with_thread_names`], the
+/// source location, formatted as `(file:line)`, follows the message when enabled by
+/// [`with_source_location`], the record number, formatted as `#seq `, precedes the
+/// timestamp when enabled by [`with_sequence_numbers`],
 /// and has kv pairs, appended, as:
 /// ```no_run
 /// # let k = "key1";
 /// # let v = "value1";
 /// format!(" {}={}", k, v);
 /// ```
-/// * ByteBuffer, the entire payload is a u8 level, i64 Utc::now().timestamp_millis(), and
-/// u32 string length followed by length * utf8.
-#[derive(Debug)]
+/// where values which are empty, or contain whitespace, '=' or '"', are quoted, with '"'
+/// and '\\' escaped, and whitespace, '=' and '"' in keys are replaced with '_'. Numbers and
+/// bools are unquoted, and strings which would read as a number, bool or null are quoted,
+/// so that the type of a value is preserved. The separators can be changed with
+/// [`with_kv_style`]. The payload ends with the line terminator set by
+/// [`with_line_terminator`], if any.
+/// * ByteBuffer, the entire payload is the 2 byte [`BYTE_BUFFER_MAGIC`], the u8
+///   [`BYTE_BUFFER_VERSION`], a u8 level (see [`with_level_codes`]),
+///   i64 Utc::now().timestamp_millis(), or micros or nanos with
+///   [`with_timestamp_precision`], or nothing with [`without_timestamp`], the target and the
+///   message, each a u32 string length followed by length * utf8, and a u16 count of kv
+///   pairs. Each kv pair is a u32 key length followed by length * utf8, a u8
+///   value tag and the value. The value is an i64 (tag 1), f64 (tag 2), u8 bool (tag 3), or
+///   u32 string length followed by length * utf8 (tag 4). The hostname, app name and process
+///   id, when provided by [`with_hostname`], [`with_app_name`] and [`with_pid`], are the first
+///   kv pairs, with the keys "host", "app" and "pid", followed by "thread" when enabled by
+///   [`with_thread_names`], and "file" and "line" when enabled by [`with_source_location`].
+///   A "seq" kv pair, when enabled by [`with_sequence_numbers`], precedes them.
+///   With [`with_checksum`], a u32 CRC32 of
+///   the payload follows. With [`with_hmac_key`], an HMAC-SHA256 tag of the payload,
+///   including any checksum, follows last.
+/// * ByteBufferBase64, the ByteBuffer payload, including any checksum and HMAC tag, base64
+///   encoded as a single line of text, ending with '\n', for channels which only carry text.
+/// * GzipUncompressed, with the `gzip` feature, the Uncompressed payload, gzip compressed at
+///   the provided level (0-9).
+/// * Syslog5424, with the `syslog` feature, an RFC 5424 syslog message, formatted as:
+///   `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID - STRUCTURED-DATA [target] message`, where
+///   PRI combines the [`Facility`] and the severity of the level, and the kv pairs are the
+///   parameters of a `kv@32473` STRUCTURED-DATA element. The hostname and app name are
+///   provided by [`with_hostname`] and [`with_app_name`], and the thread, when enabled by
+///   [`with_thread_names`], and the source location, when enabled by
+///   [`with_source_location`], are `thread`, `file` and `line` parameters of the
+///   STRUCTURED-DATA element.
+/// * Logfmt, a line of logfmt, formatted as:
+///   `ts=TIMESTAMP level=info target=App msg="the message" key1=value1`, where the timestamp
+///   format can be changed with [`with_timestamp_format`], and the hostname, app name and
+///   process id, when provided by [`with_hostname`], [`with_app_name`] and [`with_pid`], are
+///   `host`, `app` and `pid` fields following the level, and a `seq` field, when enabled by
+///   [`with_sequence_numbers`], followed by a `thread` field when
+///   enabled by [`with_thread_names`], and `file` and `line` fields when enabled by
+///   [`with_source_location`]. Values which are empty, or contain
+///   whitespace, '=' or '"', are quoted, with '"' and '\\' escaped. As with Uncompressed,
+///   strings which would read as a number, bool or null are quoted.
+/// * Protobuf, with the `protobuf` feature, a `LogRecord` message, as described by the
+///   `proto/udp_logger.proto` schema, with the timestamp in milliseconds since the Unix
+///   epoch, the level, target, message, and typed kv pairs, which include the same built-in
+///   fields as ByteBuffer. With [`with_hmac_key`], an HMAC-SHA256 tag of the message follows
+///   it.
+///
+/// Payloads larger than a UDP datagram, or the size set by [`with_max_datagram_size`], are
+/// handled according to the [`OversizePolicy`]. ByteBuffer, Uncompressed and Logfmt payloads
+/// can be decoded by collectors with the [`decode`] module, which base64 decodes
+/// ByteBufferBase64 payloads.
+///
+/// Wire formats are displayed, and parsed case insensitively, by name: `uncompressed`,
+/// `bytebuffer`, `bytebufferbase64`, `gzip:LEVEL`, where the level defaults to 6 when left out, `syslog5424`,
+/// `logfmt` and `protobuf`.
+///
+/// ```no_run
+/// use udp_logger_rs::{UdpLogger, WireFmt};
+///
+/// let wire_fmt: WireFmt = std::env::var("LOG_WIRE_FMT")
+///     .unwrap_or_else(|_| "uncompressed".to_string())
+///     .parse()
+///     .unwrap();
+/// UdpLogger::new().with_wire_fmt(wire_fmt).init().unwrap();
+/// ```
+///
+/// [`decode`]: decode/index.html
+/// [`with_timestamp_format`]: struct.UdpLogger.html#method.with_timestamp_format
+/// [`with_kv_style`]: struct.UdpLogger.html#method.with_kv_style
+/// [`Facility`]: enum.Facility.html
+/// [`with_hostname`]: struct.UdpLogger.html#method.with_hostname
+/// [`with_app_name`]: struct.UdpLogger.html#method.with_app_name
+/// [`with_pid`]: struct.UdpLogger.html#method.with_pid
+/// [`with_thread_names`]: struct.UdpLogger.html#method.with_thread_names
+/// [`with_source_location`]: struct.UdpLogger.html#method.with_source_location
+/// [`OversizePolicy`]: enum.OversizePolicy.html
+/// [`with_checksum`]: struct.UdpLogger.html#method.with_checksum
+/// [`with_level_codes`]: struct.UdpLogger.html#method.with_level_codes
+/// [`with_level_style`]: struct.UdpLogger.html#method.with_level_style
+/// [`with_line_terminator`]: struct.UdpLogger.html#method.with_line_terminator
+/// [`with_max_datagram_size`]: struct.UdpLogger.html#method.with_max_datagram_size
+/// [`with_hmac_key`]: struct.UdpLogger.html#method.with_hmac_key
+/// [`with_sequence_numbers`]: struct.UdpLogger.html#method.with_sequence_numbers
+/// [`with_timestamp_precision`]: struct.UdpLogger.html#method.with_timestamp_precision
+/// [`without_timestamp`]: struct.UdpLogger.html#method.without_timestamp
+/// [`BYTE_BUFFER_MAGIC`]: constant.BYTE_BUFFER_MAGIC.html
+/// [`BYTE_BUFFER_VERSION`]: constant.BYTE_BUFFER_VERSION.html
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Deserialize))]
 pub enum WireFmt {
     /// No Compression, the payload can be consistered a string of utf8 bytes.
+    #[default]
     Uncompressed,
-    /// 1 byte Level, 8 bytes timestamp, 4 bytes len followed by len * utf8 (string)
+    /// 2 bytes magic, 1 byte version, 1 byte Level, 8 bytes timestamp, 4 bytes len followed by len * utf8 (target),
+    /// 4 bytes len followed by len * utf8 (message), 2 bytes kv count followed by count * typed kv pairs.
+    /// The message of a record logged by log_bytes() is its bytes, rather than utf8.
     ByteBuffer,
+    /// The ByteBuffer payload, base64 encoded as a line of text.
+    ByteBufferBase64,
+    /// The Uncompressed payload, gzip compressed with a compression level of 0 (none) to 9 (best).
+    #[cfg(feature = "gzip")]
+    GzipUncompressed(u32),
+    /// An RFC 5424 syslog message, the payload is a string of utf8 bytes.
+    #[cfg(feature = "syslog")]
+    Syslog5424,
+    /// A line of logfmt, the payload is a string of utf8 bytes.
+    Logfmt,
+    /// A protobuf encoded LogRecord, see proto/udp_logger.proto.
+    #[cfg(feature = "protobuf")]
+    Protobuf,
 }
 
-/// The UdpLogger is a control structure for logging via UDP packets.
-#[derive(Debug)]
-pub struct UdpLogger {
-    default_level: LevelFilter,
-    module_levels: Vec<(String, LevelFilter)>,
-    default_source: UdpSocket,
-    sources: Vec<(LevelFilter, UdpSocket)>,
-    default_destination: String,
-    destinations: Vec<(LevelFilter, String)>,
-    wire_fmt: WireFmt,
+impl WireFmt {
+    // Text payloads can be truncated, and remain readable.
+    fn is_text(&self) -> bool {
+        match self {
+            WireFmt::Uncompressed | WireFmt::Logfmt => true,
+            #[cfg(feature = "syslog")]
+            WireFmt::Syslog5424 => true,
+            _ => false,
+        }
+    }
 }
 
-impl UdpLogger {
-    /// Initializes the global logger with a UdpLogger instance with
-    /// default log level set to `Level::Trace`.
-    ///
-    /// # Examples
-    /// ```no_run
-    /// use udp_logger_rs::{UdpLogger, warn};
-    ///
-    /// UdpLogger::new().env().init().unwrap();
-    /// warn!("This is an example message.");
-    /// ```
-    ///
-    /// [`init`]: #method.init
-    #[must_use = "You must call init() to begin logging"]
-    pub fn new() -> Self {
-        let socket = UdpSocket::bind("127.0.0.1:4000").expect("unable to bind to socket");
-        socket
-            .set_nonblocking(true)
-            .expect("unable to set socket non-blocking");
+// The compression level of a gzip wire format parsed without one.
+#[cfg(feature = "gzip")]
+const DEFAULT_GZIP_LEVEL: u32 = 6;
 
-        Self {
-            default_level: LevelFilter::Trace,
-            module_levels: Vec::new(),
-            default_source: socket,
-            sources: Vec::new(),
-            default_destination: "127.0.0.1:4010".to_string(),
-            destinations: Vec::new(),
-            wire_fmt: WireFmt::Uncompressed,
+impl std::fmt::Display for WireFmt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WireFmt::Uncompressed => f.write_str("uncompressed"),
+            WireFmt::ByteBuffer => f.write_str("bytebuffer"),
+            WireFmt::ByteBufferBase64 => f.write_str("bytebufferbase64"),
+            #[cfg(feature = "gzip")]
+            WireFmt::GzipUncompressed(level) => write!(f, "gzip:{}", level),
+            #[cfg(feature = "syslog")]
+            WireFmt::Syslog5424 => f.write_str("syslog5424"),
+            WireFmt::Logfmt => f.write_str("logfmt"),
+            #[cfg(feature = "protobuf")]
+            WireFmt::Protobuf => f.write_str("protobuf"),
         }
     }
+}
 
-    /// Simulates env_logger behavior, which enables the user to choose log
-    /// level by setting a `RUST_LOG` environment variable. This will use
-    /// the default level set by [`with_level`] if `RUST_LOG` is not set or
-    /// can't be parsed as a standard log level.
-    ///
-    /// [`with_level`]: #method.with_level
-    #[must_use = "You must call init() to begin logging"]
-    pub fn env(mut self) -> Self {
-        if let Ok(level) = std::env::var("RUST_LOG") {
-            match level.to_lowercase().as_str() {
-                "trace" => self.default_level = log::LevelFilter::Trace,
-                "debug" => self.default_level = log::LevelFilter::Debug,
-                "info" => self.default_level = log::LevelFilter::Info,
-                "warn" => self.default_level = log::LevelFilter::Warn,
-                "error" => self.default_level = log::LevelFilter::Error,
-                _ => (),
-            }
+impl std::str::FromStr for WireFmt {
+    type Err = ParseWireFmtError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, level) = match s.split_once(':') {
+            Some((name, level)) => (name, Some(level)),
+            None => (s, None),
         };
-        self
+        let unknown = || ParseWireFmtError(s.to_string());
+        match name.to_ascii_lowercase().as_str() {
+            #[cfg(feature = "gzip")]
+            "gzip" => {
+                let level = match level {
+                    Some(level) => level.parse().map_err(|_err| unknown())?,
+                    None => DEFAULT_GZIP_LEVEL,
+                };
+                if level > 9 {
+                    return Err(unknown());
+                }
+                Ok(WireFmt::GzipUncompressed(level))
+            }
+            _ if level.is_some() => Err(unknown()),
+            "uncompressed" => Ok(WireFmt::Uncompressed),
+            "bytebuffer" => Ok(WireFmt::ByteBuffer),
+            "bytebufferbase64" => Ok(WireFmt::ByteBufferBase64),
+            #[cfg(feature = "syslog")]
+            "syslog5424" => Ok(WireFmt::Syslog5424),
+            "logfmt" => Ok(WireFmt::Logfmt),
+            #[cfg(feature = "protobuf")]
+            "protobuf" => Ok(WireFmt::Protobuf),
+            _ => Err(unknown()),
+        }
     }
+}
 
-    /// Set the 'default' log level.
-    ///
-    /// You can override the default level for specific modules and their sub-modules using [`with_module_level`]
-    ///
-    /// [`with_module_level`]: #method.with_module_level
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_level(mut self, level: LevelFilter) -> Self {
-        self.default_level = level;
-        self
+/// The error returned when a [`WireFmt`] can't be parsed from a string, holding the string.
+///
+/// [`WireFmt`]: enum.WireFmt.html
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseWireFmtError(String);
+
+impl std::fmt::Display for ParseWireFmtError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "unknown wire format {:?}, expected one of ", self.0)?;
+        f.write_str("uncompressed, bytebuffer, bytebufferbase64")?;
+        #[cfg(feature = "gzip")]
+        f.write_str(", gzip:0-9")?;
+        #[cfg(feature = "syslog")]
+        f.write_str(", syslog5424")?;
+        f.write_str(", logfmt")?;
+        #[cfg(feature = "protobuf")]
+        f.write_str(", protobuf")?;
+        Ok(())
     }
+}
 
-    /// Override the log level for some specific modules.
-    ///
-    /// This sets the log level of a specific module and all its sub-modules.
-    /// When both the level for a parent module as well as a child module are set,
-    /// the more specific value is taken. If the log level for the same module is
-    /// specified twice, the resulting log level is implementation defined.
-    ///
-    /// # Examples
-    ///
-    /// Silence an overly verbose crate:
-    ///
-    /// ```no_run
-    /// use udp_logger_rs::UdpLogger;
-    /// use log::LevelFilter;
-    ///
-    /// UdpLogger::new().with_module_level("chatty_dependency", LevelFilter::Warn).init().unwrap();
-    /// ```
-    ///
-    /// Disable logging for all dependencies:
-    ///
-    /// ```no_run
-    /// use udp_logger_rs::UdpLogger;
-    /// use log::LevelFilter;
-    ///
-    /// UdpLogger::new()
-    ///     .with_level(LevelFilter::Off)
-    ///     .with_module_level("my_crate", LevelFilter::Info)
-    ///     .init()
-    ///     .unwrap();
-    /// ```
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_module_level(mut self, target: &str, level: LevelFilter) -> Self {
-        self.module_levels.push((target.to_string(), level));
+impl std::error::Error for ParseWireFmtError {}
 
-        /* Normally this is only called in `init` to avoid redundancy, but we can't initialize the logger in tests */
-        #[cfg(test)]
-        self.module_levels
-            .sort_by_key(|(name, _level)| name.len().wrapping_neg());
+/// Syslog facilities, used by the Syslog5424 wire format. Default is User.
+#[cfg(feature = "syslog")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Facility {
+    /// Kernel messages
+    Kern = 0,
+    /// User-level messages
+    User = 1,
+    /// Mail system
+    Mail = 2,
+    /// System daemons
+    Daemon = 3,
+    /// Security/authorization messages
+    Auth = 4,
+    /// Messages generated internally by syslogd
+    Syslog = 5,
+    /// Line printer subsystem
+    Lpr = 6,
+    /// Network news subsystem
+    News = 7,
+    /// UUCP subsystem
+    Uucp = 8,
+    /// Clock daemon
+    Cron = 9,
+    /// Security/authorization messages
+    Authpriv = 10,
+    /// FTP daemon
+    Ftp = 11,
+    /// Local use 0
+    Local0 = 16,
+    /// Local use 1
+    Local1 = 17,
+    /// Local use 2
+    Local2 = 18,
+    /// Local use 3
+    Local3 = 19,
+    /// Local use 4
+    Local4 = 20,
+    /// Local use 5
+    Local5 = 21,
+    /// Local use 6
+    Local6 = 22,
+    /// Local use 7
+    Local7 = 23,
+}
+
+thread_local! {
+    // The context fields of the current thread, see UdpLogger::push_context().
+    static CONTEXT: RefCell<Vec<(String, String)>> = const { RefCell::new(Vec::new()) };
+    // The buffers records are formatted in, reused by each record logged by the thread.
+    static BUFFERS: RefCell<Buffers> = RefCell::new(Buffers::default());
+    // The timestamp of the record being logged by the thread, see the timestamp: macro form.
+    static TIMESTAMP: Cell<Option<chrono::DateTime<chrono::Utc>>> = const { Cell::new(None) };
+    // The bytes of the record being logged by the thread, see log_bytes().
+    static RAW_MESSAGE: Cell<Option<Vec<u8>>> = const { Cell::new(None) };
+}
+
+// The capacity the buffers of a thread keep, once a larger record has been formatted.
+const BUFFER_RETAINED_CAPACITY: usize = 64 * 1024;
+
+// The text of a record, as it's formatted, and the payload that's sent.
+#[derive(Default)]
+struct Buffers {
+    text: String,
+    payload: Vec<u8>,
+}
+
+impl Buffers {
+    // Release the memory of an unusually large record.
+    fn shrink(&mut self) {
+        self.text.shrink_to(BUFFER_RETAINED_CAPACITY);
+        self.payload.shrink_to(BUFFER_RETAINED_CAPACITY);
+    }
+}
+
+/// Pops a context field when dropped, see [`UdpLogger::scoped_context`].
+///
+/// [`UdpLogger::scoped_context`]: struct.UdpLogger.html#method.scoped_context
+#[derive(Debug)]
+#[must_use = "The context field is popped when the guard is dropped"]
+pub struct ContextGuard(());
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        UdpLogger::pop_context();
+    }
+}
+
+/// The separators of kv pairs appended to Uncompressed payloads. Default is a `pair_sep`
+/// of " " and a `kv_sep` of "=", appending pairs as " key=value".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KvStyle {
+    /// Precedes each pair.
+    pub pair_sep: &'static str,
+    /// Separates the key from the value.
+    pub kv_sep: &'static str,
+}
+
+impl Default for KvStyle {
+    fn default() -> Self {
+        KvStyle {
+            pair_sep: " ",
+            kv_sep: "=",
+        }
+    }
+}
+
+/// The formatting of the level in Uncompressed payloads. Default is uppercase, padded to a
+/// width of 5, such as "INFO ".
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LevelStyle {
+    /// Format the level in lowercase, rather than uppercase.
+    pub lowercase: bool,
+    /// The width the level is padded to, with trailing spaces.
+    pub width: usize,
+}
+
+impl Default for LevelStyle {
+    fn default() -> Self {
+        LevelStyle {
+            lowercase: false,
+            width: 5,
+        }
+    }
+}
+
+/// The handling of kv pairs with redacted keys, see [`with_redacted_keys`]. Default is to
+/// replace values, matching keys exactly.
+///
+/// [`with_redacted_keys`]: struct.UdpLogger.html#method.with_redacted_keys
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Redaction {
+    /// Drop the kv pairs, rather than replacing their values with "[REDACTED]".
+    pub drop: bool,
+    /// Match keys ignoring ASCII case.
+    pub ignore_case: bool,
+}
+
+/// The line terminator which ends Uncompressed payloads. Default is None.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    /// Payloads aren't terminated.
+    None,
+    /// Payloads end with "\n".
+    Lf,
+    /// Payloads end with "\r\n".
+    Crlf,
+}
+
+impl Terminator {
+    fn as_str(self) -> &'static str {
+        match self {
+            Terminator::None => "",
+            Terminator::Lf => "\n",
+            Terminator::Crlf => "\r\n",
+        }
+    }
+}
+
+/// The precision of timestamps. Default is Millis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampPrecision {
+    /// Milliseconds, formatted with "%.3f".
+    Millis,
+    /// Microseconds, formatted with "%.6f".
+    Micros,
+    /// Nanoseconds, formatted with "%.9f".
+    Nanos,
+}
+
+impl TimestampPrecision {
+    fn fraction(self) -> &'static str {
+        match self {
+            TimestampPrecision::Millis => "%.3f",
+            TimestampPrecision::Micros => "%.6f",
+            TimestampPrecision::Nanos => "%.9f",
+        }
+    }
+
+    // The time since the Unix epoch, in units of the precision.
+    fn now(self, clock: &dyn Clock) -> i64 {
+        match self {
+            TimestampPrecision::Millis => clock.now_millis(),
+            TimestampPrecision::Micros => clock.now_nanos() / 1_000,
+            TimestampPrecision::Nanos => clock.now_nanos(),
+        }
+    }
+}
+
+// The SD-ID of the STRUCTURED-DATA element holding kv pairs, 32473 is the private
+// enterprise number reserved for documentation.
+#[cfg(feature = "syslog")]
+const SYSLOG_SD_ID: &str = "kv@32473";
+
+/// The handling of payloads which are too large for a UDP datagram. Default is Drop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OversizePolicy {
+    /// The payload isn't sent, and is reported as an error.
+    Drop,
+    /// Text payloads, Uncompressed, Syslog5424 and Logfmt, are truncated to fit, ending with
+    /// "...[truncated]". Other payloads can't be decoded once truncated, so they are dropped.
+    Truncate,
+    /// The payload is split into fragments, each sent as a datagram no larger than the
+    /// fragment size, see [`with_fragment_size`]. Each fragment is the
+    /// 2 byte [`FRAGMENT_MAGIC`], a u32 message id, shared by the fragments of a payload,
+    /// a u16 fragment index, a u16 fragment count, and the fragment of the payload. Receivers
+    /// reassemble the payload by concatenating the fragments in index order.
+    ///
+    /// [`with_fragment_size`]: struct.UdpLogger.html#method.with_fragment_size
+    /// [`FRAGMENT_MAGIC`]: constant.FRAGMENT_MAGIC.html
+    Fragment,
+}
+
+/// The magic bytes which begin every fragment of a payload, "UF".
+pub const FRAGMENT_MAGIC: [u8; 2] = [0x55, 0x46];
+
+// The length of the header preceding each fragment of a payload.
+const FRAGMENT_HEADER_SIZE: usize = 10;
+
+// The ANSI colors of Error, Warn, Info, Debug, and Trace, see with_colors().
+const DEFAULT_LEVEL_COLORS: [&str; 5] = ["31", "33", "32", "36", "90"];
+
+// Error, Warn, Info, Debug, and Trace, as the default LevelStyle formats them.
+const LEVEL_NAMES: [&str; 5] = ["ERROR", "WARN ", "INFO ", "DEBUG", "TRACE"];
+
+// The marker which ends a truncated Uncompressed payload.
+const TRUNCATED_MARKER: &str = "...[truncated]";
+
+/// The magic bytes which begin every ByteBuffer payload, "UL".
+pub const BYTE_BUFFER_MAGIC: [u8; 2] = [0x55, 0x4C];
+
+/// The version of the ByteBuffer payload layout, which follows the magic bytes. The version
+/// changes whenever the layout changes.
+///
+/// Versions 1 and 2 preceded the target having its own field, and carried it in the message
+/// as `[target] message`.
+pub const BYTE_BUFFER_VERSION: u8 = 3;
+
+/// The version of the ByteBuffer payload layout without a timestamp, see
+/// [`without_timestamp`].
+///
+/// [`without_timestamp`]: struct.UdpLogger.html#method.without_timestamp
+pub const BYTE_BUFFER_VERSION_NO_TIMESTAMP: u8 = 4;
+
+/// The version of the ByteBuffer payload layout whose message field is the bytes logged by
+/// [`log_bytes`], rather than utf8.
+///
+/// [`log_bytes`]: fn.log_bytes.html
+pub const BYTE_BUFFER_VERSION_RAW: u8 = 5;
+
+/// The version of the ByteBuffer payload layout whose message field is the bytes logged by
+/// [`log_bytes`], without a timestamp.
+///
+/// [`log_bytes`]: fn.log_bytes.html
+pub const BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP: u8 = 6;
+
+// The largest payload which fits in a single UDP datagram over IPv4.
+const MAX_DATAGRAM_SIZE: usize = 65_507;
+
+// The smallest datagram size which can be configured, leaving room for the fragment header,
+// or the truncation marker, and some of the payload.
+const MIN_DATAGRAM_SIZE: usize = 64;
+
+// The timestamp format used by the Uncompressed wire format, unless overridden.
+const DEFAULT_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.3f";
+
+// The longest flush() will wait for pending payloads to be sent.
+const FLUSH_TIMEOUT: Duration = Duration::from_secs(1);
+
+// The most payloads held until they can be sent, after which the oldest is dropped.
+const MAX_PENDING_PAYLOADS: usize = 1024;
+
+/// A source of timestamps for log payloads.
+///
+/// The UdpLogger uses [`UtcClock`] unless another clock is provided with [`with_clock`],
+/// which allows for local, simulated, or fixed time, the latter being useful in testing.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use udp_logger_rs::{Clock, UdpLogger};
+///
+/// #[derive(Debug)]
+/// struct Epoch;
+/// impl Clock for Epoch {
+///     fn now_millis(&self) -> i64 {
+///         0
+///     }
+///     fn format_now(&self, _fmt: &str) -> String {
+///         "1970-01-01 00:00:00.000".to_string()
+///     }
+/// }
+///
+/// UdpLogger::new().with_clock(Arc::new(Epoch)).init().unwrap();
+/// ```
+///
+/// [`UtcClock`]: struct.UtcClock.html
+/// [`with_clock`]: struct.UdpLogger.html#method.with_clock
+pub trait Clock: std::fmt::Debug {
+    /// Milliseconds since the Unix epoch.
+    fn now_millis(&self) -> i64;
+    /// Nanoseconds since the Unix epoch, which otherwise are derived from the milliseconds.
+    fn now_nanos(&self) -> i64 {
+        self.now_millis().saturating_mul(1_000_000)
+    }
+    /// The current time, formatted with a chrono strftime format string.
+    fn format_now(&self, fmt: &str) -> String;
+    /// Append the current time, formatted as by format_now(), to a string. Override this
+    /// to format without allocating a string for each record.
+    fn write_now(&self, fmt: &str, out: &mut String) {
+        out.push_str(&self.format_now(fmt));
+    }
+}
+
+/// A Clock, providing the current UTC time. This is the default Clock.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct UtcClock;
+
+impl Clock for UtcClock {
+    fn now_millis(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis()
+    }
+    fn now_nanos(&self) -> i64 {
+        chrono::Utc::now().timestamp_nanos_opt().unwrap_or(i64::MAX)
+    }
+    fn format_now(&self, fmt: &str) -> String {
+        chrono::Utc::now().format(fmt).to_string()
+    }
+    fn write_now(&self, fmt: &str, out: &mut String) {
+        let _ = write!(out, "{}", chrono::Utc::now().format(fmt));
+    }
+}
+
+/// A Clock, providing the current local time.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct LocalClock;
+
+impl Clock for LocalClock {
+    fn now_millis(&self) -> i64 {
+        chrono::Local::now().timestamp_millis()
+    }
+    fn now_nanos(&self) -> i64 {
+        chrono::Local::now()
+            .timestamp_nanos_opt()
+            .unwrap_or(i64::MAX)
+    }
+    fn format_now(&self, fmt: &str) -> String {
+        chrono::Local::now().format(fmt).to_string()
+    }
+    fn write_now(&self, fmt: &str, out: &mut String) {
+        let _ = write!(out, "{}", chrono::Local::now().format(fmt));
+    }
+}
+
+/// A Clock, providing the time elapsed since it was created, which is unaffected by
+/// changes to the system clock.
+///
+/// The elapsed time is formatted as a time since the Unix epoch, so that "%s%.3f" formats
+/// it as seconds, see [`with_relative_time`].
+///
+/// [`with_relative_time`]: struct.UdpLogger.html#method.with_relative_time
+#[derive(Debug, Clone, Copy)]
+pub struct RelativeClock(Instant);
+
+impl RelativeClock {
+    /// Create a clock, starting now.
+    pub fn new() -> Self {
+        RelativeClock(Instant::now())
+    }
+
+    // The elapsed time, as a time since the Unix epoch.
+    fn elapsed_time(&self) -> chrono::DateTime<chrono::Utc> {
+        let elapsed = self.0.elapsed();
+        chrono::DateTime::<chrono::Utc>::from_timestamp(
+            elapsed.as_secs() as i64,
+            elapsed.subsec_nanos(),
+        )
+        .unwrap_or_default()
+    }
+}
+
+impl Default for RelativeClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A Clock, providing a fixed time, which is formatted as UTC.
+///
+/// This is the clock of a record logged with an explicit timestamp, see [`log!`], and is
+/// useful in testing.
+///
+/// # Examples
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use udp_logger_rs::{FixedClock, UdpLogger};
+///
+/// UdpLogger::new()
+///     .with_clock(Arc::new(FixedClock(chrono::DateTime::UNIX_EPOCH)))
+///     .init()
+///     .unwrap();
+/// ```
+///
+/// [`log!`]: macro.log.html
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub chrono::DateTime<chrono::Utc>);
+
+impl Clock for FixedClock {
+    fn now_millis(&self) -> i64 {
+        self.0.timestamp_millis()
+    }
+    fn now_nanos(&self) -> i64 {
+        self.0.timestamp_nanos_opt().unwrap_or(i64::MAX)
+    }
+    fn format_now(&self, fmt: &str) -> String {
+        self.0.format(fmt).to_string()
+    }
+    fn write_now(&self, fmt: &str, out: &mut String) {
+        let _ = write!(out, "{}", self.0.format(fmt));
+    }
+}
+
+// The clock timestamping a record, which is the logger's clock unless the record was logged
+// with an explicit timestamp.
+enum RecordClock<'a> {
+    Logger(&'a dyn Clock),
+    Fixed(FixedClock),
+}
+
+impl<'a> std::ops::Deref for RecordClock<'a> {
+    type Target = dyn Clock + 'a;
+
+    fn deref(&self) -> &Self::Target {
+        match self {
+            RecordClock::Logger(clock) => *clock,
+            RecordClock::Fixed(clock) => clock,
+        }
+    }
+}
+
+impl Clock for RelativeClock {
+    fn now_millis(&self) -> i64 {
+        std::convert::TryFrom::try_from(self.0.elapsed().as_millis()).unwrap_or(i64::MAX)
+    }
+    fn now_nanos(&self) -> i64 {
+        std::convert::TryFrom::try_from(self.0.elapsed().as_nanos()).unwrap_or(i64::MAX)
+    }
+    fn format_now(&self, fmt: &str) -> String {
+        self.elapsed_time().format(fmt).to_string()
+    }
+    fn write_now(&self, fmt: &str, out: &mut String) {
+        let _ = write!(out, "{}", self.elapsed_time().format(fmt));
+    }
+}
+
+/// The configuration of a UdpLogger, such as read from a configuration file, see
+/// [`UdpLogger::from_config`]. Fields which are left out take their defaults, as for
+/// [`UdpLogger::new`].
+///
+/// # Examples
+///
+/// ```no_run
+/// use udp_logger_rs::{UdpLogger, UdpLoggerConfig};
+///
+/// let config: UdpLoggerConfig = serde_json::from_str(
+///     r#"{
+///         "level": "info",
+///         "module_levels": [["my_app::net", "debug"]],
+///         "destinations": [["error", "10.0.0.5:4010"]],
+///         "wire_fmt": "ByteBuffer"
+///     }"#,
+/// )
+/// .unwrap();
+/// UdpLogger::from_config(config).init().unwrap();
+/// ```
+///
+/// [`UdpLogger::from_config`]: struct.UdpLogger.html#method.from_config
+/// [`UdpLogger::new`]: struct.UdpLogger.html#method.new
+#[cfg(feature = "serde")]
+#[derive(Debug, Clone, PartialEq, serde::Deserialize)]
+#[serde(default)]
+pub struct UdpLoggerConfig {
+    /// The default level, see [`with_level`](struct.UdpLogger.html#method.with_level).
+    pub level: LevelFilter,
+    /// The levels of modules, see
+    /// [`with_module_level`](struct.UdpLogger.html#method.with_module_level).
+    pub module_levels: Vec<(String, LevelFilter)>,
+    /// The default source, see [`with_source`](struct.UdpLogger.html#method.with_source).
+    pub source: Option<String>,
+    /// Level specific sources, see
+    /// [`with_sources`](struct.UdpLogger.html#method.with_sources).
+    pub sources: Vec<(LevelFilter, String)>,
+    /// The default destination, see
+    /// [`with_destination`](struct.UdpLogger.html#method.with_destination).
+    pub destination: Option<String>,
+    /// Level specific destinations, see
+    /// [`with_destinations`](struct.UdpLogger.html#method.with_destinations).
+    pub destinations: Vec<(LevelFilter, String)>,
+    /// The wire format, see [`with_wire_fmt`](struct.UdpLogger.html#method.with_wire_fmt).
+    pub wire_fmt: WireFmt,
+}
+
+#[cfg(feature = "serde")]
+impl Default for UdpLoggerConfig {
+    fn default() -> Self {
+        UdpLoggerConfig {
+            level: LevelFilter::Trace,
+            module_levels: Vec::new(),
+            source: None,
+            sources: Vec::new(),
+            destination: None,
+            destinations: Vec::new(),
+            wire_fmt: WireFmt::default(),
+        }
+    }
+}
+
+/// The UdpLogger is a control structure for logging via UDP packets.
+#[derive(Debug)]
+pub struct UdpLogger {
+    default_level: LevelFilter,
+    module_levels: Vec<(String, LevelFilter)>,
+    #[cfg(feature = "regex")]
+    regex_levels: Vec<(regex::Regex, LevelFilter)>,
+    default_source: Arc<Socket>,
+    sources: Vec<(LevelFilter, Arc<Socket>)>,
+    exact_sources: Vec<(Level, Arc<Socket>)>,
+    default_destination: String,
+    destinations: Vec<(LevelFilter, String)>,
+    exact_destinations: Vec<(Level, String)>,
+    additional_destinations: Vec<String>,
+    target_destinations: Vec<(String, String)>,
+    resolve_interval: Option<Duration>,
+    resolved: Mutex<HashMap<String, Resolved>>,
+    init_error: Option<UdpLoggerError>,
+    invalid_directives: Vec<String>,
+    rate_limits: [Option<RateLimiter>; 5],
+    sampling: [Option<Sampler>; 5],
+    wire_fmt: WireFmt,
+    formatter: Option<CustomFormatter>,
+    null_sink: bool,
+    checksum: bool,
+    sequence: Option<AtomicU64>,
+    #[cfg(feature = "signing")]
+    hmac: Option<Signer>,
+    #[cfg(feature = "signing")]
+    hmac_len: usize,
+    sorted_kv: bool,
+    redacted_keys: Vec<String>,
+    redaction: Redaction,
+    max_kv_pairs: Option<usize>,
+    kv_style: KvStyle,
+    level_style: LevelStyle,
+    terminator: Terminator,
+    global_fields: Vec<(String, String)>,
+    level_codes: [u8; 5],
+    level_colors: Option<[&'static str; 5]>,
+    // the levels, formatted per the level style and colors by build()
+    level_names: [Cow<'static, str>; 5],
+    #[cfg(feature = "syslog")]
+    facility: Facility,
+    hostname: Option<String>,
+    app_name: Option<String>,
+    pid: Option<u32>,
+    thread_names: bool,
+    source_location: bool,
+    tee: Option<Tee>,
+    connected_socket: bool,
+    multicast_interface: Option<MulticastInterface>,
+    broadcast: bool,
+    send_buffer_size: Option<usize>,
+    send_retries: u32,
+    blocking: bool,
+    ttl: Option<u32>,
+    oversize_policy: OversizePolicy,
+    fragment_size: usize,
+    max_datagram_size: usize,
+    next_message_id: std::sync::atomic::AtomicU32,
+    multicast_ttl: Option<u32>,
+    timestamp_format: String,
+    timestamp_precision: Option<TimestampPrecision>,
+    timestamps: bool,
+    clock: Arc<dyn Clock + Send + Sync>,
+    error_handler: ErrorHandler,
+    pending: Mutex<VecDeque<Pending>>,
+    async_capacity: Option<usize>,
+    queue_full_policy: QueueFullPolicy,
+    async_sender: Option<AsyncSender>,
+    #[cfg(feature = "tokio")]
+    tokio_runtime: Option<(tokio::runtime::Handle, usize)>,
+    #[cfg(feature = "tokio")]
+    tokio_sender: Option<TokioSender>,
+    counters: Arc<Counters>,
+    dynamic_levels: Option<Arc<RwLock<Levels>>>,
+    // the wire format, when it can be changed through a UdpLoggerHandle
+    dynamic_wire_fmt: Option<Arc<RwLock<WireFmt>>>,
+    // the most verbose level of any module, as a usize, shared with a UdpLoggerHandle
+    level_ceiling: Arc<AtomicUsize>,
+}
+
+// The levels of a logger which can be changed at runtime, through a UdpLoggerHandle.
+#[derive(Debug)]
+struct Levels {
+    default_level: LevelFilter,
+    module_levels: Vec<(String, LevelFilter)>,
+    // the module levels the logger was built with, restored by clear_module_level()
+    built_module_levels: Vec<(String, LevelFilter)>,
+    // set by UdpLoggerHandle::shutdown(), after which nothing is logged
+    shut_down: bool,
+}
+
+impl Levels {
+    fn module_level(&self, target: &str) -> Option<LevelFilter> {
+        module_level(&self.module_levels, target)
+    }
+}
+
+/// A handle for changing the levels, and wire format, of a UdpLogger at runtime, once it has
+/// been installed by init_with_handle().
+#[derive(Debug, Clone)]
+pub struct UdpLoggerHandle {
+    levels: Arc<RwLock<Levels>>,
+    wire_fmt: Arc<RwLock<WireFmt>>,
+    level_ceiling: Arc<AtomicUsize>,
+    // the levels of regular expressions can't be changed, but they bound the max level
+    regex_ceiling: LevelFilter,
+    counters: Arc<Counters>,
+}
+
+impl UdpLoggerHandle {
+    /// Set the 'default' log level.
+    pub fn set_level(&self, level: LevelFilter) {
+        let mut levels = self.write();
+        levels.default_level = level;
+        self.set_max_level(&levels);
+    }
+
+    /// Set the log level of a module and its sub-modules, replacing any level previously
+    /// set for the module, until it's cleared with [`clear_module_level`].
+    ///
+    /// # Examples
+    ///
+    /// Log a module verbosely during an incident, then restore it:
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{LevelFilter, UdpLogger};
+    ///
+    /// let handle = UdpLogger::new()
+    ///     .with_level(LevelFilter::Info)
+    ///     .init_with_handle()
+    ///     .unwrap();
+    /// handle.set_module_level("my_app::net", LevelFilter::Trace);
+    /// handle.clear_module_level("my_app::net");
+    /// ```
+    ///
+    /// [`clear_module_level`]: #method.clear_module_level
+    pub fn set_module_level(&self, target: &str, level: LevelFilter) {
+        let mut levels = self.write();
+        match levels
+            .module_levels
+            .iter_mut()
+            .find(|(name, _level)| name == target)
+        {
+            Some((_name, module_level)) => *module_level = level,
+            None => {
+                levels.module_levels.push((target.to_string(), level));
+                levels
+                    .module_levels
+                    .sort_by_key(|(name, _level)| specificity(name));
+            }
+        }
+        self.set_max_level(&levels);
+    }
+
+    /// Restore the log level of a module, set by [`set_module_level`], to the level it was
+    /// built with. A module without a level when the logger was built logs at the level of
+    /// its parent module, or the default level, once more.
+    ///
+    /// [`set_module_level`]: #method.set_module_level
+    pub fn clear_module_level(&self, target: &str) {
+        let mut levels = self.write();
+        let built = levels
+            .built_module_levels
+            .iter()
+            .find(|(name, _level)| name == target)
+            .map(|(_name, level)| *level);
+        match built {
+            Some(built) => {
+                if let Some((_name, level)) = levels
+                    .module_levels
+                    .iter_mut()
+                    .find(|(name, _level)| name == target)
+                {
+                    *level = built;
+                }
+            }
+            None => levels.module_levels.retain(|(name, _level)| name != target),
+        }
+        self.set_max_level(&levels);
+    }
+
+    /// Set the wire format of the records logged from now on, such as once collectors have
+    /// been upgraded to decode it. Each record is formatted, and sent, in one wire format,
+    /// even while the wire format is changed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{UdpLogger, WireFmt};
+    ///
+    /// let handle = UdpLogger::new().init_with_handle().unwrap();
+    /// handle.set_wire_fmt(WireFmt::ByteBuffer);
+    /// ```
+    pub fn set_wire_fmt(&self, wire_fmt: WireFmt) {
+        *self
+            .wire_fmt
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = wire_fmt;
+    }
+
+    /// The counts of payloads sent and dropped so far.
+    pub fn stats(&self) -> LogStats {
+        self.counters.stats()
+    }
+
+    /// Flush the logger, and leave it inert, so that nothing more is logged, even once levels
+    /// are set again. This is for the global logger, which is never dropped, so that the last
+    /// records aren't lost at exit.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{info, UdpLogger};
+    ///
+    /// let handle = UdpLogger::new().init_with_handle().unwrap();
+    /// info!("exiting");
+    /// handle.shutdown();
+    /// ```
+    pub fn shutdown(&self) {
+        let mut levels = self.write();
+        levels.shut_down = true;
+        self.set_max_level(&levels);
+        drop(levels);
+        log::logger().flush();
+    }
+
+    // Set the max level, of the logger and of the log crate, to the most verbose level.
+    fn set_max_level(&self, levels: &Levels) {
+        let max_level = if levels.shut_down {
+            LevelFilter::Off
+        } else {
+            max_level_of(levels.default_level, &levels.module_levels).max(self.regex_ceiling)
+        };
+        self.level_ceiling
+            .store(max_level as usize, Ordering::Relaxed);
+        log::set_max_level(max_level);
+    }
+
+    fn write(&self) -> std::sync::RwLockWriteGuard<'_, Levels> {
+        self.levels
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// The outgoing interface for multicast sends, an address for IPv4 or an index for IPv6.
+#[derive(Debug)]
+enum MulticastInterface {
+    V4(std::net::Ipv4Addr),
+    V6(u32),
+}
+
+// The local stream which records are copied to, in addition to being sent.
+#[derive(Debug, Clone, Copy)]
+enum Tee {
+    Stdout,
+    Stderr,
+}
+
+/// The errors which can occur while initializing the UdpLogger.
+///
+/// Errors encountered by the builder, such as a source which couldn't be bound, are held
+/// until [`init`] is called, so that the builder can still be chained.
+///
+/// [`init`]: struct.UdpLogger.html#method.init
+#[derive(Debug)]
+pub enum UdpLoggerError {
+    /// A logger has already been set.
+    SetLogger(SetLoggerError),
+    /// A socket couldn't be bound, or configured, with the address of the socket.
+    Bind(std::io::Error, String),
+    /// The destination couldn't be resolved to an address.
+    Resolve(std::io::Error, String),
+    /// The destination is an IPv6 address, which can't be broadcast to, though broadcast is
+    /// enabled.
+    NotBroadcast(String),
+    /// The background sender thread couldn't be spawned.
+    Spawn(std::io::Error),
+    /// The sampling fraction isn't greater than 0, and no greater than 1.
+    Sampling(f64),
+    /// The fragment size doesn't exceed the fragment header.
+    FragmentSize(usize),
+    /// The largest datagram size is too small to send a payload.
+    DatagramSize(usize),
+    /// The HMAC tag length is outside of 16 to 32 bytes.
+    HmacLen(usize),
+    /// The capacity of the background sender's, or tokio sender's, queue is 0.
+    QueueCapacity(usize),
+}
+
+impl std::fmt::Display for UdpLoggerError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UdpLoggerError::SetLogger(err) => write!(f, "unable to set logger, err={}", err),
+            UdpLoggerError::Bind(err, addr) => {
+                write!(f, "unable to bind to socket {}, err={}", addr, err)
+            }
+            UdpLoggerError::Resolve(err, destination) => write!(
+                f,
+                "unable to resolve destination {}, err={}",
+                destination, err
+            ),
+            UdpLoggerError::NotBroadcast(destination) => {
+                write!(f, "destination {} is not a broadcast address", destination)
+            }
+            UdpLoggerError::Spawn(err) => {
+                write!(f, "unable to spawn the sender thread, err={}", err)
+            }
+            UdpLoggerError::Sampling(fraction) => write!(
+                f,
+                "sampling fraction {} must be greater than 0, and no greater than 1",
+                fraction
+            ),
+            UdpLoggerError::FragmentSize(bytes) => write!(
+                f,
+                "fragment size {} must exceed the {} byte fragment header",
+                bytes, FRAGMENT_HEADER_SIZE
+            ),
+            UdpLoggerError::DatagramSize(bytes) => write!(
+                f,
+                "max datagram size {} must be at least {} bytes",
+                bytes, MIN_DATAGRAM_SIZE
+            ),
+            UdpLoggerError::HmacLen(bytes) => {
+                write!(f, "hmac length {} must be from 16 to 32 bytes", bytes)
+            }
+            UdpLoggerError::QueueCapacity(capacity) => {
+                write!(f, "queue capacity {} must be at least 1", capacity)
+            }
+        }
+    }
+}
+
+impl std::error::Error for UdpLoggerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            UdpLoggerError::SetLogger(err) => Some(err),
+            UdpLoggerError::Bind(err, _addr) => Some(err),
+            UdpLoggerError::Resolve(err, _destination) => Some(err),
+            UdpLoggerError::NotBroadcast(_destination) => None,
+            UdpLoggerError::Spawn(err) => Some(err),
+            UdpLoggerError::Sampling(_fraction) => None,
+            UdpLoggerError::FragmentSize(_bytes) => None,
+            UdpLoggerError::DatagramSize(_bytes) => None,
+            UdpLoggerError::HmacLen(_bytes) => None,
+            UdpLoggerError::QueueCapacity(_capacity) => None,
+        }
+    }
+}
+
+impl From<SetLoggerError> for UdpLoggerError {
+    fn from(err: SetLoggerError) -> Self {
+        UdpLoggerError::SetLogger(err)
+    }
+}
+
+// Keeps every nth record, starting with the first.
+#[derive(Debug)]
+struct Sampler {
+    every: u64,
+    count: AtomicU64,
+}
+
+impl Sampler {
+    fn keep(&self) -> bool {
+        self.count
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.every)
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    refilled: Instant,
+    suppressed: u64,
+}
+
+// A token bucket, admitting up to a rate of records per second, with bursts of up to a
+// second's worth of records.
+#[derive(Debug)]
+struct RateLimiter {
+    rate: f64,
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    fn new(per_second: u32) -> Self {
+        RateLimiter {
+            rate: f64::from(per_second),
+            bucket: Mutex::new(Bucket {
+                tokens: f64::from(per_second),
+                refilled: Instant::now(),
+                suppressed: 0,
+            }),
+        }
+    }
+
+    // None if the record is suppressed, otherwise the count of records suppressed since
+    // the previous record was admitted.
+    fn admit(&self) -> Option<u64> {
+        let mut bucket = self
+            .bucket
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let now = Instant::now();
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * self.rate).min(self.rate);
+        bucket.refilled = now;
+        if bucket.tokens < 1.0 {
+            bucket.suppressed += 1;
+            return None;
+        }
+        bucket.tokens -= 1.0;
+        Some(std::mem::replace(&mut bucket.suppressed, 0))
+    }
+}
+
+// The addresses a destination hostname resolved to, and when it was resolved.
+#[derive(Debug)]
+struct Resolved {
+    addrs: Vec<SocketAddr>,
+    at: Instant,
+}
+
+// A payload which couldn't be sent without blocking, it is held until it can be sent, before
+// the next payload, or by flush().
+#[derive(Debug)]
+struct Pending {
+    socket: Arc<Socket>,
+    destination: String,
+    payload: Vec<u8>,
+}
+
+/// A Transport sends payloads to destinations.
+///
+/// The UdpLogger sends with a UdpSocket unless another transport is provided with
+/// [`with_transport`], which allows for alternate sinks, or capturing payloads in testing.
+/// A transport may return an error of kind WouldBlock, in which case the payload is held
+/// until it can be sent, which is retried before later payloads are sent, so that they're
+/// sent in order, and by flush. Up to 1024 payloads are held, after which the oldest is
+/// dropped.
+///
+/// # Examples
+///
+/// ```no_run
+/// use udp_logger_rs::{Transport, UdpLogger};
+///
+/// #[derive(Debug)]
+/// struct Stdout;
+/// impl Transport for Stdout {
+///     fn send(&self, bytes: &[u8], _dest: &str) -> std::io::Result<usize> {
+///         println!("{}", String::from_utf8_lossy(bytes));
+///         Ok(bytes.len())
+///     }
+/// }
+///
+/// UdpLogger::new().with_transport(Box::new(Stdout)).init().unwrap();
+/// ```
+///
+/// [`with_transport`]: struct.UdpLogger.html#method.with_transport
+pub trait Transport: std::fmt::Debug {
+    /// Send the bytes to the destination, returning the number of bytes sent.
+    fn send(&self, bytes: &[u8], dest: &str) -> std::io::Result<usize>;
+}
+
+impl Transport for UdpSocket {
+    fn send(&self, bytes: &[u8], dest: &str) -> std::io::Result<usize> {
+        self.send_to(bytes, dest)
+    }
+}
+
+#[cfg(unix)]
+impl Transport for UnixDatagram {
+    fn send(&self, bytes: &[u8], dest: &str) -> std::io::Result<usize> {
+        self.send_to(bytes, dest)
+    }
+}
+
+// The most bytes of frames held by a TcpTransport while it is disconnected.
+const TCP_BACKLOG_SIZE: usize = 1 << 20;
+
+// The delay before the first reconnection attempt of a TcpTransport, doubling with each
+// failed attempt up to the maximum.
+const TCP_INITIAL_BACKOFF: Duration = Duration::from_millis(100);
+const TCP_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+// The longest a TcpTransport waits to connect, or to write a frame, before it drops the
+// connection, so that logging threads aren't stalled by an unresponsive collector.
+const TCP_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug)]
+struct TcpState {
+    stream: Option<std::net::TcpStream>,
+    backlog: VecDeque<Vec<u8>>,
+    backlog_size: usize,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+// A transport, sending each payload as a frame, a u32 length followed by the payload, over
+// a TCP connection. The connection is established on the first send, and re-established,
+// with backoff, after it fails, or times out. Meanwhile, frames are held in a bounded
+// backlog. Frames are counted as sent once they're written, rather than when queued.
+#[derive(Debug)]
+struct TcpTransport {
+    state: Mutex<TcpState>,
+    counters: Arc<Counters>,
+}
+
+impl TcpTransport {
+    fn new(counters: Arc<Counters>) -> Self {
+        TcpTransport {
+            state: Mutex::new(TcpState {
+                stream: None,
+                backlog: VecDeque::new(),
+                backlog_size: 0,
+                backoff: TCP_INITIAL_BACKOFF,
+                next_attempt: Instant::now(),
+            }),
+            counters,
+        }
+    }
+}
+
+// Connect to the first address of the destination which accepts within the timeout.
+fn connect_tcp(dest: &str) -> std::io::Result<std::net::TcpStream> {
+    let mut last_err = None;
+    for addr in dest.to_socket_addrs()? {
+        match std::net::TcpStream::connect_timeout(&addr, TCP_TIMEOUT) {
+            Ok(stream) => {
+                stream.set_write_timeout(Some(TCP_TIMEOUT))?;
+                return Ok(stream);
+            }
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} didn't resolve to an address", dest),
+        )
+    }))
+}
+
+impl Transport for TcpTransport {
+    fn send(&self, bytes: &[u8], dest: &str) -> std::io::Result<usize> {
+        let len: u32 = std::convert::TryFrom::try_from(bytes.len())
+            .map_err(|_| oversize_error(bytes.len(), u32::MAX as usize))?;
+        let mut frame = Vec::with_capacity(4 + bytes.len());
+        frame.extend_from_slice(&len.to_be_bytes());
+        frame.extend_from_slice(bytes);
+
+        let mut state = self
+            .state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if state.backlog_size + frame.len() > TCP_BACKLOG_SIZE {
+            return Err(std::io::Error::other("tcp backlog is full"));
+        }
+        state.backlog_size += frame.len();
+        state.backlog.push_back(frame);
+        if state.stream.is_none() && Instant::now() >= state.next_attempt {
+            match connect_tcp(dest) {
+                Ok(stream) => {
+                    state.stream = Some(stream);
+                    state.backoff = TCP_INITIAL_BACKOFF;
+                }
+                Err(_err) => {
+                    state.next_attempt = Instant::now() + state.backoff;
+                    state.backoff = std::cmp::min(state.backoff * 2, TCP_MAX_BACKOFF);
+                }
+            }
+        }
+        let state = &mut *state;
+        if let Some(stream) = &mut state.stream {
+            while let Some(frame) = state.backlog.front() {
+                // a write which times out fails as any other, and the frame is resent whole
+                if stream.write_all(frame).is_err() {
+                    state.stream = None;
+                    state.next_attempt = Instant::now() + state.backoff;
+                    state.backoff = std::cmp::min(state.backoff * 2, TCP_MAX_BACKOFF);
+                    break;
+                }
+                self.counters.sent(frame.len() - 4);
+                state.backlog_size -= frame.len();
+                state.backlog.pop_front();
+            }
+        }
+        // the frames written have been counted, none may have been written yet
+        Ok(0)
+    }
+}
+
+/// The configuration of DTLS sessions with collectors, see [`with_dtls`].
+///
+/// The connector, built for `SslMethod::dtls()`, carries the trusted certificates, and any
+/// client certificate, and the domain is the name the collector's certificate is verified
+/// against. Requires the `dtls` feature.
+///
+/// [`with_dtls`]: struct.UdpLogger.html#method.with_dtls
+#[cfg(feature = "dtls")]
+#[derive(Clone)]
+pub struct DtlsConfig {
+    connector: openssl::ssl::SslConnector,
+    domain: String,
+    handshake_timeout: Duration,
+    session_lifetime: Duration,
+}
+
+#[cfg(feature = "dtls")]
+impl DtlsConfig {
+    /// Create a configuration, verifying collectors as the domain.
+    pub fn new(connector: openssl::ssl::SslConnector, domain: &str) -> Self {
+        Self {
+            connector,
+            domain: domain.to_string(),
+            handshake_timeout: DTLS_HANDSHAKE_TIMEOUT,
+            session_lifetime: DTLS_SESSION_LIFETIME,
+        }
+    }
+
+    /// Limit how long a handshake waits for each response from a collector, which
+    /// otherwise is 5 seconds.
+    #[must_use]
+    pub fn with_handshake_timeout(mut self, timeout: Duration) -> Self {
+        self.handshake_timeout = timeout;
+
+        self
+    }
+
+    /// Re-establish sessions once they reach the lifetime, which otherwise is 10 minutes.
+    ///
+    /// As UDP is connectionless, a collector which restarts, losing its sessions, can't
+    /// tell the logger, which continues sending payloads the collector discards. Renewing
+    /// sessions bounds the loss.
+    #[must_use]
+    pub fn with_session_lifetime(mut self, lifetime: Duration) -> Self {
+        self.session_lifetime = lifetime;
+
+        self
+    }
+}
+
+#[cfg(feature = "dtls")]
+impl std::fmt::Debug for DtlsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DtlsConfig")
+            .field("domain", &self.domain)
+            .field("handshake_timeout", &self.handshake_timeout)
+            .field("session_lifetime", &self.session_lifetime)
+            .finish()
+    }
+}
+
+// The defaults of a DtlsConfig.
+#[cfg(feature = "dtls")]
+const DTLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+#[cfg(feature = "dtls")]
+const DTLS_SESSION_LIFETIME: Duration = Duration::from_secs(600);
+
+// The delay before retrying a failed handshake, doubling with each failure up to the maximum.
+#[cfg(feature = "dtls")]
+const DTLS_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+#[cfg(feature = "dtls")]
+const DTLS_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+// A UDP socket, connected to a collector, over which a DTLS session runs.
+#[cfg(feature = "dtls")]
+#[derive(Debug)]
+struct DtlsChannel(UdpSocket);
+
+#[cfg(feature = "dtls")]
+impl std::io::Read for DtlsChannel {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.recv(buf)
+    }
+}
+
+#[cfg(feature = "dtls")]
+impl Write for DtlsChannel {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.send(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "dtls")]
+#[derive(Debug)]
+struct DtlsSession {
+    stream: Option<openssl::ssl::SslStream<DtlsChannel>>,
+    established: Instant,
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+// A transport, sending each payload as a DTLS record, over a session with each destination.
+// A session is established on the first send, and re-established after a send fails, or it
+// reaches its lifetime. A failed handshake is retried with backoff, meanwhile payloads are
+// dropped.
+#[cfg(feature = "dtls")]
+#[derive(Debug)]
+struct DtlsTransport {
+    config: DtlsConfig,
+    sessions: Mutex<HashMap<String, DtlsSession>>,
+}
+
+#[cfg(feature = "dtls")]
+impl DtlsTransport {
+    fn new(config: DtlsConfig) -> Self {
+        DtlsTransport {
+            config,
+            sessions: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn handshake(&self, dest: &str) -> std::io::Result<openssl::ssl::SslStream<DtlsChannel>> {
+        let addr = dest.to_socket_addrs()?.next().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} didn't resolve to any address", dest),
+            )
+        })?;
+        let local = if addr.is_ipv4() {
+            SocketAddr::from(([0, 0, 0, 0], 0))
+        } else {
+            SocketAddr::from(([0u16; 8], 0))
+        };
+        let socket = UdpSocket::bind(local)?;
+        socket.connect(addr)?;
+        socket.set_read_timeout(Some(self.config.handshake_timeout))?;
+        self.config
+            .connector
+            .connect(&self.config.domain, DtlsChannel(socket))
+            .map_err(|err| {
+                std::io::Error::other(format!("dtls handshake with {} failed: {}", dest, err))
+            })
+    }
+}
+
+#[cfg(feature = "dtls")]
+impl Transport for DtlsTransport {
+    fn send(&self, bytes: &[u8], dest: &str) -> std::io::Result<usize> {
+        let mut sessions = self
+            .sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let session = sessions
+            .entry(dest.to_string())
+            .or_insert_with(|| DtlsSession {
+                stream: None,
+                established: Instant::now(),
+                backoff: DTLS_INITIAL_BACKOFF,
+                next_attempt: Instant::now(),
+            });
+        if session.stream.is_some() && session.established.elapsed() >= self.config.session_lifetime
+        {
+            if let Some(mut stream) = session.stream.take() {
+                let _result = stream.shutdown();
+            }
+        }
+        if session.stream.is_none() {
+            if Instant::now() < session.next_attempt {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::NotConnected,
+                    format!("dtls session with {} isn't established", dest),
+                ));
+            }
+            match self.handshake(dest) {
+                Ok(stream) => {
+                    session.stream = Some(stream);
+                    session.established = Instant::now();
+                    session.backoff = DTLS_INITIAL_BACKOFF;
+                }
+                Err(err) => {
+                    session.next_attempt = Instant::now() + session.backoff;
+                    session.backoff = std::cmp::min(session.backoff * 2, DTLS_MAX_BACKOFF);
+                    return Err(err);
+                }
+            }
+        }
+        let result = match &mut session.stream {
+            Some(stream) => stream.write(bytes),
+            None => unreachable!("the dtls session was just established"),
+        };
+        if result.is_err() {
+            session.stream = None;
+        }
+        result
+    }
+}
+
+// A source socket, sending to addresses, or with a Unix domain socket, to paths, or a
+// transport provided by the user.
+#[derive(Debug)]
+enum Socket {
+    Udp(UdpSource),
+    #[cfg(unix)]
+    Unix(UnixDatagram),
+    Transport(Box<dyn Transport + Send + Sync>),
+}
+
+impl Socket {
+    fn send_to(&self, payload: &[u8], destination: &str) -> std::io::Result<usize> {
+        match self {
+            Socket::Udp(source) => source.send_to(payload, destination),
+            #[cfg(unix)]
+            Socket::Unix(socket) => socket.send_to(payload, destination),
+            Socket::Transport(transport) => transport.send(payload, destination),
+        }
+    }
+
+    // The address the socket is bound to, as it was provided, for reporting errors.
+    fn addr(&self) -> String {
+        match self {
+            Socket::Udp(source) => source.addr.clone(),
+            #[cfg(unix)]
+            Socket::Unix(socket) => socket
+                .local_addr()
+                .ok()
+                .and_then(|addr| addr.as_pathname().map(|path| path.display().to_string()))
+                .unwrap_or_else(|| UNBOUND_UNIX.to_string()),
+            Socket::Transport(_transport) => "transport".to_string(),
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<SocketAddr> {
+        match self {
+            Socket::Udp(source) => source.with(UdpSocket::local_addr),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "the source isn't a UDP socket",
+            )),
+        }
+    }
+
+    fn set_nonblocking(&self, nonblocking: bool) -> std::io::Result<()> {
+        match self {
+            Socket::Udp(source) => source.with(|socket| socket.set_nonblocking(nonblocking)),
+            #[cfg(unix)]
+            Socket::Unix(socket) => socket.set_nonblocking(nonblocking),
+            Socket::Transport(_transport) => Ok(()),
+        }
+    }
+}
+
+// The address reported for a Unix datagram socket which isn't bound to a path.
+#[cfg(unix)]
+const UNBOUND_UNIX: &str = "(unbound unix socket)";
+
+// The consecutive send failures of a UDP source socket after which it's rebound.
+const REBIND_THRESHOLD: u32 = 3;
+
+// The errno of a send on a closed, or otherwise invalid, socket descriptor.
+#[cfg(not(windows))]
+const EBADF: i32 = 9;
+// WSAENOTSOCK, the Windows error of a send on a closed socket.
+#[cfg(windows)]
+const EBADF: i32 = 10038;
+
+// Whether a send failed because of the source socket, the network down, its address gone,
+// its descriptor invalid, or the socket unbound since a rebind failed, rather than because
+// of the destination, which rebinding wouldn't help.
+fn is_socket_error(err: &std::io::Error) -> bool {
+    match err.kind() {
+        std::io::ErrorKind::NetworkDown
+        | std::io::ErrorKind::AddrNotAvailable
+        | std::io::ErrorKind::NotConnected => true,
+        _ => err.raw_os_error() == Some(EBADF),
+    }
+}
+
+// The delay before retrying a failed rebind, doubling with each failure up to the maximum.
+const REBIND_INITIAL_BACKOFF: Duration = Duration::from_secs(1);
+const REBIND_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Rebind {
+    backoff: Duration,
+    next_attempt: Instant,
+}
+
+// A UDP source socket, and the address it's bound to, so that it can be rebound after
+// repeated send failures, see UdpLogger::recover(). The socket is None while it can't be
+// rebound.
+#[derive(Debug)]
+struct UdpSource {
+    addr: String,
+    socket: RwLock<Option<UdpSocket>>,
+    failures: AtomicU32,
+    rebind: Mutex<Rebind>,
+    // the destination the socket is connected to, see with_connected_socket(), which is
+    // connected again when the socket is rebound
+    peer: Mutex<Option<SocketAddr>>,
+    connected: AtomicBool,
+    // incremented when the socket is rebound, so that clones of the socket are replaced
+    generation: AtomicU32,
+}
+
+impl UdpSource {
+    // A source which is bound later, see bind().
+    fn unbound(addr: &str) -> Self {
+        UdpSource {
+            addr: addr.to_string(),
+            socket: RwLock::new(None),
+            failures: AtomicU32::new(0),
+            rebind: Mutex::new(Rebind {
+                backoff: REBIND_INITIAL_BACKOFF,
+                next_attempt: Instant::now(),
+            }),
+            peer: Mutex::new(None),
+            connected: AtomicBool::new(false),
+            generation: AtomicU32::new(0),
+        }
+    }
+
+    // Connect the socket to a destination, so that payloads are sent without an address.
+    fn connect(&self, peer: SocketAddr) -> std::io::Result<()> {
+        self.with(|socket| socket.connect(peer))?;
+        *self
+            .peer
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = Some(peer);
+        self.connected.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    // Bind the socket, unless it's already bound.
+    fn bind(&self) -> std::io::Result<()> {
+        let mut slot = self
+            .socket
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        if slot.is_none() {
+            let socket = UdpSocket::bind(&self.addr)?;
+            socket.set_nonblocking(true)?;
+            *slot = Some(socket);
+        }
+        Ok(())
+    }
+
+    fn with<T>(&self, f: impl FnOnce(&UdpSocket) -> std::io::Result<T>) -> std::io::Result<T> {
+        let socket = self
+            .socket
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        match &*socket {
+            Some(socket) => f(socket),
+            None => Err(std::io::Error::new(
+                std::io::ErrorKind::NotConnected,
+                format!("socket {} is unbound", self.addr),
+            )),
+        }
+    }
+
+    fn send_to(&self, payload: &[u8], destination: &str) -> std::io::Result<usize> {
+        let count = self.with(|socket| {
+            if self.connected.load(Ordering::Relaxed) {
+                socket.send(payload)
+            } else {
+                socket.send_to(payload, destination)
+            }
+        })?;
+        if self.failures.load(Ordering::Relaxed) != 0 {
+            self.failures.store(0, Ordering::Relaxed);
+            self.rebind
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .backoff = REBIND_INITIAL_BACKOFF;
+        }
+        Ok(count)
+    }
+}
+
+/// A formatter, provided to [`with_formatter`], which produces the payload of a record from
+/// the record and its kv pairs.
+///
+/// [`with_formatter`]: struct.UdpLogger.html#method.with_formatter
+pub type FormatFn = dyn Fn(&Record<'_>, &[(String, String)]) -> Vec<u8> + Send + Sync;
+
+#[cfg(feature = "signing")]
+type HmacSha256 = hmac::Hmac<sha2::Sha256>;
+
+// The length of an HMAC-SHA256 tag, and the shortest it can be truncated to.
+#[cfg(feature = "signing")]
+const HMAC_LEN: usize = 32;
+#[cfg(feature = "signing")]
+const HMAC_MIN_LEN: usize = 16;
+
+// Signs payloads, keyed by with_hmac_key(). The key is hashed once, and the state cloned for
+// each payload.
+#[cfg(feature = "signing")]
+#[derive(Clone)]
+struct Signer(HmacSha256);
+
+#[cfg(feature = "signing")]
+impl std::fmt::Debug for Signer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Signer")
+    }
+}
+
+// Wraps the user supplied formatter, so that UdpLogger can remain Debug.
+struct CustomFormatter(Box<FormatFn>);
+
+impl std::fmt::Debug for CustomFormatter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("CustomFormatter")
+    }
+}
+
+// Wraps the user supplied error handler, so that UdpLogger can remain Debug. The handler
+// is shared with the background sender, when there is one.
+#[derive(Clone)]
+struct ErrorHandler(Arc<dyn Fn(std::io::Error) + Send + Sync>);
+
+impl Default for ErrorHandler {
+    fn default() -> Self {
+        ErrorHandler(Arc::new(|err| {
+            eprintln!("error sending payload, err={}", err)
+        }))
+    }
+}
+
+impl std::fmt::Debug for ErrorHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("ErrorHandler")
+    }
+}
+
+/// Counts of the payloads sent and dropped by a UdpLogger.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LogStats {
+    /// The number of datagrams sent.
+    pub sent: u64,
+    /// The number of payloads, or datagrams, dropped because they couldn't be sent.
+    pub dropped: u64,
+    /// The number of bytes sent.
+    pub bytes_sent: u64,
+}
+
+/// A handle for reading the LogStats of a UdpLogger, which remains usable once the
+/// UdpLogger has been installed by init().
+///
+/// # Examples
+///
+/// ```no_run
+/// use udp_logger_rs::UdpLogger;
+///
+/// let logger = UdpLogger::new();
+/// let stats = logger.stats_handle();
+/// logger.init().unwrap();
+///
+/// println!("dropped {} log messages", stats.stats().dropped);
+/// ```
+#[derive(Debug, Clone)]
+pub struct LogStatsHandle(Arc<Counters>);
+
+impl LogStatsHandle {
+    /// The current LogStats.
+    pub fn stats(&self) -> LogStats {
+        self.0.stats()
+    }
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+impl Counters {
+    fn sent(&self, bytes: usize) {
+        // nothing was sent, such as a frame queued by a TcpTransport, which counts its frames
+        // as they're written
+        if bytes == 0 {
+            return;
+        }
+        self.sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    fn dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn stats(&self) -> LogStats {
+        LogStats {
+            sent: self.sent.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The handling of payloads logged while the background sender's queue is full.
+/// Default is Block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueueFullPolicy {
+    /// Logging waits until the queue has room for the payload.
+    Block,
+    /// The oldest queued payload is dropped, making room for the payload.
+    DropOldest,
+    /// The payload is dropped.
+    DropNewest,
+}
+
+// A payload, queued for the background sender.
+#[derive(Debug)]
+struct Queued {
+    socket: Arc<Socket>,
+    destination: String,
+    payload: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct QueueState {
+    queue: VecDeque<Queued>,
+    sending: bool,
+    shutdown: bool,
+}
+
+// The state shared by the background sender thread and the logger.
+#[derive(Debug, Default)]
+struct SenderShared {
+    state: Mutex<QueueState>,
+    // signalled when a payload is queued, or on shutdown
+    queued: Condvar,
+    // signalled when a payload is dequeued, or sent
+    dequeued: Condvar,
+}
+
+impl SenderShared {
+    fn lock(&self) -> std::sync::MutexGuard<'_, QueueState> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+// The background sender, which sends queued payloads from its own thread. On drop, it
+// sends whatever remains queued, before stopping the thread.
+#[derive(Debug)]
+struct AsyncSender {
+    shared: Arc<SenderShared>,
+    capacity: usize,
+    policy: QueueFullPolicy,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl AsyncSender {
+    fn spawn(
+        capacity: usize,
+        policy: QueueFullPolicy,
+        error_handler: ErrorHandler,
+        counters: Arc<Counters>,
+    ) -> std::io::Result<Self> {
+        let shared = Arc::new(SenderShared::default());
+        let thread_shared = shared.clone();
+        let thread = std::thread::Builder::new()
+            .name("udp-logger".to_string())
+            .spawn(move || AsyncSender::run(&thread_shared, &error_handler, &counters))?;
+        Ok(Self {
+            shared,
+            capacity,
+            policy,
+            thread: Some(thread),
+        })
+    }
+
+    fn run(shared: &SenderShared, error_handler: &ErrorHandler, counters: &Counters) {
+        let mut state = shared.lock();
+        loop {
+            if let Some(queued) = state.queue.pop_front() {
+                state.sending = true;
+                shared.dequeued.notify_all();
+                drop(state);
+                match queued.socket.send_to(&queued.payload, &queued.destination) {
+                    Ok(count) => counters.sent(count),
+                    Err(err) => {
+                        counters.dropped();
+                        (error_handler.0)(err);
+                    }
+                }
+                state = shared.lock();
+                state.sending = false;
+                shared.dequeued.notify_all();
+            } else if state.shutdown {
+                break;
+            } else {
+                state = shared
+                    .queued
+                    .wait(state)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+            }
+        }
+    }
+
+    fn enqueue(&self, queued: Queued) -> std::io::Result<()> {
+        let mut state = self.shared.lock();
+        let mut result = Ok(());
+        while state.queue.len() >= self.capacity {
+            match self.policy {
+                QueueFullPolicy::Block => {
+                    state = self
+                        .shared
+                        .dequeued
+                        .wait(state)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                }
+                QueueFullPolicy::DropOldest => {
+                    state.queue.pop_front();
+                    result = Err(queue_full_error());
+                }
+                QueueFullPolicy::DropNewest => return Err(queue_full_error()),
+            }
+        }
+        state.queue.push_back(queued);
+        self.shared.queued.notify_one();
+        result
+    }
+
+    // Wait until all queued payloads have been sent, or the deadline passes.
+    fn flush(&self, deadline: Instant) {
+        let mut state = self.shared.lock();
+        while !state.queue.is_empty() || state.sending {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
+            }
+            state = self
+                .shared
+                .dequeued
+                .wait_timeout(state, deadline - now)
+                .map(|(state, _timeout)| state)
+                .unwrap_or_else(|poisoned| poisoned.into_inner().0);
+        }
+    }
+}
+
+impl Drop for AsyncSender {
+    fn drop(&mut self) {
+        self.shared.lock().shutdown = true;
+        self.shared.queued.notify_all();
+        if let Some(thread) = self.thread.take() {
+            let _result = thread.join();
+        }
+    }
+}
+
+// The sender of with_tokio(), which sends queued payloads from a task on a tokio runtime.
+// On drop, the channel closes, and the task sends whatever remains queued before ending.
+#[cfg(feature = "tokio")]
+#[derive(Debug)]
+struct TokioSender {
+    sender: tokio::sync::mpsc::Sender<Queued>,
+    // the payloads queued, or being sent
+    in_flight: Arc<AtomicUsize>,
+}
+
+// A tokio socket, sending from a clone of a UDP source socket, and the generation of the
+// source socket it was cloned from.
+#[cfg(feature = "tokio")]
+type TokioSocket = (Arc<Socket>, u32, tokio::net::UdpSocket);
+
+#[cfg(feature = "tokio")]
+impl TokioSender {
+    fn spawn(
+        runtime: &tokio::runtime::Handle,
+        capacity: usize,
+        blocking: bool,
+        error_handler: ErrorHandler,
+        counters: Arc<Counters>,
+    ) -> Self {
+        let (sender, receiver) = tokio::sync::mpsc::channel(capacity);
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        runtime.spawn(TokioSender::run(
+            receiver,
+            in_flight.clone(),
+            blocking,
+            error_handler,
+            counters,
+        ));
+        Self { sender, in_flight }
+    }
+
+    async fn run(
+        mut receiver: tokio::sync::mpsc::Receiver<Queued>,
+        in_flight: Arc<AtomicUsize>,
+        blocking: bool,
+        error_handler: ErrorHandler,
+        counters: Arc<Counters>,
+    ) {
+        let mut sockets = Vec::new();
+        while let Some(queued) = receiver.recv().await {
+            let result = match &*queued.socket {
+                // a tokio socket can't be cloned from a blocking socket, without making the
+                // source socket nonblocking too
+                Socket::Udp(source) if !blocking => {
+                    match TokioSender::socket(&mut sockets, &queued.socket, source) {
+                        Ok(socket) => {
+                            socket
+                                .send_to(&queued.payload, queued.destination.as_str())
+                                .await
+                        }
+                        Err(err) => Err(err),
+                    }
+                }
+                // only UDP sockets have a tokio counterpart, others send as they otherwise would
+                _ => queued.socket.send_to(&queued.payload, &queued.destination),
+            };
+            match result {
+                Ok(count) => counters.sent(count),
+                Err(err) => {
+                    counters.dropped();
+                    (error_handler.0)(err);
+                }
+            }
+            in_flight.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    // The tokio socket of a UDP source, created from a clone of its socket when first sent
+    // from, and again once the socket is rebound. The clone shares the nonblocking mode of
+    // the socket, so the socket is left as it is.
+    fn socket<'a>(
+        sockets: &'a mut Vec<TokioSocket>,
+        socket: &Arc<Socket>,
+        source: &UdpSource,
+    ) -> std::io::Result<&'a tokio::net::UdpSocket> {
+        let generation = source.generation.load(Ordering::Relaxed);
+        let index = sockets
+            .iter()
+            .position(|(known, _generation, _socket)| Arc::ptr_eq(known, socket));
+        if let Some(index) = index {
+            if sockets[index].1 == generation {
+                return Ok(&sockets[index].2);
+            }
+            sockets.swap_remove(index);
+        }
+        let clone = source.with(|socket| socket.try_clone())?;
+        sockets.push((
+            socket.clone(),
+            generation,
+            tokio::net::UdpSocket::from_std(clone)?,
+        ));
+        Ok(&sockets[sockets.len() - 1].2)
+    }
+
+    fn enqueue(&self, queued: Queued) -> std::io::Result<()> {
+        use tokio::sync::mpsc::error::TrySendError;
+        self.in_flight.fetch_add(1, Ordering::Relaxed);
+        self.sender.try_send(queued).map_err(|err| {
+            self.in_flight.fetch_sub(1, Ordering::Relaxed);
+            match err {
+                TrySendError::Full(_queued) => queue_full_error(),
+                TrySendError::Closed(_queued) => std::io::Error::new(
+                    std::io::ErrorKind::BrokenPipe,
+                    "tokio runtime has shut down, payload dropped",
+                ),
+            }
+        })
+    }
+
+    // Wait until all queued payloads have been sent, or the deadline passes. On a
+    // current_thread runtime, the task can't send while its only thread waits, so flushing
+    // returns at once. On a multi_thread runtime, the worker is handed off while waiting.
+    fn flush(&self, deadline: Instant) {
+        let wait = || {
+            while self.in_flight.load(Ordering::Relaxed) != 0 && Instant::now() < deadline {
+                std::thread::sleep(Duration::from_millis(1));
+            }
+        };
+        match tokio::runtime::Handle::try_current().map(|handle| handle.runtime_flavor()) {
+            Ok(tokio::runtime::RuntimeFlavor::CurrentThread) => (),
+            Ok(tokio::runtime::RuntimeFlavor::MultiThread) => tokio::task::block_in_place(wait),
+            _ => wait(),
+        }
+    }
+}
+
+impl UdpLogger {
+    /// Initializes the global logger with a UdpLogger instance with
+    /// default log level set to `Level::Trace`.
+    ///
+    /// # Examples
+    /// ```no_run
+    /// use udp_logger_rs::{UdpLogger, warn};
+    ///
+    /// UdpLogger::new().env().init().unwrap();
+    /// warn!("This is an example message.");
+    /// ```
+    ///
+    /// [`init`]: #method.init
+    #[must_use = "You must call init() to begin logging"]
+    pub fn new() -> Self {
+        Self {
+            default_level: LevelFilter::Trace,
+            module_levels: Vec::new(),
+            #[cfg(feature = "regex")]
+            regex_levels: Vec::new(),
+            // bound by build(), so that a replaced default source is never bound
+            default_source: Arc::new(Socket::Udp(UdpSource::unbound("127.0.0.1:4000"))),
+            sources: Vec::new(),
+            exact_sources: Vec::new(),
+            default_destination: "127.0.0.1:4010".to_string(),
+            destinations: Vec::new(),
+            exact_destinations: Vec::new(),
+            additional_destinations: Vec::new(),
+            target_destinations: Vec::new(),
+            resolve_interval: None,
+            resolved: Mutex::new(HashMap::new()),
+            init_error: None,
+            invalid_directives: Vec::new(),
+            rate_limits: Default::default(),
+            sampling: Default::default(),
+            wire_fmt: WireFmt::Uncompressed,
+            formatter: None,
+            null_sink: false,
+            checksum: false,
+            sequence: None,
+            #[cfg(feature = "signing")]
+            hmac: None,
+            #[cfg(feature = "signing")]
+            hmac_len: HMAC_LEN,
+            sorted_kv: false,
+            redacted_keys: Vec::new(),
+            redaction: Redaction::default(),
+            max_kv_pairs: None,
+            kv_style: KvStyle::default(),
+            level_style: LevelStyle::default(),
+            terminator: Terminator::None,
+            global_fields: Vec::new(),
+            level_codes: [1, 2, 3, 4, 5],
+            level_colors: None,
+            level_names: LEVEL_NAMES.map(Cow::Borrowed),
+            #[cfg(feature = "syslog")]
+            facility: Facility::User,
+            hostname: None,
+            app_name: None,
+            pid: None,
+            thread_names: false,
+            tee: None,
+            connected_socket: false,
+            source_location: false,
+            multicast_interface: None,
+            broadcast: false,
+            send_buffer_size: None,
+            send_retries: 0,
+            blocking: false,
+            ttl: None,
+            oversize_policy: OversizePolicy::Drop,
+            fragment_size: MAX_DATAGRAM_SIZE,
+            max_datagram_size: MAX_DATAGRAM_SIZE,
+            next_message_id: std::sync::atomic::AtomicU32::new(0),
+            multicast_ttl: None,
+            timestamp_format: DEFAULT_TIMESTAMP_FORMAT.to_string(),
+            timestamp_precision: None,
+            timestamps: true,
+            clock: Arc::new(UtcClock),
+            error_handler: ErrorHandler::default(),
+            pending: Mutex::new(VecDeque::new()),
+            async_capacity: None,
+            queue_full_policy: QueueFullPolicy::Block,
+            async_sender: None,
+            #[cfg(feature = "tokio")]
+            tokio_runtime: None,
+            #[cfg(feature = "tokio")]
+            tokio_sender: None,
+            counters: Arc::new(Counters::default()),
+            dynamic_levels: None,
+            dynamic_wire_fmt: None,
+            level_ceiling: Arc::new(AtomicUsize::new(LevelFilter::Trace as usize)),
+        }
+    }
+
+    /// Create a UdpLogger from a configuration, such as read from a configuration file.
+    ///
+    /// The configuration is applied as the builder methods its fields refer to would apply
+    /// it, sources being bound, and the first which can't be returned as an error by
+    /// [`build`], or [`init`]. The remaining builder methods can follow.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{UdpLogger, UdpLoggerConfig};
+    ///
+    /// let config = std::fs::read_to_string("logging.json").unwrap();
+    /// let config: UdpLoggerConfig = serde_json::from_str(&config).unwrap();
+    /// UdpLogger::from_config(config)
+    ///     .with_app_name("my_app")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    #[cfg(feature = "serde")]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn from_config(config: UdpLoggerConfig) -> Self {
+        let mut logger = UdpLogger::new()
+            .with_level(config.level)
+            .with_wire_fmt(config.wire_fmt)
+            .with_module_levels(config.module_levels);
+        if let Some(source) = &config.source {
+            logger = logger.with_source(source);
+        }
+        if let Some(destination) = &config.destination {
+            logger = logger.with_destination(destination);
+        }
+        let sources = config
+            .sources
+            .iter()
+            .map(|(level, source)| (*level, source.as_str()))
+            .collect();
+
+        logger
+            .with_sources(sources)
+            .with_destinations(config.destinations)
+    }
+
+    /// Simulates env_logger behavior, which enables the user to choose log
+    /// level by setting a `RUST_LOG` environment variable. `RUST_LOG` is a
+    /// comma separated list of directives, where a bare level sets the default
+    /// level and `module=level` sets the level of a module, as with [`with_module_level`].
+    /// A bare module, which isn't a level, logs at Trace, as with `module=trace`.
+    /// This will use the default level set by [`with_level`] if `RUST_LOG` is not
+    /// set or doesn't provide a bare level. Levels are case insensitive, and may be numeric,
+    /// from 0 for Off to 5 for Trace, and whitespace around directives is ignored. A level
+    /// of `off` disables logging.
+    /// Directives which can't be parsed are skipped, and reported to the error handler, see
+    /// [`with_error_handler`], by init().
+    ///
+    /// # Examples
+    ///
+    /// With `RUST_LOG=warn,my_app::net=trace`, the default level is Warn, while `my_app::net`
+    /// logs at Trace.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new().env().init().unwrap();
+    /// ```
+    ///
+    /// [`with_level`]: #method.with_level
+    /// [`with_module_level`]: #method.with_module_level
+    /// [`with_error_handler`]: #method.with_error_handler
+    #[must_use = "You must call init() to begin logging"]
+    pub fn env(self) -> Self {
+        self.env_with_var("RUST_LOG")
+    }
+
+    /// Behaves like [`env`], with a default level of `level` when `RUST_LOG` is unset, or
+    /// doesn't provide a bare level which can be parsed.
+    ///
+    /// # Examples
+    ///
+    /// Use `RUST_LOG`, else Info.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    ///
+    /// UdpLogger::new().env_or(LevelFilter::Info).init().unwrap();
+    /// ```
+    ///
+    /// [`env`]: #method.env
+    #[must_use = "You must call init() to begin logging"]
+    pub fn env_or(self, level: LevelFilter) -> Self {
+        self.with_level(level).env()
+    }
+
+    /// Behaves like [`env`], reading the directives from the named environment variable,
+    /// rather than `RUST_LOG`. This allows the UdpLogger to be configured independently of
+    /// other loggers.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new().env_with_var("UDP_LOG").init().unwrap();
+    /// ```
+    ///
+    /// [`env`]: #method.env
+    #[must_use = "You must call init() to begin logging"]
+    pub fn env_with_var(mut self, var: &str) -> Self {
+        if let Ok(directives) = std::env::var(var) {
+            for directive in directives.split(',').map(str::trim) {
+                let mut parts = directive.splitn(2, '=').map(str::trim);
+                let (module, level) = match (parts.next(), parts.next()) {
+                    (Some(""), None) => continue,
+                    // a bare module, rather than a level, enables all of its levels
+                    (Some(module), None) if parse_level(module).is_none() => {
+                        (Some(module), "trace")
+                    }
+                    (Some(level), None) => (None, level),
+                    (Some(module), Some(level)) if !module.is_empty() => (Some(module), level),
+                    _ => (None, ""),
+                };
+                match (module, parse_level(level)) {
+                    (None, Some(level)) => self.default_level = level,
+                    (Some(module), Some(level)) => self = self.with_module_level(module, level),
+                    (_, None) => self
+                        .invalid_directives
+                        .push(format!("invalid directive \"{}\" in {}", directive, var)),
+                }
+            }
+        };
+        self
+    }
+
+    /// Set the 'default' log level.
+    ///
+    /// You can override the default level for specific modules and their sub-modules using [`with_module_level`]
+    ///
+    /// [`with_module_level`]: #method.with_module_level
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_level(mut self, level: LevelFilter) -> Self {
+        self.default_level = level;
+        self
+    }
+
+    /// Override the log level for some specific modules.
+    ///
+    /// This sets the log level of a specific module and all its sub-modules.
+    /// When both the level for a parent module as well as a child module are set,
+    /// the more specific value is taken. If the log level for the same module is
+    /// specified twice, the last level specified is taken.
+    ///
+    /// # Examples
+    ///
+    /// Silence an overly verbose crate:
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    ///
+    /// UdpLogger::new().with_module_level("chatty_dependency", LevelFilter::Warn).init().unwrap();
+    /// ```
+    ///
+    /// Disable logging for all dependencies:
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    ///
+    /// UdpLogger::new()
+    ///     .with_level(LevelFilter::Off)
+    ///     .with_module_level("my_crate", LevelFilter::Info)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_module_level(mut self, target: &str, level: LevelFilter) -> Self {
+        self.module_levels.retain(|(name, _level)| name != target);
+        self.module_levels.push((target.to_string(), level));
+
+        /* Sort all module levels from most specific to least specific. The length of the module
+         * name is used instead of its actual depth to avoid module name parsing.
+         */
+        self.module_levels
+            .sort_by_key(|(name, _level)| specificity(name));
+
+        self
+    }
+
+    /// Set the log level of the modules matching a glob, and their sub-modules, where '*'
+    /// matches any characters, including "::".
+    ///
+    /// Globs are taken alongside the levels of [`with_module_level`], the most specific
+    /// first, which is the one matching the most text literally, and a module before a glob
+    /// which is as specific. So "app::*::db" takes precedence over "app", and "app::net" over
+    /// "app::*".
+    ///
+    /// # Examples
+    ///
+    /// Silence every internal module, and log the database modules of the app verbosely:
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    ///
+    /// UdpLogger::new()
+    ///     .with_level(LevelFilter::Info)
+    ///     .with_module_glob("*::internal", LevelFilter::Off)
+    ///     .with_module_glob("app::*::db", LevelFilter::Trace)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_module_level`]: #method.with_module_level
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_module_glob(self, glob: &str, level: LevelFilter) -> Self {
+        self.with_module_level(glob, level)
+    }
+
+    /// Set the log level of the targets matching a regular expression, with the `regex`
+    /// feature, for what modules and globs can't express, such as alternations.
+    ///
+    /// Regular expressions are checked after the levels of [`with_module_level`] and
+    /// [`with_module_glob`], so a module or glob matching a target takes precedence. When
+    /// neither matches, the level of the first expression matching the target, in the order
+    /// provided, is taken, otherwise the default level. An expression can match anywhere in
+    /// the target, unless it's anchored with '^' or '$'. As the expressions are evaluated
+    /// for each record no module matches, prefer modules and globs where they suffice.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    /// use regex::Regex;
+    ///
+    /// UdpLogger::new()
+    ///     .with_level(LevelFilter::Warn)
+    ///     .with_regex_level(Regex::new("^(auth|billing)::").unwrap(), LevelFilter::Debug)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_module_level`]: #method.with_module_level
+    /// [`with_module_glob`]: #method.with_module_glob
+    #[cfg(feature = "regex")]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_regex_level(mut self, regex: regex::Regex, level: LevelFilter) -> Self {
+        self.regex_levels.push((regex, level));
+
+        self
+    }
+
+    /// Set the log levels of modules, replacing any set before, rather than adding to them as
+    /// [`with_module_level`] does, such as when the levels are computed from a configuration.
+    ///
+    /// The levels are taken as [`with_module_level`] takes each, so when a module is listed
+    /// twice, the last level listed is taken.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    ///
+    /// UdpLogger::new()
+    ///     .with_module_levels(vec![
+    ///         ("chatty_dependency".to_string(), LevelFilter::Warn),
+    ///         ("my_crate".to_string(), LevelFilter::Debug),
+    ///     ])
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_module_level`]: #method.with_module_level
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_module_levels(mut self, module_levels: Vec<(String, LevelFilter)>) -> Self {
+        self.module_levels.clear();
+        module_levels.iter().fold(self, |logger, (target, level)| {
+            logger.with_module_level(target, *level)
+        })
+    }
+
+    /// Override the default source socket.
+    ///
+    /// This sets the default source socket, which otherwise defaults to "127.0.0.1:4000".
+    ///
+    /// # Examples
+    ///
+    /// Log from UDP port "127.0.0.1:4444"
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_source("127.0.0.1:4444")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_source(mut self, source: &str) -> Self {
+        match bind_udp(source) {
+            Ok(socket) => self.default_source = Arc::new(Socket::Udp(socket)),
+            Err(err) => self.defer_error(UdpLoggerError::Bind(err, source.to_string())),
+        }
+
+        self
+    }
+
+    /// Send from a port chosen by the OS, on "127.0.0.1", rather than the default source.
+    ///
+    /// This avoids the default source colliding with that of another logger, such as in a
+    /// test suite. The port is known once the logger is built, see [`source_addr`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// let logger = UdpLogger::new().with_ephemeral_source().build().unwrap();
+    /// println!("logging from {}", logger.source_addr().unwrap());
+    /// ```
+    ///
+    /// [`source_addr`]: #method.source_addr
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_ephemeral_source(self) -> Self {
+        self.with_source("127.0.0.1:0")
+    }
+
+    /// The local address of the default source socket, such as the port chosen for
+    /// [`with_ephemeral_source`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the default source isn't a UDP socket, or isn't bound, as the
+    /// default "127.0.0.1:4000" isn't until the logger is built.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// let logger = UdpLogger::new().with_source("127.0.0.1:4444").build().unwrap();
+    /// assert_eq!(logger.source_addr().unwrap().port(), 4444);
+    /// ```
+    ///
+    /// [`with_ephemeral_source`]: #method.with_ephemeral_source
+    pub fn source_addr(&self) -> std::io::Result<SocketAddr> {
+        self.default_source.local_addr()
+    }
+
+    /// The local address of the source socket records of the level are sent from, which is
+    /// the default source unless a level specific source admits the level, see
+    /// [`with_source_level`] and [`with_source_exact_level`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source isn't a UDP socket, or isn't bound, as for
+    /// [`source_addr`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Level, LevelFilter, UdpLogger};
+    ///
+    /// let logger = UdpLogger::new()
+    ///     .with_source_level("127.0.0.1:4001", LevelFilter::Warn)
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(logger.source_addr_for(Level::Error).unwrap().port(), 4001);
+    /// assert_eq!(logger.source_addr_for(Level::Info).unwrap().port(), 4000);
+    /// ```
+    ///
+    /// [`with_source_level`]: #method.with_source_level
+    /// [`with_source_exact_level`]: #method.with_source_exact_level
+    /// [`source_addr`]: #method.source_addr
+    pub fn source_addr_for(&self, level: Level) -> std::io::Result<SocketAddr> {
+        self.source_for(level).local_addr()
+    }
+
+    /// Provide a level specific source address.
+    ///
+    /// This sets the source address, for log messages matching the level. A log message is
+    /// sent from the source with the least verbose level which admits the message, or from
+    /// the default source, when no level admits it. When sources share a level, the first
+    /// provided is used.
+    ///
+    /// # Examples
+    ///
+    /// Log from UDP port "127.0.0.1:4001" all Info log messages. Trace and Debug messages
+    /// will log from the default source address. If with_source_level() for
+    /// Warn or Error aren't set, those levels will also send from "127.0.0.1:4001".
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    ///
+    /// UdpLogger::new()
+    ///     .with_source_level("127.0.0.1:4001", LevelFilter::Info)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_source_level(mut self, source: &str, level: LevelFilter) -> Self {
+        match bind_udp(source) {
+            Ok(socket) => self.sources.push((level, Arc::new(Socket::Udp(socket)))),
+            Err(err) => self.defer_error(UdpLoggerError::Bind(err, source.to_string())),
+        }
+
+        self
+    }
+
+    /// Provide several level specific source addresses, as [`with_source_level`] does for
+    /// each, such as from a configuration file.
+    ///
+    /// The sources are appended to those already provided. Each is bound, and the first which
+    /// can't be is returned as an error by [`build`], or [`init`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    ///
+    /// UdpLogger::new()
+    ///     .with_sources(vec![
+    ///         (LevelFilter::Info, "127.0.0.1:4001"),
+    ///         (LevelFilter::Error, "127.0.0.1:4002"),
+    ///     ])
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_source_level`]: #method.with_source_level
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_sources(self, sources: Vec<(LevelFilter, &str)>) -> Self {
+        sources.into_iter().fold(self, |logger, (level, source)| {
+            logger.with_source_level(source, level)
+        })
+    }
+
+    /// Connect the default source socket to the default destination, so that payloads are
+    /// sent without the destination address, which is faster, and a destination which is
+    /// unreachable is reported to the error handler, see [`with_error_handler`].
+    ///
+    /// The destination is resolved once, when the logger is built. As a connected socket can
+    /// only send to its destination, the socket isn't connected when records are routed to
+    /// other destinations, such as by [`with_destination_level`], and is sent from as usual.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_destination("127.0.0.1:4040")
+    ///     .with_connected_socket()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_error_handler`]: #method.with_error_handler
+    /// [`with_destination_level`]: #method.with_destination_level
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_connected_socket(mut self) -> Self {
+        self.connected_socket = true;
+
+        self
+    }
+
+    /// Override the default destination address.
+    ///
+    /// This sets the default destination address, which otherwise defaults to "127.0.0.1:4010".
+    ///
+    /// # Examples
+    ///
+    /// Log to UDP port "127.0.0.1:4040"
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_destination("127.0.0.1:4040")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_destination(mut self, destination: &str) -> Self {
+        self.default_destination = destination.to_string();
+
+        self
+    }
+
+    /// Provide a level specific destination address.
+    ///
+    /// This sets the destination address, for log messages matching the level. A log message
+    /// is sent to the destination with the least verbose level which admits the message, or
+    /// to the default destination, when no level admits it. When destinations share a level,
+    /// the first provided is used.
+    ///
+    /// # Examples
+    ///
+    /// Log to UDP port "127.0.0.1:4040" all Info log messages. Trace and Debug messages
+    /// will send to the default destination address. If with_destination_level() for
+    /// Warn or Error aren't set, those levels will also send to "127.0.0.1:4040".
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    /// UdpLogger::new()
+    ///     .with_destination_level("127.0.0.1:4040", LevelFilter::Info)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_destination_level(mut self, destination: &str, level: LevelFilter) -> Self {
+        self.destinations.push((level, destination.to_string()));
+
+        self
+    }
+
+    /// Provide several level specific destination addresses, as [`with_destination_level`]
+    /// does for each, such as from a configuration file.
+    ///
+    /// The destinations are appended to those already provided.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    /// use log::LevelFilter;
+    ///
+    /// UdpLogger::new()
+    ///     .with_destinations(vec![
+    ///         (LevelFilter::Info, "127.0.0.1:4040".to_string()),
+    ///         (LevelFilter::Error, "127.0.0.1:4041".to_string()),
+    ///     ])
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_destination_level`]: #method.with_destination_level
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_destinations(mut self, destinations: Vec<(LevelFilter, String)>) -> Self {
+        self.destinations.extend(destinations);
+
+        self
+    }
+
+    /// Override the default source socket with a Unix domain datagram socket.
+    ///
+    /// This binds the default source to the path, replacing the default UDP socket. The
+    /// destinations of a Unix domain source are paths, see [`with_unix_destination`].
+    ///
+    /// [`with_unix_destination`]: #method.with_unix_destination
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_unix_source("/tmp/my_app.log.sock")
+    ///     .with_unix_destination("/run/collector.sock")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[cfg(unix)]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_unix_source(mut self, path: &str) -> Self {
+        let socket = UnixDatagram::bind(path).and_then(|socket| {
+            socket.set_nonblocking(true)?;
+            Ok(socket)
+        });
+        match socket {
+            Ok(socket) => self.default_source = Arc::new(Socket::Unix(socket)),
+            Err(err) => self.defer_error(UdpLoggerError::Bind(err, path.to_string())),
+        }
+
+        self
+    }
+
+    /// Override the default destination address with the path of a Unix domain datagram
+    /// socket.
+    ///
+    /// Unless the default source is already a Unix domain socket, see [`with_unix_source`],
+    /// it is replaced with an unbound Unix domain socket.
+    ///
+    /// [`with_unix_source`]: #method.with_unix_source
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_unix_destination("/run/collector.sock")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[cfg(unix)]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_unix_destination(mut self, path: &str) -> Self {
+        if let Socket::Udp(_socket) = &*self.default_source {
+            let socket = UnixDatagram::unbound().and_then(|socket| {
+                socket.set_nonblocking(true)?;
+                Ok(socket)
+            });
+            match socket {
+                Ok(socket) => self.default_source = Arc::new(Socket::Unix(socket)),
+                Err(err) => self.defer_error(UdpLoggerError::Bind(err, UNBOUND_UNIX.to_string())),
+            }
+        }
+        self.default_destination = path.to_string();
+
+        self
+    }
+
+    /// Send to a TCP destination, rather than a UDP destination.
+    ///
+    /// This changes delivery from fire-and-forget to reliable, and blocking. Each payload is
+    /// sent as a frame, a u32 length followed by the payload, over a connection to the
+    /// destination, which replaces the default source and destination. The connection is
+    /// established when the first payload is sent, and re-established, with a backoff of up
+    /// to 30 seconds, when it fails. Connecting, and writing a frame, time out after 500ms,
+    /// which fails the connection, so that logging isn't stalled by an unresponsive
+    /// collector. Meanwhile, up to 1MiB of frames are held, to be sent once the connection is
+    /// re-established, further payloads are dropped. Frames are counted as sent by
+    /// [`stats`] once they're written to the connection.
+    ///
+    /// [`stats`]: #method.stats
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_tcp_destination("10.0.0.5:5140")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_tcp_destination(mut self, destination: &str) -> Self {
+        self.default_source = Arc::new(Socket::Transport(Box::new(TcpTransport::new(
+            self.counters.clone(),
+        ))));
+        self.default_destination = destination.to_string();
+
+        self
+    }
+
+    /// Encrypt payloads with DTLS, sending each as a record over a session with its
+    /// destination.
+    ///
+    /// This replaces the default source, the destinations are otherwise unchanged, and must
+    /// be DTLS collectors. A session is established, blocking, when the first payload is sent
+    /// to a destination, and re-established when a send fails, or the session reaches its
+    /// lifetime, see [`DtlsConfig`]. A failed handshake is retried with a backoff of up to 60
+    /// seconds, meanwhile payloads are dropped. Records add up to about 64 bytes to a
+    /// payload, which [`with_max_datagram_size`] should leave room for. Requires the `dtls`
+    /// feature.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use openssl::ssl::{SslConnector, SslMethod};
+    /// use udp_logger_rs::{DtlsConfig, UdpLogger};
+    ///
+    /// let mut connector = SslConnector::builder(SslMethod::dtls()).unwrap();
+    /// connector.set_ca_file("collector-ca.pem").unwrap();
+    /// UdpLogger::new()
+    ///     .with_destination("10.0.0.5:4433")
+    ///     .with_max_datagram_size(1400)
+    ///     .with_dtls(DtlsConfig::new(connector.build(), "collector.example.com"))
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`DtlsConfig`]: struct.DtlsConfig.html
+    /// [`with_max_datagram_size`]: #method.with_max_datagram_size
+    #[cfg(feature = "dtls")]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_dtls(mut self, config: DtlsConfig) -> Self {
+        self.default_source = Arc::new(Socket::Transport(Box::new(DtlsTransport::new(config))));
+
+        self
+    }
+
+    /// Keep a fraction of the records of a level, which otherwise are all kept.
+    ///
+    /// Sampling is deterministic, keeping every nth record, starting with the first, where
+    /// n is the reciprocal of the fraction, rounded. Records which aren't kept are discarded,
+    /// without being counted as dropped.
+    ///
+    /// A fraction which isn't greater than 0, and no greater than 1, such as NaN, is returned
+    /// as a [`UdpLoggerError::Sampling`] by [`build`], or [`init`].
+    ///
+    /// # Examples
+    ///
+    /// Keep 1 in 100 Info records.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Level, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_sampling(Level::Info, 0.01)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`UdpLoggerError::Sampling`]: enum.UdpLoggerError.html#variant.Sampling
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_sampling(mut self, level: Level, fraction: f64) -> Self {
+        // also rejects NaN, which compares false
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            self.defer_error(UdpLoggerError::Sampling(fraction));
+            return self;
+        }
+        // the reciprocal is at least 1, and saturates for the smallest fractions
+        self.sampling[level as usize - 1] = Some(Sampler {
+            every: (1.0 / fraction).round() as u64,
+            count: AtomicU64::new(0),
+        });
+
+        self
+    }
+
+    /// Limit the rate at which records of a level are logged.
+    ///
+    /// Up to the rate of records per second are logged, with bursts of up to a second's
+    /// worth of records, further records are suppressed, and counted as dropped. Once records
+    /// are logged again, a record of the same level, "N messages suppressed", precedes them.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Level, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_rate_limit(Level::Debug, 1000)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_rate_limit(mut self, level: Level, per_second: u32) -> Self {
+        self.rate_limits[level as usize - 1] = Some(RateLimiter::new(per_second));
+
+        self
+    }
+
+    /// Re-resolve destination hostnames at an interval.
+    ///
+    /// Destinations which are hostnames, rather than addresses, are resolved when they're
+    /// first sent to, and the address is cached, rather than resolved for each log message.
+    /// Without an interval, the address is cached indefinitely. With an interval, the
+    /// hostname is re-resolved once the interval has elapsed, following DNS changes. If
+    /// re-resolution fails, the error is reported to the error handler and the previous
+    /// address remains in use. When a hostname resolves to several addresses, the first with
+    /// the same address family as the source socket is used.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::time::Duration;
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_destination("logs.internal:514")
+    ///     .with_resolve_interval(Duration::from_secs(60))
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_resolve_interval(mut self, interval: Duration) -> Self {
+        self.resolve_interval = Some(interval);
+
+        self
+    }
+
+    /// Provide a target specific destination address.
+    ///
+    /// This sets the destination address, for log messages whose target is the provided
+    /// target, or one of its sub-modules, regardless of their level. Target destinations are
+    /// matched before level specific destinations, with the most specific target taken.
+    ///
+    /// # Examples
+    ///
+    /// Log the "audit" target to a dedicated collector.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_target_destination("audit", "10.0.0.5:5140")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_target_destination(mut self, target: &str, destination: &str) -> Self {
+        self.target_destinations
+            .push((target.to_string(), destination.to_string()));
+
+        self
+    }
+
+    /// Provide a source address, for log messages of exactly the level.
+    ///
+    /// Exact level sources take precedence over level specific sources, see
+    /// [`with_source_level`].
+    ///
+    /// [`with_source_level`]: #method.with_source_level
+    ///
+    /// # Examples
+    ///
+    /// Log from UDP port "127.0.0.1:4001" only Warn log messages.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Level, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_source_exact_level("127.0.0.1:4001", Level::Warn)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_source_exact_level(mut self, source: &str, level: Level) -> Self {
+        match bind_udp(source) {
+            Ok(socket) => self
+                .exact_sources
+                .push((level, Arc::new(Socket::Udp(socket)))),
+            Err(err) => self.defer_error(UdpLoggerError::Bind(err, source.to_string())),
+        }
+
+        self
+    }
+
+    /// Provide a destination address, for log messages of exactly the level.
+    ///
+    /// Target specific destinations take precedence over exact level destinations, which
+    /// take precedence over level specific destinations, see [`with_target_destination`] and
+    /// [`with_destination_level`].
+    ///
+    /// [`with_target_destination`]: #method.with_target_destination
+    /// [`with_destination_level`]: #method.with_destination_level
+    ///
+    /// # Examples
+    ///
+    /// Send only Error log messages to a pager integration.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Level, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_destination_exact_level("10.0.0.9:4010", Level::Error)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_destination_exact_level(mut self, destination: &str, level: Level) -> Self {
+        self.exact_destinations
+            .push((level, destination.to_string()));
+
+        self
+    }
+
+    /// Provide an additional destination address, which receives every log message.
+    ///
+    /// Log messages are sent to each additional destination, as well as to the destination
+    /// selected by level. A failure to send to one destination doesn't prevent sending to
+    /// the others.
+    ///
+    /// # Examples
+    ///
+    /// Log to a primary and a backup collector.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_destination("10.0.0.1:4010")
+    ///     .with_additional_destination("10.0.0.2:4010")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_additional_destination(mut self, destination: &str) -> Self {
+        self.additional_destinations.push(destination.to_string());
+
+        self
+    }
+
+    /// Send to a multicast group.
+    ///
+    /// This sets the default destination address to the multicast group, and configures
+    /// the source sockets to send to it through the provided interface, with loopback
+    /// enabled, so that receivers on this host also see each log message. For an IPv4
+    /// group, the interface is the address of the outgoing interface, "0.0.0.0" lets the
+    /// OS choose. For an IPv6 group, the interface is the interface index, "0" lets the
+    /// OS choose, and the sources must be IPv6 sockets. In either case, the source should
+    /// be bound to the interface, or an unspecified address, rather than the default
+    /// loopback address.
+    ///
+    /// A malformed group, or one which isn't a multicast address, is returned as a
+    /// [`UdpLoggerError::Resolve`] by [`build`], or [`init`], and a malformed interface as a
+    /// [`UdpLoggerError::Bind`].
+    ///
+    /// # Examples
+    ///
+    /// Log to the group "239.255.0.1:4010", from any interface.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_source("0.0.0.0:4000")
+    ///     .with_multicast("239.255.0.1:4010", "0.0.0.0")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`UdpLoggerError::Resolve`]: enum.UdpLoggerError.html#variant.Resolve
+    /// [`UdpLoggerError::Bind`]: enum.UdpLoggerError.html#variant.Bind
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_multicast(mut self, group: &str, interface: &str) -> Self {
+        let invalid =
+            |message: &str| std::io::Error::new(std::io::ErrorKind::InvalidInput, message);
+        let group_addr = match group.parse::<SocketAddr>() {
+            Ok(group_addr) if group_addr.ip().is_multicast() => group_addr,
+            Ok(_group_addr) => {
+                let err = invalid("not a multicast group");
+                self.defer_error(UdpLoggerError::Resolve(err, group.to_string()));
+                return self;
+            }
+            Err(_err) => {
+                let err = invalid("invalid multicast group");
+                self.defer_error(UdpLoggerError::Resolve(err, group.to_string()));
+                return self;
+            }
+        };
+        let parsed = match group_addr {
+            SocketAddr::V4(_) => interface.parse().map(MulticastInterface::V4).ok(),
+            SocketAddr::V6(_) => interface.parse().map(MulticastInterface::V6).ok(),
+        };
+        match parsed {
+            Some(parsed) => {
+                self.multicast_interface = Some(parsed);
+                self.default_destination = group.to_string();
+            }
+            None => {
+                let err = invalid("invalid multicast interface");
+                self.defer_error(UdpLoggerError::Bind(err, interface.to_string()));
+            }
+        }
+
+        self
+    }
+
+    /// Enable broadcast sends.
+    ///
+    /// This enables broadcast on the source sockets, so that log messages can be sent to an
+    /// IPv4 broadcast destination, either the limited broadcast address 255.255.255.255, or
+    /// the directed broadcast address of a network. IPv6 has no broadcast, so [`build`], or
+    /// [`init`], return [`UdpLoggerError::NotBroadcast`], naming the destination, if any is
+    /// an IPv6 address. Whether an IPv4 destination is a directed broadcast address depends
+    /// on the netmask of the interface, so it isn't validated, though sending to a broadcast
+    /// address without this fails.
+    ///
+    /// # Examples
+    ///
+    /// Log to any listener on port 4010 of the LAN.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_source("0.0.0.0:4000")
+    ///     .with_destination("255.255.255.255:4010")
+    ///     .with_broadcast()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`UdpLoggerError::NotBroadcast`]: enum.UdpLoggerError.html#variant.NotBroadcast
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_broadcast(mut self) -> Self {
+        self.broadcast = true;
+
+        self
+    }
+
+    /// Set the send buffer size (SO_SNDBUF) of the source sockets.
+    ///
+    /// A larger send buffer reduces the payloads which can't be sent without blocking during
+    /// bursts of logging. The OS treats the size as a request, it may round it, double it to
+    /// allow for bookkeeping (as Linux does), or clamp it to a system minimum or maximum (such
+    /// as Linux's net.core.wmem_max).
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_send_buffer_size(1024 * 1024)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_send_buffer_size(mut self, bytes: usize) -> Self {
+        self.send_buffer_size = Some(bytes);
+
+        self
+    }
+
+    /// Retry a send which would block, yielding the thread between attempts, before the
+    /// payload is held for [`flush`].
+    ///
+    /// Retrying rides out brief bursts which fill the send buffer, at the cost of latency
+    /// on the logging thread. Without retries, which is the default, the payload is held
+    /// immediately.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_send_retries(8)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`flush`]: https://docs.rs/log/0.4/log/trait.Log.html#tymethod.flush
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_send_retries(mut self, retries: u32) -> Self {
+        self.send_retries = retries;
+
+        self
+    }
+
+    /// Use blocking sockets, rather than non-blocking sockets, so that sends wait for space
+    /// in the send buffer instead of being held for [`flush`].
+    ///
+    /// Blocking favors delivery over latency, a burst of logging can stall the logging thread
+    /// until the OS has sent earlier payloads. It suits low-volume logging, where every record
+    /// matters.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new().with_blocking().init().unwrap();
+    /// ```
+    ///
+    /// [`flush`]: https://docs.rs/log/0.4/log/trait.Log.html#tymethod.flush
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_blocking(mut self) -> Self {
+        self.blocking = true;
+
+        self
+    }
+
+    /// Set the IP time-to-live of the source sockets.
+    ///
+    /// This bounds the number of router hops a log message can take, which otherwise is
+    /// the OS default.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_source("0.0.0.0:4000")
+    ///     .with_destination("10.1.2.3:4010")
+    ///     .with_ttl(8)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_ttl(mut self, ttl: u32) -> Self {
+        self.ttl = Some(ttl);
+
+        self
+    }
+
+    /// Set the multicast time-to-live (hop limit, for IPv6) of the source sockets.
+    ///
+    /// This bounds the number of router hops a multicast log message can take, which
+    /// otherwise is 1, keeping it within the local network.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_source("0.0.0.0:4000")
+    ///     .with_multicast("239.255.0.1:4010", "0.0.0.0")
+    ///     .with_multicast_ttl(4)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_multicast_ttl(mut self, ttl: u32) -> Self {
+        self.multicast_ttl = Some(ttl);
+
+        self
+    }
+
+    /// Set the handling of payloads which are too large for a UDP datagram, which otherwise
+    /// defaults to OversizePolicy::Drop.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{OversizePolicy, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_oversize_policy(OversizePolicy::Truncate)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_oversize_policy(mut self, policy: OversizePolicy) -> Self {
+        self.oversize_policy = policy;
+
+        self
+    }
+
+    /// Set the size of the datagrams, including their header, which payloads are split into
+    /// with OversizePolicy::Fragment. This otherwise defaults to the UDP datagram limit of
+    /// 65507 bytes, and can be reduced to keep the datagrams under the path MTU.
+    ///
+    /// A size which doesn't exceed the fragment header is returned as a
+    /// [`UdpLoggerError::FragmentSize`] by [`build`], or [`init`].
+    ///
+    /// # Examples
+    ///
+    /// Split payloads to fit in an Ethernet frame.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{OversizePolicy, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_oversize_policy(OversizePolicy::Fragment)
+    ///     .with_fragment_size(1472)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`UdpLoggerError::FragmentSize`]: enum.UdpLoggerError.html#variant.FragmentSize
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_fragment_size(mut self, bytes: usize) -> Self {
+        if bytes <= FRAGMENT_HEADER_SIZE {
+            self.defer_error(UdpLoggerError::FragmentSize(bytes));
+            return self;
+        }
+        self.fragment_size = bytes.min(MAX_DATAGRAM_SIZE);
+
+        self
+    }
+
+    /// Set the largest datagram which is sent, which otherwise defaults to the UDP datagram
+    /// limit of 65507 bytes.
+    ///
+    /// Reducing the size to fit the path MTU, less the IP and UDP headers, avoids IP
+    /// fragmentation. The size applies to the encoded payload, including any checksum,
+    /// and larger payloads are handled according to the [`OversizePolicy`]. Fragments are
+    /// no larger than this size, or the fragment size, whichever is smaller.
+    ///
+    /// A size of less than 64 bytes is returned as a [`UdpLoggerError::DatagramSize`] by
+    /// [`build`], or [`init`].
+    ///
+    /// # Examples
+    ///
+    /// Avoid IP fragmentation on a path with a 1400 byte MTU.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{OversizePolicy, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_max_datagram_size(1400 - 28)
+    ///     .with_oversize_policy(OversizePolicy::Truncate)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`OversizePolicy`]: enum.OversizePolicy.html
+    /// [`UdpLoggerError::DatagramSize`]: enum.UdpLoggerError.html#variant.DatagramSize
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_max_datagram_size(mut self, bytes: usize) -> Self {
+        if bytes < MIN_DATAGRAM_SIZE {
+            self.defer_error(UdpLoggerError::DatagramSize(bytes));
+            return self;
+        }
+        self.max_datagram_size = bytes.min(MAX_DATAGRAM_SIZE);
+
+        self
+    }
+
+    /// Set the wire format for logging.
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_wire_fmt(mut self, wire_fmt: WireFmt) -> Self {
+        self.wire_fmt = wire_fmt;
+
+        self
+    }
+
+    /// Provide a formatter, which produces the payload of each record in place of the wire
+    /// format.
+    ///
+    /// The formatter is given the record and its kv pairs, which include the global and
+    /// context fields. Payloads which are too large are not truncated, as the format is
+    /// unknown; they are fragmented or dropped according to the oversize policy.
+    ///
+    /// The formatter runs on the logging thread, for every record which is logged, so it
+    /// should be quick.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_formatter(Box::new(|record, kvs| {
+    ///         let mut line = format!("{}|{}", record.level(), record.args());
+    ///         for (key, value) in kvs {
+    ///             line.push_str(&format!("|{}:{}", key, value));
+    ///         }
+    ///         line.into_bytes()
+    ///     }))
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_formatter(mut self, formatter: Box<FormatFn>) -> Self {
+        self.formatter = Some(CustomFormatter(formatter));
+
+        self
+    }
+
+    /// Override the codes which encode the level in ByteBuffer payloads.
+    ///
+    /// The codes are for Error, Warn, Info, Debug, and Trace, in that order, and otherwise
+    /// default to 1, 2, 3, 4, and 5. Codes may repeat, though the levels sharing a code
+    /// can't be told apart by a receiver, see [`decode_bytebuffer_with_codes`].
+    ///
+    /// # Examples
+    ///
+    /// Encode levels as syslog severities.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{UdpLogger, WireFmt};
+    ///
+    /// UdpLogger::new()
+    ///     .with_wire_fmt(WireFmt::ByteBuffer)
+    ///     .with_level_codes([3, 4, 6, 7, 7])
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`decode_bytebuffer_with_codes`]: decode/fn.decode_bytebuffer_with_codes.html
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_level_codes(mut self, codes: [u8; 5]) -> Self {
+        self.level_codes = codes;
+
+        self
+    }
+
+    /// Color the level of Uncompressed payloads with ANSI escape codes, for reading them in a
+    /// terminal.
+    ///
+    /// Error is red, Warn is yellow, Info is green, Debug is cyan, and Trace is gray. The
+    /// colors can be changed with [`with_level_colors`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new().with_colors().init().unwrap();
+    /// ```
+    ///
+    /// [`with_level_colors`]: #method.with_level_colors
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_colors(self) -> Self {
+        self.with_level_colors(DEFAULT_LEVEL_COLORS)
+    }
+
+    /// Color the level of Uncompressed payloads with ANSI escape codes, as [`with_colors`],
+    /// with the provided colors.
+    ///
+    /// The colors are ANSI SGR parameters, such as "31" for red or "1;31" for bold red, for
+    /// Error, Warn, Info, Debug, and Trace, in that order.
+    ///
+    /// # Examples
+    ///
+    /// Make errors stand out.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_level_colors(["1;41", "33", "0", "0", "0"])
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_colors`]: #method.with_colors
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_level_colors(mut self, colors: [&'static str; 5]) -> Self {
+        self.level_colors = Some(colors);
+
+        self
+    }
+
+    /// Redact the values of kv pairs with the keys, so that they never leave the process.
+    ///
+    /// This applies to the kv pairs of records, and global and context fields, which are
+    /// sent with the value "[REDACTED]", or dropped, according to the [`Redaction`] set by
+    /// [`with_redaction`]. Keys are matched exactly, unless the redaction ignores case.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Redaction, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_redacted_keys(&["password", "token"])
+    ///     .with_redaction(Redaction {
+    ///         drop: false,
+    ///         ignore_case: true,
+    ///     })
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`Redaction`]: struct.Redaction.html
+    /// [`with_redaction`]: #method.with_redaction
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_redacted_keys(mut self, keys: &[&str]) -> Self {
+        self.redacted_keys
+            .extend(keys.iter().map(|key| key.to_string()));
+
+        self
+    }
+
+    /// Set how the kv pairs of redacted keys are handled, see [`with_redacted_keys`].
+    ///
+    /// [`with_redacted_keys`]: #method.with_redacted_keys
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_redaction(mut self, redaction: Redaction) -> Self {
+        self.redaction = redaction;
+
+        self
+    }
+
+    /// Limit the kv pairs of a record, including global and context fields, to a number,
+    /// which otherwise is unlimited.
+    ///
+    /// Once the limit is reached, further pairs are omitted, and counted. Uncompressed payloads
+    /// end with a marker, such as " ...(+12 more)", while the other wire formats end with a
+    /// `kvs_omitted` pair of the count, so that payloads remain well formed.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_max_kv_pairs(32)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_max_kv_pairs(mut self, count: usize) -> Self {
+        self.max_kv_pairs = Some(count);
+
+        self
+    }
+
+    /// Sort kv pairs by key, rather than formatting them in the order they are visited.
+    ///
+    /// Sources such as a HashMap visit their pairs in an arbitrary order, sorting them makes
+    /// payloads reproducible, at the cost of collecting the pairs for each record.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_sorted_kv()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_sorted_kv(mut self) -> Self {
+        self.sorted_kv = true;
+
+        self
+    }
+
+    /// Add a field to every record, alongside its kv pairs.
+    ///
+    /// Global fields precede the kv pairs of a record, unless sorted by [`with_sorted_kv`],
+    /// and are omitted from records which have a kv pair with the same key.
+    ///
+    /// [`with_sorted_kv`]: #method.with_sorted_kv
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_global_field("service", "checkout")
+    ///     .with_global_field("env", "prod")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_global_field(mut self, key: &str, value: &str) -> Self {
+        self.global_fields
+            .push((key.to_string(), value.to_string()));
+
+        self
+    }
+
+    /// Set the separators of kv pairs appended to Uncompressed payloads.
+    ///
+    /// Values containing the `kv_sep` are quoted.
+    ///
+    /// # Examples
+    ///
+    /// Append pairs as tab separated "key:value".
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{KvStyle, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_kv_style(KvStyle { pair_sep: "\t", kv_sep: ":" })
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_kv_style(mut self, kv_style: KvStyle) -> Self {
+        self.kv_style = kv_style;
+
+        self
+    }
+
+    /// Set the case and padding of the level in Uncompressed payloads.
+    ///
+    /// # Examples
+    ///
+    /// Format the level in lowercase, without padding.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{LevelStyle, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_level_style(LevelStyle { lowercase: true, width: 0 })
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_level_style(mut self, level_style: LevelStyle) -> Self {
+        self.level_style = level_style;
+
+        self
+    }
+
+    /// End Uncompressed payloads with a line terminator, which otherwise defaults to
+    /// Terminator::None.
+    ///
+    /// With a terminator, payloads can be appended to a file, or read from a stream, as
+    /// lines. A truncated payload keeps its terminator.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Terminator, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_line_terminator(Terminator::Lf)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_line_terminator(mut self, terminator: Terminator) -> Self {
+        self.terminator = terminator;
+
+        self
+    }
+
+    /// Append a CRC32 checksum to ByteBuffer payloads.
+    ///
+    /// When enabled, a u32 [`crc32`] of the encoded payload follows the payload, allowing
+    /// receivers to discard damaged datagrams. Receivers must be configured to expect it.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{UdpLogger, WireFmt};
+    ///
+    /// UdpLogger::new()
+    ///     .with_wire_fmt(WireFmt::ByteBuffer)
+    ///     .with_checksum(true)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`crc32`]: fn.crc32.html
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_checksum(mut self, checksum: bool) -> Self {
+        self.checksum = checksum;
+
+        self
+    }
+
+    /// Number records, allowing collectors to detect loss and reordering.
+    ///
+    /// Each record is numbered by a u64 counter of the logger, starting at 1. The number is
+    /// the first `seq` kv pair of the ByteBuffer and Protobuf wire formats, a `seq` field,
+    /// following the level, of Logfmt, and prefixes Uncompressed, as `#42 `. With
+    /// Syslog5424, it's the `sequenceId` of a `meta` STRUCTURED-DATA element, which wraps
+    /// to 1 after 2147483647. A record sent to several destinations, or fragmented, has one
+    /// number.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{UdpLogger, WireFmt};
+    ///
+    /// UdpLogger::new()
+    ///     .with_wire_fmt(WireFmt::ByteBuffer)
+    ///     .with_sequence_numbers()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_sequence_numbers(mut self) -> Self {
+        self.sequence = Some(AtomicU64::new(0));
+
+        self
+    }
+
+    /// Sign ByteBuffer and Protobuf payloads with an HMAC-SHA256 tag, allowing receivers
+    /// sharing the key to authenticate them.
+    ///
+    /// The tag, of the encoded payload, follows the payload, and is truncated to the length
+    /// set by [`with_hmac_len`], otherwise it's the full 32 bytes. It's computed before a
+    /// payload is fragmented, so receivers verify reassembled payloads. Payloads aren't
+    /// encrypted. Requires the `signing` feature.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{UdpLogger, WireFmt};
+    ///
+    /// UdpLogger::new()
+    ///     .with_wire_fmt(WireFmt::ByteBuffer)
+    ///     .with_hmac_key(b"shared secret")
+    ///     .with_hmac_len(16)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// A receiver verifies a datagram, using the hmac and sha2 crates.
+    ///
+    /// ```no_run
+    /// use hmac::{Hmac, Mac};
+    ///
+    /// fn verify(datagram: &[u8]) -> Option<&[u8]> {
+    ///     let (payload, tag) = datagram.split_at(datagram.len().checked_sub(16)?);
+    ///     let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"shared secret").ok()?;
+    ///     mac.update(payload);
+    ///     mac.verify_truncated_left(tag).ok()?;
+    ///     Some(payload)
+    /// }
+    /// ```
+    ///
+    /// [`with_hmac_len`]: #method.with_hmac_len
+    #[cfg(feature = "signing")]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_hmac_key(mut self, key: &[u8]) -> Self {
+        use hmac::Mac;
+        let mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+        self.hmac = Some(Signer(mac));
+
+        self
+    }
+
+    /// Truncate the HMAC-SHA256 tag of signed payloads, see [`with_hmac_key`], to a length
+    /// from 16 to 32 bytes.
+    ///
+    /// A length outside of 16 to 32 bytes is returned as a [`UdpLoggerError::HmacLen`] by
+    /// [`build`], or [`init`].
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`UdpLoggerError::HmacLen`]: enum.UdpLoggerError.html#variant.HmacLen
+    /// [`with_hmac_key`]: #method.with_hmac_key
+    #[cfg(feature = "signing")]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_hmac_len(mut self, bytes: usize) -> Self {
+        if !(HMAC_MIN_LEN..=HMAC_LEN).contains(&bytes) {
+            self.defer_error(UdpLoggerError::HmacLen(bytes));
+            return self;
+        }
+        self.hmac_len = bytes;
+
+        self
+    }
+
+    /// Set the syslog facility of Syslog5424 payloads, which otherwise defaults to User.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Facility, UdpLogger, WireFmt};
+    ///
+    /// UdpLogger::new()
+    ///     .with_wire_fmt(WireFmt::Syslog5424)
+    ///     .with_facility(Facility::Local0)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[cfg(feature = "syslog")]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_facility(mut self, facility: Facility) -> Self {
+        self.facility = facility;
+
+        self
+    }
+
+    /// Identify the sending host, which is otherwise omitted from payloads.
+    ///
+    /// The hostname follows the level of Uncompressed and Logfmt payloads, is the first kv pair
+    /// of ByteBuffer payloads, and is the HOSTNAME of Syslog5424 payloads, which otherwise is
+    /// the nil value, "-". Use [`with_local_hostname`] to detect the hostname of this machine.
+    ///
+    /// [`with_local_hostname`]: #method.with_local_hostname
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{UdpLogger, WireFmt};
+    ///
+    /// UdpLogger::new()
+    ///     .with_wire_fmt(WireFmt::Logfmt)
+    ///     .with_hostname("web-1.example.com")
+    ///     .with_app_name("my_app")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_hostname(mut self, hostname: &str) -> Self {
+        self.hostname = Some(hostname.to_string());
+
+        self
+    }
+
+    /// Identify the sending host by the hostname of this machine, see [`with_hostname`].
+    ///
+    /// The hostname is detected once, rather than each time a record is logged.
+    ///
+    /// [`with_hostname`]: #method.with_hostname
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_local_hostname()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_local_hostname(mut self) -> Self {
+        self.hostname = Some(gethostname::gethostname().to_string_lossy().into_owned());
+
+        self
+    }
+
+    /// Identify the sending application, which is otherwise omitted from payloads.
+    ///
+    /// The app name follows the level, and hostname, of Uncompressed and Logfmt payloads, is a
+    /// kv pair of ByteBuffer payloads, and is the APP-NAME of Syslog5424 payloads, which
+    /// otherwise is the nil value, "-".
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_app_name(mut self, app_name: &str) -> Self {
+        self.app_name = Some(app_name.to_string());
+
+        self
+    }
+
+    /// Identify the sending process by its id, which is otherwise omitted from payloads.
+    ///
+    /// The process id is captured once, and included alongside the app name. Syslog5424
+    /// payloads always include the process id, as their PROCID.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_app_name("svc")
+    ///     .with_pid()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_pid(mut self) -> Self {
+        self.pid = Some(std::process::id());
+
+        self
+    }
+
+    /// Identify the thread which logged each record, which is otherwise omitted from payloads.
+    ///
+    /// The thread is identified by its name, or its id when it is unnamed. As the current
+    /// thread has to be looked up for each record, this is disabled by default.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_thread_names()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_thread_names(mut self) -> Self {
+        self.thread_names = true;
+
+        self
+    }
+
+    /// Copy each record to stderr, as well as sending it, for local debugging.
+    ///
+    /// Records are written as `Uncompressed` lines, whatever the wire format, and only once
+    /// they have passed the level filters. The copy is made on the logging thread.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_stderr_tee()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_stderr_tee(mut self) -> Self {
+        self.tee = Some(Tee::Stderr);
+
+        self
+    }
+
+    /// Copy each record to stdout, as well as sending it, for local debugging.
+    ///
+    /// This is the same as [`with_stderr_tee`], writing to stdout instead.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_stdout_tee()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_stderr_tee`]: #method.with_stderr_tee
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_stdout_tee(mut self) -> Self {
+        self.tee = Some(Tee::Stdout);
+
+        self
+    }
+
+    /// Identify the file and line which logged each record, which are otherwise omitted from
+    /// payloads, to keep them small.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_source_location()
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_source_location(mut self) -> Self {
+        self.source_location = true;
+
+        self
+    }
+
+    /// Override the timestamp format.
+    ///
+    /// This sets the format of the timestamp, which prefixes Uncompressed payloads. The format
+    /// is a chrono strftime string, and otherwise defaults to "%Y-%m-%d %H:%M:%S%.3f".
+    ///
+    /// # Examples
+    ///
+    /// Log RFC3339 timestamps, with microsecond precision and a timezone offset.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_timestamp_format("%Y-%m-%dT%H:%M:%S%.6f%z")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_timestamp_format(mut self, format: &str) -> Self {
+        self.timestamp_format = format.to_string();
+
+        self
+    }
+
+    /// Set the precision of timestamps, which otherwise is milliseconds.
+    ///
+    /// This changes the fraction of seconds of the timestamp format, "%.3f", "%.6f" or
+    /// "%.9f", to that of the precision, regardless of the order of this and
+    /// [`with_timestamp_format`], and the timestamp of ByteBuffer payloads to microseconds,
+    /// or nanoseconds, since the Unix epoch. Receivers must be configured to expect it.
+    /// Protobuf timestamps remain milliseconds.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{TimestampPrecision, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_timestamp_precision(TimestampPrecision::Micros)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`with_timestamp_format`]: #method.with_timestamp_format
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_timestamp_precision(mut self, precision: TimestampPrecision) -> Self {
+        self.timestamp_precision = Some(precision);
+
+        self
+    }
+
+    /// Omit the timestamp from payloads, for collectors which timestamp payloads as they
+    /// arrive.
+    ///
+    /// Uncompressed payloads begin with the level, rather than the timestamp, and Logfmt
+    /// payloads omit the `ts` field. ByteBuffer payloads omit the 8 byte timestamp, and have
+    /// the version [`BYTE_BUFFER_VERSION_NO_TIMESTAMP`], so that receivers can tell the
+    /// layouts apart. The TIMESTAMP of Syslog5424 payloads is "-", and the timestamp of
+    /// Protobuf payloads is omitted, reading as 0.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new().without_timestamp().init().unwrap();
+    /// ```
+    ///
+    /// [`BYTE_BUFFER_VERSION_NO_TIMESTAMP`]: constant.BYTE_BUFFER_VERSION_NO_TIMESTAMP.html
+    #[must_use = "You must call init() to begin logging"]
+    pub fn without_timestamp(mut self) -> Self {
+        self.timestamps = false;
+
+        self
+    }
+
+    /// Use local time, rather than UTC, for timestamps.
+    ///
+    /// This affects the formatted timestamp of the Uncompressed payloads. ByteBuffer payloads
+    /// carry milliseconds since the Unix epoch, which are the same in every timezone.
+    ///
+    /// # Examples
+    ///
+    /// Log local timestamps, including the local offset.
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new()
+    ///     .with_local_time()
+    ///     .with_timestamp_format("%Y-%m-%d %H:%M:%S%.3f%z")
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_local_time(self) -> Self {
+        self.with_clock(Arc::new(LocalClock))
+    }
+
+    /// Use the time elapsed since the logger was created, rather than the time of day, for
+    /// timestamps.
+    ///
+    /// This provides a [`RelativeClock`], so that intervals between records aren't
+    /// affected by changes to the system clock, and sets the timestamp format to "%s%.3f",
+    /// the elapsed seconds, such as "12.345". ByteBuffer and Protobuf payloads carry the
+    /// elapsed milliseconds. The format can be changed by [`with_timestamp_format`], and the
+    /// precision by [`with_timestamp_precision`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new().with_relative_time().init().unwrap();
+    /// ```
+    ///
+    /// [`RelativeClock`]: struct.RelativeClock.html
+    /// [`with_timestamp_format`]: #method.with_timestamp_format
+    /// [`with_timestamp_precision`]: #method.with_timestamp_precision
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_relative_time(self) -> Self {
+        self.with_clock(Arc::new(RelativeClock::new()))
+            .with_timestamp_format("%s%.3f")
+    }
+
+    /// Provide the transport used to send payloads, which otherwise is the default source
+    /// socket.
+    ///
+    /// The transport replaces the default source socket, payloads are still formatted,
+    /// and routed to destinations, as configured.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Transport, UdpLogger};
+    ///
+    /// #[derive(Debug)]
+    /// struct Discard;
+    /// impl Transport for Discard {
+    ///     fn send(&self, bytes: &[u8], _dest: &str) -> std::io::Result<usize> {
+    ///         Ok(bytes.len())
+    ///     }
+    /// }
+    ///
+    /// UdpLogger::new()
+    ///     .with_transport(Box::new(Discard))
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_transport(mut self, transport: Box<dyn Transport + Send + Sync>) -> Self {
+        self.default_source = Arc::new(Socket::Transport(transport));
+
+        self
+    }
+
+    /// Format records, without sending them.
+    ///
+    /// Records are filtered and formatted as usual, then discarded, which is useful for
+    /// measuring the cost of formatting, or for exercising logging without a network.
+    /// Destinations aren't resolved, and nothing is counted as sent or dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// UdpLogger::new().with_null_sink().init().unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_null_sink(mut self) -> Self {
+        self.null_sink = true;
+
+        self
+    }
+
+    /// Provide the clock used for timestamps, which otherwise defaults to [`UtcClock`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use std::sync::Arc;
+    /// use udp_logger_rs::{LocalClock, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_clock(Arc::new(LocalClock))
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`UtcClock`]: struct.UtcClock.html
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_clock(mut self, clock: Arc<dyn Clock + Send + Sync>) -> Self {
+        self.clock = clock;
+
+        self
+    }
+
+    /// Send from a background thread, so that logging doesn't wait on the network.
+    ///
+    /// Payloads are formatted by the logging thread, then queued for the background thread
+    /// to send. The queue holds up to queue_capacity payloads, once full, logging follows the
+    /// [`QueueFullPolicy`], which defaults to Block. The source sockets are blocking, as only
+    /// the background thread sends from them.
+    ///
+    /// A queue_capacity of 0 is returned as a [`UdpLoggerError::QueueCapacity`] by
+    /// [`build`], or [`init`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{QueueFullPolicy, UdpLogger};
+    ///
+    /// UdpLogger::new()
+    ///     .with_async_sender(1024)
+    ///     .with_queue_full_policy(QueueFullPolicy::DropOldest)
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`QueueFullPolicy`]: enum.QueueFullPolicy.html
+    /// [`UdpLoggerError::QueueCapacity`]: enum.UdpLoggerError.html#variant.QueueCapacity
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_async_sender(mut self, queue_capacity: usize) -> Self {
+        if queue_capacity == 0 {
+            self.defer_error(UdpLoggerError::QueueCapacity(queue_capacity));
+            return self;
+        }
+        self.async_capacity = Some(queue_capacity);
+
+        self
+    }
+
+    /// Send from a task on a tokio runtime, so that logging from async code doesn't block a
+    /// worker of the runtime on the network.
+    ///
+    /// Payloads are formatted by the logging thread, then queued for a task, spawned on the
+    /// runtime by [`build`], which sends them with tokio UdpSockets. The queue holds up to
+    /// queue_capacity payloads, once full, payloads are dropped, as logging can't wait on the
+    /// runtime from within it. Unix domain sockets, and transports, are sent to by the task
+    /// as they otherwise would be. This is used in place of [`with_async_sender`]. With
+    /// [`with_blocking`], UDP sockets are also sent to as they otherwise would be, blocking
+    /// the task.
+    ///
+    /// Flushing waits for the task to send the queued payloads, unless it's called from a
+    /// current_thread runtime, where the task can't run until flushing returns, so flushing
+    /// returns at once.
+    ///
+    /// A queue_capacity of 0 is returned as a [`UdpLoggerError::QueueCapacity`] by
+    /// [`build`], or [`init`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// # async fn run() {
+    /// UdpLogger::new()
+    ///     .with_tokio(tokio::runtime::Handle::current(), 1024)
+    ///     .init()
+    ///     .unwrap();
+    /// # }
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`UdpLoggerError::QueueCapacity`]: enum.UdpLoggerError.html#variant.QueueCapacity
+    /// [`with_async_sender`]: #method.with_async_sender
+    /// [`with_blocking`]: #method.with_blocking
+    #[cfg(feature = "tokio")]
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_tokio(mut self, runtime: tokio::runtime::Handle, queue_capacity: usize) -> Self {
+        if queue_capacity == 0 {
+            self.defer_error(UdpLoggerError::QueueCapacity(queue_capacity));
+            return self;
+        }
+        self.tokio_runtime = Some((runtime, queue_capacity));
+
+        self
+    }
+
+    /// Set the handling of payloads logged while the background sender's queue is full,
+    /// see [`with_async_sender`].
+    ///
+    /// [`with_async_sender`]: #method.with_async_sender
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_queue_full_policy(mut self, policy: QueueFullPolicy) -> Self {
+        self.queue_full_policy = policy;
+
+        self
+    }
+
+    /// Provide a handler for errors encountered while sending a log payload.
+    ///
+    /// Without a handler, send errors are written to stderr.
+    ///
+    /// # Examples
+    ///
+    /// Count send errors, rather than reporting them.
+    ///
+    /// ```no_run
+    /// use std::sync::atomic::{AtomicUsize, Ordering};
+    /// use std::sync::Arc;
+    /// use udp_logger_rs::UdpLogger;
+    ///
+    /// let errors = Arc::new(AtomicUsize::new(0));
+    /// let counter = errors.clone();
+    /// UdpLogger::new()
+    ///     .with_error_handler(Box::new(move |_err| {
+    ///         counter.fetch_add(1, Ordering::Relaxed);
+    ///     }))
+    ///     .init()
+    ///     .unwrap();
+    /// ```
+    #[must_use = "You must call init() to begin logging"]
+    pub fn with_error_handler(
+        mut self,
+        handler: Box<dyn Fn(std::io::Error) + Send + Sync>,
+    ) -> Self {
+        self.error_handler = ErrorHandler(Arc::from(handler));
 
         self
     }
 
-    /// Override the default source socket.
-    ///
-    /// This sets the default source socket, which otherwise defaults to "127.0.0.1:4000".
-    ///
-    /// # Examples
-    ///
-    /// Log from UDP port "127.0.0.1:4444"
-    ///
-    /// ```no_run
-    /// use udp_logger_rs::UdpLogger;
-    ///
-    /// UdpLogger::new()
-    ///     .with_source("127.0.0.1:4444")
-    ///     .init()
-    ///     .unwrap();
-    /// ```
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_source(mut self, source: &str) -> Self {
-        let socket = UdpSocket::bind(source).expect("unable to bind to socket");
-        socket
-            .set_nonblocking(true)
-            .expect("unable to set socket non-blocking");
-        self.default_source = socket;
+    /// The counts of payloads sent and dropped so far.
+    pub fn stats(&self) -> LogStats {
+        self.counters.stats()
+    }
+
+    /// A handle for reading the counts of payloads sent and dropped, which remains usable
+    /// once the UdpLogger has been installed by init().
+    pub fn stats_handle(&self) -> LogStatsHandle {
+        LogStatsHandle(self.counters.clone())
+    }
+
+    /// Flush the logger, sending the payloads it holds, and release its sockets.
+    ///
+    /// This is what dropping the logger does, making it explicit where a logger built with
+    /// [`build`] goes out of use. The global logger, installed by [`init`], is never dropped,
+    /// [`UdpLoggerHandle::shutdown`] flushes it and leaves it inert.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{info, UdpLogger};
+    ///
+    /// let audit = UdpLogger::new().build().unwrap();
+    /// info!(logger: &audit, "user logged out");
+    /// audit.shutdown();
+    /// ```
+    ///
+    /// [`build`]: #method.build
+    /// [`init`]: #method.init
+    /// [`UdpLoggerHandle::shutdown`]: struct.UdpLoggerHandle.html#method.shutdown
+    pub fn shutdown(self) {
+        drop(self)
+    }
+
+    /// Determines if a message logged at the level, with the target, will be logged by this
+    /// logger, as [`level_enabled`] does for the global logger.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{debug, Level, UdpLogger};
+    ///
+    /// let audit = UdpLogger::new().build().unwrap();
+    /// if audit.level_enabled(Level::Debug, "my::target") {
+    ///     debug!(logger: &audit, target: "my::target", "state dump");
+    /// }
+    /// ```
+    ///
+    /// [`level_enabled`]: fn.level_enabled.html
+    pub fn level_enabled(&self, level: Level, target: &str) -> bool {
+        level <= STATIC_MAX_LEVEL
+            && self.enabled(&Metadata::builder().level(level).target(target).build())
+    }
+
+    /// Log some bytes at the level, with the target, to this logger, as [`log_bytes`] does
+    /// for the global logger.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{Level, UdpLogger, WireFmt};
+    ///
+    /// let frames = UdpLogger::new()
+    ///     .with_wire_fmt(WireFmt::ByteBuffer)
+    ///     .build()
+    ///     .unwrap();
+    /// frames.log_bytes(Level::Info, "frames", &[0x01, 0x02, 0xFF]);
+    /// ```
+    ///
+    /// [`log_bytes`]: fn.log_bytes.html
+    pub fn log_bytes(&self, level: Level, target: &str, bytes: &[u8]) {
+        if level <= STATIC_MAX_LEVEL {
+            log_raw(self, level, target, bytes);
+        }
+    }
+
+    #[doc(hidden)]
+    // partial_init is used in testing, it panics where init() would return an error.
+    pub fn partial_init(self) -> Self {
+        let logger = self.build().unwrap_or_else(|err| panic!("{}", err));
+        log::set_max_level(logger.max_level());
+        logger
+    }
+
+    /// Build the logger, without installing it as the global logger.
+    ///
+    /// The logger can be used with the `logger:` form of the logging macros, or as a
+    /// [`log::Log`], allowing several independently configured loggers in one process.
+    /// Unlike [`init`], the global maximum log level is left unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{info, UdpLogger};
+    ///
+    /// let audit = UdpLogger::new()
+    ///     .with_source("127.0.0.1:4001")
+    ///     .with_destination("10.0.0.5:5140")
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// info!(logger: &audit, "user logged in");
+    /// ```
+    ///
+    /// [`log::Log`]: https://docs.rs/log/0.4/log/trait.Log.html
+    /// [`init`]: #method.init
+    pub fn build(mut self) -> Result<Self, UdpLoggerError> {
+        if let Some(err) = self.init_error.take() {
+            return Err(err);
+        }
+        if let Socket::Udp(source) = &*self.default_source {
+            source
+                .bind()
+                .map_err(|err| UdpLoggerError::Bind(err, source.addr.clone()))?;
+        }
+        for directive in self.invalid_directives.drain(..) {
+            (self.error_handler.0)(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                directive,
+            ));
+        }
+        if let Some(precision) = self.timestamp_precision {
+            for fraction in ["%.3f", "%.6f", "%.9f"] {
+                self.timestamp_format = self
+                    .timestamp_format
+                    .replace(fraction, precision.fraction());
+            }
+        }
+        if self.level_style != LevelStyle::default() || self.level_colors.is_some() {
+            for level in [
+                Level::Error,
+                Level::Warn,
+                Level::Info,
+                Level::Debug,
+                Level::Trace,
+            ] {
+                self.level_names[level as usize - 1] = Cow::Owned(self.styled_level(level));
+            }
+        }
+        self.level_ceiling
+            .store(self.max_level() as usize, Ordering::Relaxed);
+        // most specific first, as module levels are sorted by with_module_level()
+        self.target_destinations
+            .sort_by_key(|(target, _destination)| specificity(target));
+
+        if self.broadcast {
+            if let Some(destination) = self
+                .all_destinations()
+                .find(|destination| !may_broadcast(destination))
+            {
+                return Err(UdpLoggerError::NotBroadcast(destination.to_string()));
+            }
+        }
+        // fail fast on a malformed, or unresolvable, destination
+        if !self.null_sink {
+            for destination in self.all_destinations() {
+                if let Err(err) = self.resolve(&self.default_source, destination) {
+                    return Err(UdpLoggerError::Resolve(err, destination.to_string()));
+                }
+            }
+        }
+        for socket in self.all_sources() {
+            if let Socket::Udp(source) = &**socket {
+                source
+                    .with(|socket| self.configure_socket(socket))
+                    .map_err(|err| UdpLoggerError::Bind(err, source.addr.clone()))?;
+            }
+        }
+        if self.connected_socket && !self.null_sink && self.routes_to_default_only() {
+            if let Socket::Udp(source) = &*self.default_source {
+                let destination = &self.default_destination;
+                let peer = self
+                    .resolve(&self.default_source, destination)
+                    .and_then(|peer| {
+                        peer.parse::<SocketAddr>().map_err(|err| {
+                            std::io::Error::new(std::io::ErrorKind::InvalidInput, err)
+                        })
+                    })
+                    .and_then(|peer| {
+                        source.connect(peer)?;
+                        Ok(peer)
+                    });
+                if let Err(err) = peer {
+                    return Err(UdpLoggerError::Resolve(err, destination.to_string()));
+                }
+            }
+        }
+        // the background sender, when there is one, is free to block
+        if self.blocking || self.async_capacity.is_some() {
+            for socket in self.all_sources() {
+                socket
+                    .set_nonblocking(false)
+                    .map_err(|err| UdpLoggerError::Bind(err, socket.addr()))?;
+            }
+        }
+        #[cfg(feature = "tokio")]
+        if let Some((runtime, capacity)) = &self.tokio_runtime {
+            self.tokio_sender = Some(TokioSender::spawn(
+                runtime,
+                *capacity,
+                self.blocking,
+                self.error_handler.clone(),
+                self.counters.clone(),
+            ));
+        }
+        if let Some(capacity) = self.async_capacity {
+            self.async_sender = Some(
+                AsyncSender::spawn(
+                    capacity,
+                    self.queue_full_policy,
+                    self.error_handler.clone(),
+                    self.counters.clone(),
+                )
+                .map_err(UdpLoggerError::Spawn)?,
+            );
+        }
+        Ok(self)
+    }
+
+    // The most verbose level which any module logs at.
+    fn max_level(&self) -> LevelFilter {
+        max_level_of(self.default_level, &self.module_levels).max(self.regex_ceiling())
+    }
+
+    // The level of the first regular expression matching the target, see with_regex_level().
+    fn regex_level(&self, target: &str) -> Option<LevelFilter> {
+        #[cfg(feature = "regex")]
+        {
+            self.regex_levels
+                .iter()
+                .find(|(regex, _level)| regex.is_match(target))
+                .map(|(_regex, level)| *level)
+        }
+        #[cfg(not(feature = "regex"))]
+        {
+            let _target = target;
+            None
+        }
+    }
+
+    // The most verbose level of the regular expressions, see with_regex_level().
+    fn regex_ceiling(&self) -> LevelFilter {
+        #[cfg(feature = "regex")]
+        {
+            self.regex_levels
+                .iter()
+                .map(|(_regex, level)| *level)
+                .fold(LevelFilter::Off, Ord::max)
+        }
+        #[cfg(not(feature = "regex"))]
+        {
+            LevelFilter::Off
+        }
+    }
+
+    /// 'Init' the actual logger, instantiate it and configure it,
+    /// this method MUST be called in order for the logger to be effective.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a socket couldn't be bound, a destination couldn't be resolved,
+    /// or a logger has already been set, see [`UdpLoggerError`].
+    ///
+    /// [`UdpLoggerError`]: enum.UdpLoggerError.html
+    pub fn init(self) -> Result<(), UdpLoggerError> {
+        let logger = self.build()?;
+        log::set_max_level(logger.max_level());
+        log::set_boxed_logger(Box::new(logger))?;
+        Ok(())
+    }
+
+    /// Behaves like [`init`], returning a handle for changing the levels of the logger
+    /// at runtime.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{LevelFilter, UdpLogger};
+    ///
+    /// let handle = UdpLogger::new()
+    ///     .with_level(LevelFilter::Info)
+    ///     .init_with_handle()
+    ///     .unwrap();
+    ///
+    /// // temporarily debug the network
+    /// handle.set_module_level("my_app::net", LevelFilter::Debug);
+    /// ```
+    ///
+    /// [`init`]: #method.init
+    pub fn init_with_handle(self) -> Result<UdpLoggerHandle, UdpLoggerError> {
+        let (logger, handle) = self.build()?.attach_handle();
+        log::set_max_level(logger.max_level());
+        log::set_boxed_logger(Box::new(logger))?;
+        Ok(handle)
+    }
+
+    #[doc(hidden)]
+    // partial_init_with_handle is used in testing, it panics where init_with_handle() would
+    // return an error.
+    pub fn partial_init_with_handle(self) -> (Self, UdpLoggerHandle) {
+        self.partial_init().attach_handle()
+    }
+
+    // Provide an initialized logger with levels which can be changed through a handle.
+    fn attach_handle(mut self) -> (Self, UdpLoggerHandle) {
+        let levels = Arc::new(RwLock::new(Levels {
+            default_level: self.default_level,
+            module_levels: self.module_levels.clone(),
+            built_module_levels: self.module_levels.clone(),
+            shut_down: false,
+        }));
+        self.dynamic_levels = Some(levels.clone());
+        let wire_fmt = Arc::new(RwLock::new(self.wire_fmt));
+        self.dynamic_wire_fmt = Some(wire_fmt.clone());
+        let handle = UdpLoggerHandle {
+            levels,
+            wire_fmt,
+            level_ceiling: self.level_ceiling.clone(),
+            regex_ceiling: self.regex_ceiling(),
+            counters: self.counters.clone(),
+        };
+        (self, handle)
+    }
+
+    /// Add a field to the records logged by the current thread, until it is popped.
+    ///
+    /// Context fields follow the global fields, and precede the kv pairs of a record. When
+    /// keys collide, kv pairs take precedence over context fields, which take precedence over
+    /// global fields, and the most recently pushed context field takes precedence.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{info, UdpLogger};
+    ///
+    /// UdpLogger::push_context("request_id", "42");
+    /// info!("handling request");
+    /// UdpLogger::pop_context();
+    /// ```
+    pub fn push_context(key: &str, value: &str) {
+        CONTEXT.with(|context| {
+            context
+                .borrow_mut()
+                .push((key.to_string(), value.to_string()))
+        });
+    }
+
+    /// Remove the most recently pushed field from the context of the current thread,
+    /// returning it.
+    pub fn pop_context() -> Option<(String, String)> {
+        CONTEXT.with(|context| context.borrow_mut().pop())
+    }
+
+    /// Add a field to the records logged by the current thread, until the returned guard is
+    /// dropped, see [`push_context`].
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use udp_logger_rs::{info, UdpLogger};
+    ///
+    /// fn handle(request_id: &str) {
+    ///     let _context = UdpLogger::scoped_context("request_id", request_id);
+    ///     info!("handling request");
+    /// }
+    /// ```
+    ///
+    /// [`push_context`]: #method.push_context
+    pub fn scoped_context(key: &str, value: &str) -> ContextGuard {
+        UdpLogger::push_context(key, value);
+        ContextGuard(())
+    }
+
+    // Hold the first error encountered by the builder, for init() to return.
+    fn defer_error(&mut self, err: UdpLoggerError) {
+        if self.init_error.is_none() {
+            self.init_error = Some(err);
+        }
+    }
+
+    // The source records of the level are sent from.
+    fn source_for(&self, level: Level) -> &Arc<Socket> {
+        exact_route(&self.exact_sources, level)
+            .or_else(|| route(&self.sources, level))
+            .unwrap_or(&self.default_source)
+    }
+
+    // Every record is sent to the default destination, as there's no routing by level or
+    // target, and no additional destinations.
+    fn routes_to_default_only(&self) -> bool {
+        self.destinations.is_empty()
+            && self.exact_destinations.is_empty()
+            && self.target_destinations.is_empty()
+            && self.additional_destinations.is_empty()
+    }
+
+    // Every configured source, by level, or otherwise.
+    fn all_sources(&self) -> impl Iterator<Item = &Arc<Socket>> {
+        std::iter::once(&self.default_source)
+            .chain(self.sources.iter().map(|(_level, socket)| socket))
+            .chain(self.exact_sources.iter().map(|(_level, socket)| socket))
+    }
+
+    // Every configured destination, by level, target, or otherwise.
+    fn all_destinations(&self) -> impl Iterator<Item = &String> {
+        std::iter::once(&self.default_destination)
+            .chain(self.destinations.iter().map(|(_level, addr)| addr))
+            .chain(self.exact_destinations.iter().map(|(_level, addr)| addr))
+            .chain(&self.additional_destinations)
+            .chain(self.target_destinations.iter().map(|(_target, addr)| addr))
+    }
+
+    // Format a record as an RFC 5424 syslog message.
+    #[cfg(feature = "syslog")]
+    fn syslog_5424(&self, record: &Record<'_>, target: &str, sequence: Option<u64>) -> String {
+        let severity = match record.level() {
+            Level::Error => 3,
+            Level::Warn => 4,
+            Level::Info => 6,
+            Level::Debug | Level::Trace => 7,
+        };
+        let mut structured_data = SyslogAccumulator::default();
+        self.visit_location(record, &mut structured_data);
+        self.visit_kvs(record, &mut structured_data);
+        let mut elements = String::new();
+        if let Some(sequence) = sequence {
+            let sequence_id = (sequence - 1) % SYSLOG_MAX_SEQUENCE_ID + 1;
+            elements.push_str(&format!("[meta sequenceId=\"{}\"]", sequence_id));
+        }
+        if !structured_data.0.is_empty() {
+            elements.push_str(&format!("[{}{}]", SYSLOG_SD_ID, structured_data.0));
+        }
+        if elements.is_empty() {
+            elements.push('-');
+        }
+        format!(
+            "<{}>1 {} {} {} {} - {} [{}] {}",
+            self.facility as u8 * 8 + severity,
+            if self.timestamps {
+                self.clock().format_now("%Y-%m-%dT%H:%M:%S%.6f%:z")
+            } else {
+                "-".to_string()
+            },
+            self.hostname.as_deref().unwrap_or("-"),
+            self.app_name.as_deref().unwrap_or("-"),
+            std::process::id(),
+            elements,
+            target,
+            record.args()
+        )
+    }
+
+    // The name, or id, of the current thread, when enabled by with_thread_names().
+    fn thread_name(&self) -> Option<String> {
+        if !self.thread_names {
+            return None;
+        }
+        let thread = std::thread::current();
+        Some(match thread.name() {
+            Some(name) => name.to_string(),
+            None => format!("{:?}", thread.id()),
+        })
+    }
+
+    // Visit the global fields, context fields and kv pairs of a record, sorted by key when
+    // enabled by with_sorted_kv(). Later fields take precedence over earlier fields with the
+    // same key. Pairs beyond the limit set by with_max_kv_pairs() are counted by a final
+    // kvs_omitted pair.
+    fn visit_kvs(&self, record: &Record<'_>, visitor: &mut dyn for<'v> Visitor<'v>) {
+        let omitted = self.visit_limited_kvs(record, visitor);
+        if omitted > 0 {
+            let _result = visitor.visit_pair(Key::from_str("kvs_omitted"), Value::from(omitted));
+        }
+    }
+
+    // Visit the kv pairs, up to the limit set by with_max_kv_pairs(), returning the number of
+    // pairs omitted.
+    fn visit_limited_kvs(&self, record: &Record<'_>, visitor: &mut dyn for<'v> Visitor<'v>) -> u64 {
+        match self.max_kv_pairs {
+            Some(remaining) => {
+                let mut limiter = KVLimiter {
+                    visitor,
+                    remaining,
+                    omitted: 0,
+                };
+                self.visit_redacted_kvs(record, &mut limiter);
+                limiter.omitted
+            }
+            None => {
+                self.visit_redacted_kvs(record, visitor);
+                0
+            }
+        }
+    }
+
+    fn visit_redacted_kvs(&self, record: &Record<'_>, visitor: &mut dyn for<'v> Visitor<'v>) {
+        if self.redacted_keys.is_empty() {
+            self.visit_unredacted_kvs(record, visitor);
+        } else {
+            let mut redactor = Redactor {
+                visitor,
+                keys: &self.redacted_keys,
+                redaction: self.redaction,
+            };
+            self.visit_unredacted_kvs(record, &mut redactor);
+        }
+    }
+
+    fn visit_unredacted_kvs(&self, record: &Record<'_>, visitor: &mut dyn for<'v> Visitor<'v>) {
+        CONTEXT.with(|context| {
+            let context = context.borrow();
+            let mut fields: Vec<&(String, String)> =
+                self.global_fields.iter().chain(context.iter()).collect();
+            let mut index = 0;
+            while index < fields.len() {
+                let key = fields[index].0.as_str();
+                if record.key_values().get(Key::from_str(key)).is_some()
+                    || fields[index + 1..]
+                        .iter()
+                        .any(|(other, _value)| other == key)
+                {
+                    fields.remove(index);
+                } else {
+                    index += 1;
+                }
+            }
+            let mut pairs = KVCollector::default();
+            for (key, value) in fields {
+                pairs
+                    .0
+                    .push((Key::from_str(key), Value::from(value.as_str())));
+            }
+            if !self.sorted_kv {
+                for (key, value) in pairs.0 {
+                    let _result = visitor.visit_pair(key, value);
+                }
+                let _result = record.key_values().visit(visitor);
+                return;
+            }
+            let _result = record.key_values().visit(&mut pairs);
+            pairs.0.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+            for (key, value) in pairs.0 {
+                let _result = visitor.visit_pair(key, value);
+            }
+        })
+    }
+
+    // Append the HMAC tag of a ByteBuffer or Protobuf payload, see with_hmac_key().
+    #[cfg(feature = "signing")]
+    fn sign(&self, payload: &mut Vec<u8>, wire_fmt: WireFmt) {
+        use hmac::Mac;
+        let signed = match wire_fmt {
+            WireFmt::ByteBuffer => true,
+            #[cfg(feature = "protobuf")]
+            WireFmt::Protobuf => true,
+            _ => false,
+        };
+        if let (Some(signer), true, None) = (&self.hmac, signed, &self.formatter) {
+            let mut mac = signer.0.clone();
+            mac.update(payload);
+            let tag = mac.finalize().into_bytes();
+            payload.extend_from_slice(&tag[..self.hmac_len]);
+        }
+    }
+
+    // Visit the built-in fields, as kv pairs, followed by the kv pairs of a record, for the
+    // typed wire formats.
+    fn visit_fields(
+        &self,
+        record: &Record<'_>,
+        sequence: Option<u64>,
+        visitor: &mut dyn for<'v> Visitor<'v>,
+    ) {
+        if let Some(sequence) = sequence {
+            let _result = visitor.visit_pair(Key::from_str("seq"), Value::from(sequence));
+        }
+        if let Some(hostname) = &self.hostname {
+            let _result = visitor.visit_pair(Key::from_str("host"), Value::from(hostname.as_str()));
+        }
+        if let Some(app_name) = &self.app_name {
+            let _result = visitor.visit_pair(Key::from_str("app"), Value::from(app_name.as_str()));
+        }
+        if let Some(pid) = self.pid {
+            let _result = visitor.visit_pair(Key::from_str("pid"), Value::from(pid));
+        }
+        self.visit_location(record, visitor);
+        self.visit_kvs(record, visitor);
+    }
+
+    // Visit the fields identifying where a record was logged, as enabled by with_thread_names()
+    // and with_source_location().
+    fn visit_location(&self, record: &Record<'_>, visitor: &mut dyn for<'v> Visitor<'v>) {
+        if let Some(thread) = self.thread_name() {
+            let _result = visitor.visit_pair(Key::from_str("thread"), Value::from(thread.as_str()));
+        }
+        if self.source_location {
+            if let Some(file) = record.file() {
+                let _result = visitor.visit_pair(Key::from_str("file"), Value::from(file));
+            }
+            if let Some(line) = record.line() {
+                let _result = visitor.visit_pair(Key::from_str("line"), Value::from(line));
+            }
+        }
+    }
+
+    // The clock timestamping the record being logged by the thread.
+    fn clock(&self) -> RecordClock<'_> {
+        match TIMESTAMP.try_with(Cell::get).ok().flatten() {
+            Some(timestamp) => RecordClock::Fixed(FixedClock(timestamp)),
+            None => RecordClock::Logger(&*self.clock),
+        }
+    }
+
+    // Format a record as a line of logfmt.
+    fn logfmt(&self, record: &Record<'_>, target: &str, sequence: Option<u64>) -> String {
+        let mut line = LogfmtAccumulator::default();
+        if self.timestamps {
+            line.push("ts", &self.clock().format_now(&self.timestamp_format));
+        }
+        line.push("level", &record.level().as_str().to_lowercase());
+        if let Some(sequence) = sequence {
+            line.push("seq", &sequence.to_string());
+        }
+        if let Some(hostname) = &self.hostname {
+            line.push("host", hostname);
+        }
+        if let Some(app_name) = &self.app_name {
+            line.push("app", app_name);
+        }
+        if let Some(pid) = self.pid {
+            line.push("pid", &pid.to_string());
+        }
+        self.visit_location(record, &mut line);
+        line.push("target", target);
+        line.push("msg", &record.args().to_string());
+        self.visit_kvs(record, &mut line);
+        line.0
+    }
+
+    // Apply the socket options, which were provided to the builder, to a source socket.
+    fn configure_socket(&self, socket: &UdpSocket) -> std::io::Result<()> {
+        match &self.multicast_interface {
+            Some(MulticastInterface::V4(interface)) => {
+                socket2::SockRef::from(socket).set_multicast_if_v4(interface)?;
+                socket.set_multicast_loop_v4(true)?;
+            }
+            Some(MulticastInterface::V6(interface)) => {
+                socket2::SockRef::from(socket).set_multicast_if_v6(*interface)?;
+                socket.set_multicast_loop_v6(true)?;
+            }
+            None => (),
+        }
+        if self.broadcast {
+            socket.set_broadcast(true)?;
+        }
+        if let Some(bytes) = self.send_buffer_size {
+            socket2::SockRef::from(socket).set_send_buffer_size(bytes)?;
+        }
+        if let Some(ttl) = self.ttl {
+            socket.set_ttl(ttl)?;
+        }
+        if let Some(ttl) = self.multicast_ttl {
+            if socket.local_addr()?.is_ipv4() {
+                socket.set_multicast_ttl_v4(ttl)?;
+            } else {
+                socket2::SockRef::from(socket).set_multicast_hops_v6(ttl)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Send a payload, applying the oversize policy if it doesn't fit in a datagram.
+    fn send_payload(
+        &self,
+        socket: &Arc<Socket>,
+        destination: &str,
+        payload: &[u8],
+        wire_fmt: WireFmt,
+    ) -> std::io::Result<()> {
+        let fragment_size = self.fragment_size.min(self.max_datagram_size);
+        let limit = match self.oversize_policy {
+            OversizePolicy::Fragment => fragment_size,
+            _ => self.max_datagram_size,
+        };
+        if payload.len() <= limit {
+            return self.send(socket, destination, payload);
+        }
+        match self.oversize_policy {
+            OversizePolicy::Truncate if wire_fmt.is_text() && self.formatter.is_none() => {
+                let terminator = match wire_fmt {
+                    WireFmt::Uncompressed => self.terminator.as_str(),
+                    _ => "",
+                };
+                let mut len = limit - TRUNCATED_MARKER.len() - terminator.len();
+                // back up to the start of a utf8 character
+                while payload[len] & 0xC0 == 0x80 {
+                    len -= 1;
+                }
+                let mut truncated = Vec::with_capacity(limit);
+                truncated.extend_from_slice(&payload[..len]);
+                truncated.extend_from_slice(TRUNCATED_MARKER.as_bytes());
+                truncated.extend_from_slice(terminator.as_bytes());
+                self.send(socket, destination, &truncated)
+            }
+            OversizePolicy::Fragment => {
+                let message_id = self
+                    .next_message_id
+                    .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let chunks = payload.chunks(fragment_size - FRAGMENT_HEADER_SIZE);
+                let count = chunks.len();
+                if count > usize::from(u16::MAX) {
+                    return Err(oversize_error(payload.len(), limit));
+                }
+                for (index, chunk) in chunks.enumerate() {
+                    let mut fragment = Vec::with_capacity(FRAGMENT_HEADER_SIZE + chunk.len());
+                    fragment.extend_from_slice(&FRAGMENT_MAGIC);
+                    fragment.extend_from_slice(&message_id.to_be_bytes());
+                    fragment.extend_from_slice(&(index as u16).to_be_bytes());
+                    fragment.extend_from_slice(&(count as u16).to_be_bytes());
+                    fragment.extend_from_slice(chunk);
+                    self.send(socket, destination, &fragment)?;
+                }
+                Ok(())
+            }
+            _ => Err(oversize_error(payload.len(), limit)),
+        }
+    }
+
+    // Send a payload, holding it if the socket would block, or while earlier payloads are
+    // held, so that payloads are sent in order. With a background sender, the payload is
+    // queued for it to send.
+    fn send(&self, socket: &Arc<Socket>, destination: &str, payload: &[u8]) -> std::io::Result<()> {
+        let destination = self.resolve(socket, destination)?;
+        let destination = destination.as_ref();
+        #[cfg(feature = "tokio")]
+        if let Some(sender) = &self.tokio_sender {
+            return sender.enqueue(Queued {
+                socket: socket.clone(),
+                destination: destination.to_string(),
+                payload: payload.to_vec(),
+            });
+        }
+        if let Some(sender) = &self.async_sender {
+            return sender.enqueue(Queued {
+                socket: socket.clone(),
+                destination: destination.to_string(),
+                payload: payload.to_vec(),
+            });
+        }
+        if !self.send_pending() {
+            return self.hold(socket, destination, payload);
+        }
+        let mut result = socket.send_to(payload, destination);
+        for _retry in 0..self.send_retries {
+            match &result {
+                Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                    std::thread::yield_now();
+                    result = socket.send_to(payload, destination);
+                }
+                _ => break,
+            }
+        }
+        match result {
+            Ok(count) => {
+                self.counters.sent(count);
+                Ok(())
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => {
+                self.hold(socket, destination, payload)
+            }
+            Err(err) => {
+                self.recover(socket, &err);
+                Err(err)
+            }
+        }
+    }
+
+    // Hold a payload until it can be sent, dropping the oldest held payload once the limit
+    // is reached.
+    fn hold(&self, socket: &Arc<Socket>, destination: &str, payload: &[u8]) -> std::io::Result<()> {
+        let pending = Pending {
+            socket: socket.clone(),
+            destination: destination.to_string(),
+            payload: payload.to_vec(),
+        };
+        let mut held = self
+            .pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let result = if held.len() == MAX_PENDING_PAYLOADS {
+            held.pop_front();
+            Err(pending_full_error())
+        } else {
+            Ok(())
+        };
+        held.push_back(pending);
+        result
+    }
+
+    // Send the held payloads, without waiting, until one would block, returning whether
+    // none remain held. Payloads which fail are dropped, and reported once they're released.
+    fn send_pending(&self) -> bool {
+        let mut failures = Vec::new();
+        let drained = {
+            let mut pending = self
+                .pending
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            loop {
+                let entry = match pending.front() {
+                    Some(entry) => entry,
+                    None => break true,
+                };
+                match entry.socket.send_to(&entry.payload, &entry.destination) {
+                    Ok(count) => self.counters.sent(count),
+                    Err(err) if err.kind() == std::io::ErrorKind::WouldBlock => break false,
+                    Err(err) => {
+                        self.recover(&entry.socket, &err);
+                        failures.push(err);
+                    }
+                }
+                pending.pop_front();
+            }
+        };
+        for err in failures {
+            self.report_error(err);
+        }
+        drained
+    }
+
+    // Count a send failure of a UDP source socket, rebinding the socket to its address,
+    // configured as it was, once failures repeat. Only failures of the socket itself are
+    // counted, rather than those of a destination, such as a refused connection. Rebinding
+    // backs off until a send succeeds, in case the failures weren't the socket's. The
+    // background sender of with_async_sender() doesn't rebind.
+    fn recover(&self, socket: &Socket, err: &std::io::Error) {
+        let source = match socket {
+            Socket::Udp(source) => source,
+            _ => return,
+        };
+        if !is_socket_error(err) {
+            return;
+        }
+        if source.failures.fetch_add(1, Ordering::Relaxed) + 1 < REBIND_THRESHOLD {
+            return;
+        }
+        // the handler is called once the socket, and the rebind state, are released
+        let failed = {
+            let mut rebind = source
+                .rebind
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            if Instant::now() < rebind.next_attempt {
+                return;
+            }
+            let mut slot = source
+                .socket
+                .write()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            // release the address before binding it again
+            *slot = None;
+            let blocking = self.blocking || self.async_capacity.is_some();
+            let peer = *source
+                .peer
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            let socket = UdpSocket::bind(&source.addr).and_then(|socket| {
+                self.configure_socket(&socket)?;
+                socket.set_nonblocking(!blocking)?;
+                if let Some(peer) = peer {
+                    socket.connect(peer)?;
+                }
+                Ok(socket)
+            });
+            rebind.next_attempt = Instant::now() + rebind.backoff;
+            rebind.backoff = std::cmp::min(rebind.backoff * 2, REBIND_MAX_BACKOFF);
+            match socket {
+                Ok(socket) => {
+                    *slot = Some(socket);
+                    source.failures.store(0, Ordering::Relaxed);
+                    source.generation.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
+                Err(err) => Some(err),
+            }
+        };
+        if let Some(err) = failed {
+            (self.error_handler.0)(err);
+        }
+    }
+
+    // Resolve a destination hostname of a UDP socket to a cached address, see
+    // with_resolve_interval(). Other destinations are returned as they are.
+    fn resolve<'a>(&self, socket: &Socket, destination: &'a str) -> std::io::Result<Cow<'a, str>> {
+        let local_addr = match socket {
+            // a connected socket sends to the destination it was connected to
+            Socket::Udp(source) if source.connected.load(Ordering::Relaxed) => {
+                return Ok(Cow::Borrowed(destination))
+            }
+            Socket::Udp(source) if destination.parse::<SocketAddr>().is_err() => {
+                source.with(|socket| socket.local_addr())?
+            }
+            _ => return Ok(Cow::Borrowed(destination)),
+        };
+        let stale = {
+            let resolved = self
+                .resolved
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            match (resolved.get(destination), self.resolve_interval) {
+                (None, _) => true,
+                (Some(entry), Some(interval)) => entry.at.elapsed() >= interval,
+                (Some(_entry), None) => false,
+            }
+        };
+        // resolving may block, so the cache isn't locked meanwhile, and the entry is replaced
+        // afterwards, which other threads may also have done
+        let lookup = if stale {
+            Some(destination.to_socket_addrs().map(|addrs| addrs.collect()))
+        } else {
+            None
+        };
+        let mut failed = None;
+        let addr = {
+            let mut resolved = self
+                .resolved
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner());
+            match lookup {
+                Some(Ok(addrs)) => {
+                    let entry = Resolved {
+                        addrs,
+                        at: Instant::now(),
+                    };
+                    resolved.insert(destination.to_string(), entry);
+                }
+                Some(Err(err)) => match resolved.get_mut(destination) {
+                    Some(entry) => {
+                        entry.at = Instant::now();
+                        failed = Some(err);
+                    }
+                    None => return Err(err),
+                },
+                None => (),
+            }
+            let addrs = &resolved[destination].addrs;
+            addrs
+                .iter()
+                .find(|addr| addr.is_ipv4() == local_addr.is_ipv4())
+                .or_else(|| addrs.first())
+                .map(|addr| addr.to_string())
+        };
+        // the previous addresses are used, once the handler has the failure
+        if let Some(err) = failed {
+            (self.error_handler.0)(err);
+        }
+        addr.map(Cow::Owned).ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} didn't resolve to any address", destination),
+            )
+        })
+    }
+
+    // Report a payload which couldn't be sent.
+    fn report_error(&self, err: std::io::Error) {
+        self.counters.dropped();
+        (self.error_handler.0)(err);
+    }
+}
+
+impl Drop for UdpLogger {
+    // Send the payloads held, before the sockets are released.
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+impl Default for UdpLogger {
+    /// See [this](struct.UdpLogger.html#method.new)
+    fn default() -> Self {
+        UdpLogger::new()
+    }
+}
+
+/// The CRC32 (IEEE) checksum of some bytes, as appended to ByteBuffer payloads
+/// by [`with_checksum`].
+///
+/// # Examples
+///
+/// ```
+/// assert_eq!(udp_logger_rs::crc32(b"123456789"), 0xCBF4_3926);
+/// ```
+///
+/// [`with_checksum`]: struct.UdpLogger.html#method.with_checksum
+pub fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= u32::from(*byte);
+        for _bit in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+// Bytes, displayed base64 encoded, with the standard alphabet and padding.
+pub(crate) struct Base64<'a>(pub(crate) &'a [u8]);
+
+impl std::fmt::Display for Base64<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        const ALPHABET: &[u8; 64] =
+            b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+        for chunk in self.0.chunks(3) {
+            let bits = chunk.iter().enumerate().fold(0u32, |bits, (index, byte)| {
+                bits | u32::from(*byte) << (16 - 8 * index)
+            });
+            for index in 0..4 {
+                if index <= chunk.len() {
+                    let sextet = (bits >> (18 - 6 * index)) & 0x3F;
+                    f.write_char(char::from(ALPHABET[sextet as usize]))?;
+                } else {
+                    f.write_char('=')?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The error reported for a payload dropped because the background sender's queue is full.
+fn queue_full_error() -> std::io::Error {
+    std::io::Error::other("background sender queue is full, payload dropped")
+}
+
+// The error reported for the oldest payload held for flush(), dropped to hold another.
+fn pending_full_error() -> std::io::Error {
+    std::io::Error::other("payloads held for flush are full, oldest payload dropped")
+}
+
+// The error reported for a payload which is too large to send.
+fn oversize_error(len: usize, limit: usize) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        format!(
+            "payload of {} bytes exceeds datagram limit of {} bytes",
+            len, limit
+        ),
+    )
+}
+
+// Select the route for a level, which is the route with the least verbose level filter
+// which admits the level. For example, with routes for Warn and Debug, Error and Warn
+// messages take the Warn route, Info and Debug messages take the Debug route, and Trace
+// messages take no route. When routes share a level filter, the first provided is taken.
+fn route<T>(routes: &[(LevelFilter, T)], level: Level) -> Option<&T> {
+    routes
+        .iter()
+        .filter(|(filter, _route)| level <= *filter)
+        .min_by_key(|(filter, _route)| *filter)
+        .map(|(_filter, route)| route)
+}
+
+// Bind a non-blocking UDP source socket.
+fn bind_udp(addr: &str) -> std::io::Result<UdpSource> {
+    let source = UdpSource::unbound(addr);
+    source.bind()?;
+    Ok(source)
+}
+
+// Select the route for exactly a level, the first provided is taken.
+fn exact_route<T>(routes: &[(Level, T)], level: Level) -> Option<&T> {
+    routes
+        .iter()
+        .find(|(route_level, _route)| *route_level == level)
+        .map(|(_level, route)| route)
+}
+
+// Whether a destination may be a broadcast address, which only an IPv6 address can't be. A
+// directed broadcast address depends on the netmask of the interface, so IPv4 addresses, and
+// hostnames, are left to the send, which fails with EACCES for a broadcast address when
+// broadcast isn't enabled.
+fn may_broadcast(destination: &str) -> bool {
+    !matches!(destination.parse::<SocketAddr>(), Ok(SocketAddr::V6(_addr)))
+}
+
+// The level of the most specific module matching the target, if any. The module levels are
+// sorted from most to least specific, so the first match is taken.
+fn module_level(module_levels: &[(String, LevelFilter)], target: &str) -> Option<LevelFilter> {
+    module_levels
+        .iter()
+        .find(|(name, _level)| module_matches(target, name))
+        .map(|(_name, level)| *level)
+}
+
+// The most verbose of the default and module levels.
+fn max_level_of(
+    default_level: LevelFilter,
+    module_levels: &[(String, LevelFilter)],
+) -> LevelFilter {
+    module_levels
+        .iter()
+        .map(|(_name, level)| *level)
+        .fold(default_level, Ord::max)
+}
+
+// Parse a RUST_LOG level, by name or number, returning None if it isn't a standard log
+// level.
+fn parse_level(level: &str) -> Option<LevelFilter> {
+    match level.to_lowercase().as_str() {
+        "trace" | "5" => Some(LevelFilter::Trace),
+        "debug" | "4" => Some(LevelFilter::Debug),
+        "info" | "3" => Some(LevelFilter::Info),
+        "warn" | "2" => Some(LevelFilter::Warn),
+        "error" | "1" => Some(LevelFilter::Error),
+        "off" | "0" => Some(LevelFilter::Off),
+        _ => None,
+    }
+}
+
+// A target matches a module when it is the module, or one of its sub-modules. A module
+// containing '*' is a glob, see with_module_glob().
+fn module_matches(target: &str, module: &str) -> bool {
+    if module.contains('*') {
+        return target
+            .match_indices("::")
+            .map(|(index, _separator)| &target[..index])
+            .chain(std::iter::once(target))
+            .any(|parent| glob_matches(parent, module));
+    }
+    match target.strip_prefix(module) {
+        Some(rest) => rest.is_empty() || rest.starts_with("::"),
+        None => false,
+    }
+}
+
+// A glob matches the whole of a module, with '*' matching any characters, including "::".
+// The text between the first and last '*' is matched leftmost first, which can't miss a
+// match, as each '*' can absorb whatever precedes the following text.
+fn glob_matches(module: &str, glob: &str) -> bool {
+    let (first, last) = match (glob.find('*'), glob.rfind('*')) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return module == glob,
+    };
+    let (head, tail) = (&glob[..first], &glob[last + 1..]);
+    if module.len() < head.len() + tail.len()
+        || !module.starts_with(head)
+        || !module.ends_with(tail)
+    {
+        return false;
+    }
+    let mut rest = &module[head.len()..module.len() - tail.len()];
+    for part in glob[first..last].split('*').filter(|part| !part.is_empty()) {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+// Orders modules from most to least specific, by the length of the text they match
+// literally, with a module taken before a glob of the same length.
+fn specificity(module: &str) -> (std::cmp::Reverse<usize>, bool) {
+    let wildcards = module.matches('*').count();
+    (std::cmp::Reverse(module.len() - wildcards), wildcards > 0)
+}
+
+// Append a key/value pair, so that it can be parsed unambiguously. Whitespace, '=' and '"'
+// in keys are replaced with '_'. Values which are empty, or contain whitespace, '=', '"' or
+// the kv separator, are quoted, with '"' and '\\' escaped.
+fn push_kv(out: &mut String, key: &str, kv_sep: &str, value: &str) {
+    push_key(out, key, kv_sep);
+    if needs_quotes(value, kv_sep) {
+        push_quoted(out, value);
+    } else {
+        out.push_str(value);
+    }
+}
+
+fn needs_quotes(value: &str, kv_sep: &str) -> bool {
+    value.is_empty()
+        || value.contains(|c: char| c.is_whitespace() || c == '=' || c == '"')
+        || value.contains(kv_sep)
+}
 
-        self
+// Append a kv pair, as push_kv(), preserving the type of the value. Numbers and bools are
+// unquoted, while strings which would read as a number, bool or null are quoted. The value is
+// formatted in place, and only copied when it must be quoted.
+fn push_typed_kv(out: &mut String, key: &str, kv_sep: &str, value: &Value<'_>) {
+    push_key(out, key, kv_sep);
+    let start = out.len();
+    let _result = write!(out, "{}", value);
+    let text = &out[start..];
+    let typed = text == "null" || text.parse::<f64>().is_ok() || text.parse::<bool>().is_ok();
+    let scalar = || {
+        value.to_i64().is_some()
+            || value.to_u64().is_some()
+            || value.to_f64().is_some()
+            || value.to_bool().is_some()
+    };
+    if needs_quotes(text, kv_sep) || (typed && !scalar()) {
+        let text = out.split_off(start);
+        push_quoted(out, &text);
     }
+}
 
-    /// Provide a level specific source address.
-    ///
-    /// This sets the source address, for log messages matching the level.
-    ///
-    /// # Examples
-    ///
-    /// Log from UDP port "127.0.0.1:4001" all Info log messages. Trace and Debug messages
-    /// will log from the default source address. If with_source_level() for
-    /// Warn or Error aren't set, those levels will also send from "127.0.0.1:4001".
-    ///
-    /// ```no_run
-    /// use udp_logger_rs::UdpLogger;
-    /// use log::LevelFilter;
-    ///
-    /// UdpLogger::new()
-    ///     .with_source_level("127.0.0.1:4001", LevelFilter::Info)
-    ///     .init()
-    ///     .unwrap();
-    /// ```
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_source_level(mut self, source: &str, level: LevelFilter) -> Self {
-        let socket = UdpSocket::bind(source).expect("unable to bind to socket");
-        socket
-            .set_nonblocking(true)
-            .expect("unable to set socket non-blocking");
-        self.sources.push((level, socket));
+// Append a key, and the kv separator, with whitespace, '=' and '"' replaced with '_'.
+fn push_key(out: &mut String, key: &str, kv_sep: &str) {
+    out.extend(key.chars().map(|c| match c {
+        '=' | '"' => '_',
+        c if c.is_whitespace() => '_',
+        c => c,
+    }));
+    out.push_str(kv_sep);
+}
 
-        self
+// Append a value, quoted, with '"' and '\\' escaped.
+fn push_quoted(out: &mut String, value: &str) {
+    out.push('"');
+    for c in value.chars() {
+        if c == '"' || c == '\\' {
+            out.push('\\');
+        }
+        out.push(c);
     }
+    out.push('"');
+}
 
-    /// Override the default destination address.
-    ///
-    /// This sets the default destination address, which otherwise defaults to "127.0.0.1:4010".
-    ///
-    /// # Examples
-    ///
-    /// Log to UDP port "127.0.0.1:4040"
-    ///
-    /// ```no_run
-    /// use udp_logger_rs::UdpLogger;
-    ///
-    /// UdpLogger::new()
-    ///     .with_destination("127.0.0.1:4040")
-    ///     .init()
-    ///     .unwrap();
-    /// ```
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_destination(mut self, destination: &str) -> Self {
-        self.default_destination = destination.to_string();
+// Accumulates kv pairs, appended to the Uncompressed wire format, separated per the KvStyle.
+struct KVAccumulator<'a>(&'a mut String, KvStyle);
 
-        self
+impl<'kvs, 'a> Visitor<'kvs> for KVAccumulator<'a> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.push_str(self.1.pair_sep);
+        push_typed_kv(self.0, key.as_str(), self.1.kv_sep, &value);
+        Ok(())
     }
+}
 
-    /// Provide a level specific destination address.
-    ///
-    /// This sets the destination address, for log messages matching the level.
-    ///
-    /// # Examples
-    ///
-    /// Log to UDP port "127.0.0.1:4040" all Info log messages. Trace and Debug messages
-    /// will send to the default destination address. If with_destination_level() for
-    /// Warn or Error aren't set, those levels will also send to "127.0.0.1:4040".
-    ///
-    /// ```no_run
-    /// use udp_logger_rs::UdpLogger;
-    /// use log::LevelFilter;
-    /// UdpLogger::new()
-    ///     .with_destination_level("127.0.0.1:4040", LevelFilter::Info)
-    ///     .init()
-    ///     .unwrap();
-    /// ```
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_destination_level(mut self, destination: &str, level: LevelFilter) -> Self {
-        self.destinations.push((level, destination.to_string()));
+// Passes on kv pairs to a visitor, until none remain, then counts those omitted.
+struct KVLimiter<'a> {
+    visitor: &'a mut dyn for<'v> Visitor<'v>,
+    remaining: usize,
+    omitted: u64,
+}
 
-        self
+impl<'kvs, 'a> Visitor<'kvs> for KVLimiter<'a> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        if self.remaining == 0 {
+            self.omitted += 1;
+            return Ok(());
+        }
+        self.remaining -= 1;
+        self.visitor.visit_pair(key, value)
     }
+}
 
-    /// Set the wire format for logging.
-    #[must_use = "You must call init() to begin logging"]
-    pub fn with_wire_fmt(mut self, wire_fmt: WireFmt) -> Self {
-        self.wire_fmt = wire_fmt;
+// Redacts the kv pairs of the keys, before passing them on to a visitor.
+struct Redactor<'a> {
+    visitor: &'a mut dyn for<'v> Visitor<'v>,
+    keys: &'a [String],
+    redaction: Redaction,
+}
 
-        self
+impl<'kvs, 'a> Visitor<'kvs> for Redactor<'a> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        let redacted = self.keys.iter().any(|redacted| {
+            if self.redaction.ignore_case {
+                redacted.eq_ignore_ascii_case(key.as_str())
+            } else {
+                redacted == key.as_str()
+            }
+        });
+        match (redacted, self.redaction.drop) {
+            (false, _) => self.visitor.visit_pair(key, value),
+            (true, false) => self.visitor.visit_pair(key, Value::from("[REDACTED]")),
+            (true, true) => Ok(()),
+        }
     }
+}
 
-    #[doc(hidden)]
-    // partial_init is used internally in init() and in testing.
-    pub fn partial_init(mut self) -> Self {
-        /* Sort all module levels from most specific to least specific. The length of the module
-         * name is used instead of its actual depth to avoid module name parsing.
-         */
-        self.module_levels
-            .sort_by_key(|(name, _level)| name.len().wrapping_neg());
-        let max_level = self
-            .module_levels
-            .iter()
-            .map(|(_name, level)| level)
-            .copied()
-            .max();
-        let max_level = max_level
-            .map(|lvl| lvl.max(self.default_level))
-            .unwrap_or(self.default_level);
-
-        self.sources.sort_by_key(|(level, _socket)| *level);
-        self.destinations.sort_by_key(|(level, _socket)| *level);
-        log::set_max_level(max_level);
+// Collects kv pairs, so that they can be sorted before they are formatted.
+#[derive(Default)]
+struct KVCollector<'kvs>(Vec<(Key<'kvs>, Value<'kvs>)>);
 
-        self
+impl<'kvs> Visitor<'kvs> for KVCollector<'kvs> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.push((key, value));
+        Ok(())
     }
-    /// 'Init' the actual logger, instantiate it and configure it,
-    /// this method MUST be called in order for the logger to be effective.
-    pub fn init(self) -> Result<(), SetLoggerError> {
-        let logger = self.partial_init();
-        log::set_boxed_logger(Box::new(logger))?;
+}
+
+// Collects kv pairs as strings, for a custom formatter.
+#[derive(Default)]
+struct KVStrings(Vec<(String, String)>);
+
+impl<'kvs> Visitor<'kvs> for KVStrings {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        self.0.push((key.to_string(), value.to_string()));
         Ok(())
     }
 }
 
-impl Default for UdpLogger {
-    /// See [this](struct.UdpLogger.html#method.new)
-    fn default() -> Self {
-        UdpLogger::new()
+// Accumulates kv pairs as the PARAMs of an RFC 5424 STRUCTURED-DATA element. Names are
+// limited to 32 printable characters, other than '=', ']', and '"', invalid characters are
+// replaced with '_'. In values, '"', '\\', and ']' are escaped.
+#[cfg(feature = "syslog")]
+#[derive(Default)]
+struct SyslogAccumulator(String);
+
+// The largest sequenceId of the RFC 5424 meta STRUCTURED-DATA element, after which it wraps
+// to 1.
+#[cfg(feature = "syslog")]
+const SYSLOG_MAX_SEQUENCE_ID: u64 = 2_147_483_647;
+
+#[cfg(feature = "syslog")]
+impl<'kvs> Visitor<'kvs> for SyslogAccumulator {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        let name: String = key
+            .as_str()
+            .chars()
+            .map(|c| match c {
+                '!'..='~' if c != '=' && c != ']' && c != '"' => c,
+                _ => '_',
+            })
+            .take(32)
+            .collect();
+        self.0.push(' ');
+        self.0.push_str(&name);
+        self.0.push_str("=\"");
+        for c in value.to_string().chars() {
+            if c == '"' || c == '\\' || c == ']' {
+                self.0.push('\\');
+            }
+            self.0.push(c);
+        }
+        self.0.push('"');
+        Ok(())
     }
 }
 
+// Accumulates fields as logfmt.
 #[derive(Default)]
-struct KVAccumulator(String);
+struct LogfmtAccumulator(String);
+
+impl LogfmtAccumulator {
+    fn push(&mut self, key: &str, value: &str) {
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        push_kv(&mut self.0, key, "=", value);
+    }
+}
 
-impl<'kvs> Visitor<'kvs> for KVAccumulator {
+impl<'kvs> Visitor<'kvs> for LogfmtAccumulator {
     fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
-        self.0.push_str(&format!(" {}={}", key, value));
+        if !self.0.is_empty() {
+            self.0.push(' ');
+        }
+        push_typed_kv(&mut self.0, key.as_str(), "=", &value);
         Ok(())
     }
 }
 
-impl Log for UdpLogger {
-    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
-        &metadata.level().to_level_filter()
-            <= self
-                .module_levels
-                .iter()
-                /* At this point the Vec is already sorted so that we can simply take
-                 * the first match
-                 */
-                .find(|(name, _level)| metadata.target().starts_with(name))
-                .map(|(_name, level)| level)
-                .unwrap_or(&self.default_level)
+// Tags identifying the type of a kv value in the ByteBuffer wire format.
+const KV_TAG_I64: u8 = 1;
+const KV_TAG_F64: u8 = 2;
+const KV_TAG_BOOL: u8 = 3;
+const KV_TAG_STRING: u8 = 4;
+
+// Encodes kv pairs, preserving the type of their values, for the ByteBuffer wire format. The
+// pairs are appended to the payload, following their count.
+struct KVEncoder<'a> {
+    count: u16,
+    buffer: &'a mut Vec<u8>,
+}
+
+impl<'kvs, 'a> Visitor<'kvs> for KVEncoder<'a> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        // the count can't describe any more pairs, so the remainder are dropped
+        if self.count == u16::MAX {
+            return Ok(());
+        }
+        push_length_prefixed(self.buffer, |buffer| {
+            buffer.extend_from_slice(key.as_str().as_bytes())
+        });
+        match TypedValue::of(&value) {
+            TypedValue::I64(value) => {
+                self.buffer.push(KV_TAG_I64);
+                self.buffer.extend_from_slice(&value.to_be_bytes());
+            }
+            TypedValue::F64(value) => {
+                self.buffer.push(KV_TAG_F64);
+                self.buffer
+                    .extend_from_slice(&value.to_bits().to_be_bytes());
+            }
+            TypedValue::Bool(value) => {
+                self.buffer.push(KV_TAG_BOOL);
+                self.buffer.push(value as u8);
+            }
+            TypedValue::Text => {
+                self.buffer.push(KV_TAG_STRING);
+                push_length_prefixed(self.buffer, |buffer| {
+                    let _result = write!(buffer, "{}", value);
+                });
+            }
+        }
+        self.count += 1;
+        Ok(())
     }
+}
 
-    fn log(&self, record: &Record<'_>) {
-        if self.enabled(record.metadata()) {
-            let socket = self
-                .sources
-                .iter()
-                .find(|(level, _socket)| level >= &record.level())
-                .map(|(_level, socket)| socket)
-                .unwrap_or_else(|| &self.default_source);
+// Append a string of the ByteBuffer wire format, written by a closure, preceded by its u32
+// length, which is filled in once the string is written.
+fn push_length_prefixed(buffer: &mut Vec<u8>, write: impl FnOnce(&mut Vec<u8>)) {
+    let start = buffer.len();
+    buffer.extend_from_slice(&[0; 4]);
+    write(buffer);
+    let len = (buffer.len() - start - 4) as u32;
+    buffer[start..start + 4].copy_from_slice(&len.to_be_bytes());
+}
 
-            let remote_addr = self
-                .destinations
-                .iter()
-                .find(|(level, _socket)| level >= &record.level())
-                .map(|(_level, socket)| socket)
-                .unwrap_or_else(|| &self.default_destination);
+// The value of a kv pair, as one of the types the typed wire formats encode. Any other value
+// is encoded as its text.
+enum TypedValue {
+    I64(i64),
+    F64(f64),
+    Bool(bool),
+    Text,
+}
 
-            let target = if !record.target().is_empty() {
-                record.target()
-            } else {
-                record.module_path().unwrap_or_default()
-            };
-            let source = record.key_values();
-            let mut visitor = KVAccumulator::default();
-            let _result = source.visit(&mut visitor);
+impl TypedValue {
+    fn of(value: &Value<'_>) -> Self {
+        if let Some(value) = value.to_i64() {
+            TypedValue::I64(value)
+        } else if let Some(value) = value.to_f64() {
+            TypedValue::F64(value)
+        } else if let Some(value) = value.to_bool() {
+            TypedValue::Bool(value)
+        } else {
+            TypedValue::Text
+        }
+    }
+}
+
+// Encodes kv pairs as the repeated KeyValue kvs field of a protobuf LogRecord.
+#[cfg(feature = "protobuf")]
+#[derive(Default)]
+struct ProtobufKvs(Vec<u8>);
+
+#[cfg(feature = "protobuf")]
+impl<'kvs> Visitor<'kvs> for ProtobufKvs {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), Error> {
+        use prost::encoding;
+        let mut entry = Vec::new();
+        encoding::string::encode(1, &key.as_str().to_string(), &mut entry);
+        match TypedValue::of(&value) {
+            TypedValue::I64(value) => encoding::int64::encode(2, &value, &mut entry),
+            TypedValue::F64(value) => encoding::double::encode(3, &value, &mut entry),
+            TypedValue::Bool(value) => encoding::bool::encode(4, &value, &mut entry),
+            TypedValue::Text => encoding::string::encode(5, &value.to_string(), &mut entry),
+        }
+        encoding::encode_key(5, encoding::WireType::LengthDelimited, &mut self.0);
+        encoding::encode_varint(entry.len() as u64, &mut self.0);
+        self.0.extend_from_slice(&entry);
+        Ok(())
+    }
+}
 
-            let result = match self.wire_fmt {
+impl UdpLogger {
+    // Format a record, and send it to its destinations. The payload is formatted in buffers
+    // kept by the thread, so that logging doesn't allocate for each record.
+    fn emit(&self, record: &Record<'_>) {
+        let emitted = BUFFERS.try_with(|buffers| match buffers.try_borrow_mut() {
+            Ok(mut buffers) => {
+                self.emit_with(record, &mut buffers);
+                buffers.shrink();
+            }
+            // a record is logged while another is formatted, such as by an error handler
+            Err(_borrowed) => self.emit_with(record, &mut Buffers::default()),
+        });
+        // the thread's buffers are gone, as it is exiting
+        if emitted.is_err() {
+            self.emit_with(record, &mut Buffers::default());
+        }
+    }
+
+    // Format a record in buffers, and send it to its destinations.
+    fn emit_with(&self, record: &Record<'_>, buffers: &mut Buffers) {
+        let socket = self.source_for(record.level());
+
+        let target = if !record.target().is_empty() {
+            record.target()
+        } else {
+            record.module_path().unwrap_or_default()
+        };
+        let remote_addr = self
+            .target_destinations
+            .iter()
+            .find(|(name, _destination)| module_matches(target, name))
+            .map(|(_name, destination)| destination)
+            .or_else(|| exact_route(&self.exact_destinations, record.level()))
+            .or_else(|| route(&self.destinations, record.level()))
+            .unwrap_or(&self.default_destination);
+        let sequence = self
+            .sequence
+            .as_ref()
+            .map(|counter| counter.fetch_add(1, Ordering::Relaxed) + 1);
+        // read once, so that the record is formatted and sent in one wire format
+        let wire_fmt = match &self.dynamic_wire_fmt {
+            Some(wire_fmt) => *wire_fmt
+                .read()
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+            None => self.wire_fmt,
+        };
+        let Buffers { text, payload } = buffers;
+        text.clear();
+        payload.clear();
+        if let Some(tee) = self.tee {
+            self.write_uncompressed(record, target, sequence, text);
+            text.push('\n');
+            // a failure to copy the record locally shouldn't prevent it being sent
+            let _result = match tee {
+                Tee::Stdout => std::io::stdout().lock().write_all(text.as_bytes()),
+                Tee::Stderr => std::io::stderr().lock().write_all(text.as_bytes()),
+            };
+            text.clear();
+        }
+        let result = if let Some(formatter) = &self.formatter {
+            let mut kvs = KVStrings::default();
+            self.visit_kvs(record, &mut kvs);
+            payload.extend_from_slice(&(formatter.0)(record, &kvs.0));
+            Ok(())
+        } else {
+            match wire_fmt {
                 WireFmt::Uncompressed => {
-                    let payload = format!(
-                        "{} {:<5} [{}] {}{}",
-                        chrono::Utc::now().format("%Y-%m-%d %H:%M:%S%.3f"),
-                        record.level().to_string(),
-                        target,
-                        record.args(),
-                        visitor.0
+                    self.write_uncompressed(record, target, sequence, text);
+                    text.push_str(self.terminator.as_str());
+                    payload.extend_from_slice(text.as_bytes());
+                    Ok(())
+                }
+                #[cfg(feature = "syslog")]
+                WireFmt::Syslog5424 => {
+                    payload
+                        .extend_from_slice(self.syslog_5424(record, target, sequence).as_bytes());
+                    Ok(())
+                }
+                WireFmt::Logfmt => {
+                    payload.extend_from_slice(self.logfmt(record, target, sequence).as_bytes());
+                    Ok(())
+                }
+                #[cfg(feature = "gzip")]
+                WireFmt::GzipUncompressed(level) => {
+                    self.write_uncompressed(record, target, sequence, text);
+                    let mut encoder = flate2::write::GzEncoder::new(
+                        &mut *payload,
+                        flate2::Compression::new(level),
                     );
-                    socket.send_to(payload.as_bytes(), remote_addr)
+                    encoder
+                        .write_all(text.as_bytes())
+                        .and_then(|_| encoder.finish())
+                        .map(|_payload| ())
                 }
                 WireFmt::ByteBuffer => {
-                    let mut encoder = bytebuffer::ByteBuffer::new();
-                    let level: [u8; 1] = match record.level() {
-                        Level::Error => [1],
-                        Level::Warn => [2],
-                        Level::Info => [3],
-                        Level::Debug => [4],
-                        Level::Trace => [5],
-                    };
-                    let now = chrono::Utc::now().timestamp_millis().to_be_bytes();
-                    let text = format!("[{}] {}{}", target, record.args(), visitor.0);
-                    encoder
-                        .write(&level)
-                        .and_then(|_count| encoder.write(&now))
-                        .and_then(|_count| {
-                            encoder.write_string(&text);
-                            socket.send_to(&encoder.to_bytes(), remote_addr)
-                        })
+                    self.encode_byte_buffer(record, target, sequence, payload);
+                    Ok(())
+                }
+                WireFmt::ByteBufferBase64 => {
+                    self.encode_byte_buffer(record, target, sequence, payload);
+                    // the tag signs the ByteBuffer payload, and is encoded with it
+                    #[cfg(feature = "signing")]
+                    self.sign(payload, WireFmt::ByteBuffer);
+                    let _result = write!(text, "{}", Base64(payload));
+                    text.push('\n');
+                    payload.clear();
+                    payload.extend_from_slice(text.as_bytes());
+                    Ok(())
+                }
+                #[cfg(feature = "protobuf")]
+                WireFmt::Protobuf => {
+                    use prost::encoding;
+                    let mut kvs = ProtobufKvs::default();
+                    self.visit_fields(record, sequence, &mut kvs);
+                    if self.timestamps {
+                        encoding::int64::encode(1, &self.clock().now_millis(), payload);
+                    }
+                    encoding::int32::encode(2, &(record.level() as i32), payload);
+                    encoding::string::encode(3, &target.to_string(), payload);
+                    encoding::string::encode(4, &record.args().to_string(), payload);
+                    payload.extend_from_slice(&kvs.0);
+                    Ok(())
+                }
+            }
+        };
+        if let Err(err) = result {
+            return self.report_error(err);
+        }
+        #[cfg(feature = "signing")]
+        self.sign(payload, wire_fmt);
+        if self.null_sink {
+            return;
+        }
+        for destination in &self.additional_destinations {
+            if let Err(err) = self.send_payload(socket, destination, payload, wire_fmt) {
+                self.report_error(err);
+            }
+        }
+        if let Err(err) = self.send_payload(socket, remote_addr, payload, wire_fmt) {
+            self.report_error(err);
+        }
+    }
+
+    // A level, formatted per the level style, and colored, for Uncompressed payloads.
+    fn styled_level(&self, level: Level) -> String {
+        let name = if self.level_style.lowercase {
+            level.as_str().to_ascii_lowercase()
+        } else {
+            level.as_str().to_string()
+        };
+        let mut styled = format!("{:<width$}", name, width = self.level_style.width);
+        if let Some(colors) = &self.level_colors {
+            styled.insert_str(name.len(), "\x1b[0m");
+            styled.insert_str(0, &format!("\x1b[{}m", colors[level as usize - 1]));
+        }
+        styled
+    }
+
+    // Append the Uncompressed wire format of a record, without its terminator, to a line.
+    fn write_uncompressed(
+        &self,
+        record: &Record<'_>,
+        target: &str,
+        sequence: Option<u64>,
+        line: &mut String,
+    ) {
+        if let Some(sequence) = sequence {
+            let _result = write!(line, "#{} ", sequence);
+        }
+        if self.timestamps {
+            self.clock().write_now(&self.timestamp_format, line);
+            line.push(' ');
+        }
+        line.push_str(&self.level_names[record.level() as usize - 1]);
+        if let Some(hostname) = &self.hostname {
+            line.push(' ');
+            line.push_str(hostname);
+        }
+        let _result = match (&self.app_name, self.pid) {
+            (Some(app_name), Some(pid)) => write!(line, " {}[{}]", app_name, pid),
+            (Some(app_name), None) => write!(line, " {}", app_name),
+            (None, Some(pid)) => write!(line, " [{}]", pid),
+            (None, None) => Ok(()),
+        };
+        if let Some(thread) = self.thread_name() {
+            let _result = write!(line, " ({})", thread);
+        }
+        let _result = write!(line, " [{}] {}", target, record.args());
+        if let (true, Some(file), Some(number)) =
+            (self.source_location, record.file(), record.line())
+        {
+            let _result = write!(line, " ({}:{})", file, number);
+        }
+        let omitted = self.visit_limited_kvs(record, &mut KVAccumulator(line, self.kv_style));
+        if omitted > 0 {
+            let _result = write!(line, " ...(+{} more)", omitted);
+        }
+    }
+
+    // Append the ByteBuffer wire format of a record to a payload.
+    fn encode_byte_buffer(
+        &self,
+        record: &Record<'_>,
+        target: &str,
+        sequence: Option<u64>,
+        payload: &mut Vec<u8>,
+    ) {
+        let level = match record.level() {
+            Level::Error => self.level_codes[0],
+            Level::Warn => self.level_codes[1],
+            Level::Info => self.level_codes[2],
+            Level::Debug => self.level_codes[3],
+            Level::Trace => self.level_codes[4],
+        };
+        // the bytes logged by log_bytes() are the message, rather than their base64 text
+        let raw = RAW_MESSAGE.try_with(Cell::take).ok().flatten();
+        let version = match raw {
+            Some(_) => (
+                BYTE_BUFFER_VERSION_RAW,
+                BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP,
+            ),
+            None => (BYTE_BUFFER_VERSION, BYTE_BUFFER_VERSION_NO_TIMESTAMP),
+        };
+        payload.extend_from_slice(&BYTE_BUFFER_MAGIC);
+        if self.timestamps {
+            let now = self
+                .timestamp_precision
+                .unwrap_or(TimestampPrecision::Millis)
+                .now(&*self.clock());
+            payload.extend_from_slice(&[version.0, level]);
+            payload.extend_from_slice(&now.to_be_bytes());
+        } else {
+            payload.extend_from_slice(&[version.1, level]);
+        }
+        push_length_prefixed(payload, |payload| {
+            payload.extend_from_slice(target.as_bytes())
+        });
+        push_length_prefixed(payload, |payload| match &raw {
+            Some(bytes) => payload.extend_from_slice(bytes),
+            None => {
+                let _result = write!(payload, "{}", record.args());
+            }
+        });
+        // the count of kv pairs precedes them, and is filled in once they're encoded
+        let count_at = payload.len();
+        payload.extend_from_slice(&[0; 2]);
+        let mut kvs = KVEncoder {
+            count: 0,
+            buffer: &mut *payload,
+        };
+        self.visit_fields(record, sequence, &mut kvs);
+        let count = kvs.count;
+        payload[count_at..count_at + 2].copy_from_slice(&count.to_be_bytes());
+        if self.checksum {
+            let checksum = crc32(payload);
+            payload.extend_from_slice(&checksum.to_be_bytes());
+        }
+    }
+}
+
+impl Log for UdpLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        let level = metadata.level().to_level_filter();
+        // reject a record more verbose than any module, before matching its target
+        if level as usize > self.level_ceiling.load(Ordering::Relaxed) {
+            return false;
+        }
+        match &self.dynamic_levels {
+            Some(levels) => {
+                let levels = levels
+                    .read()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                level
+                    <= levels
+                        .module_level(metadata.target())
+                        .or_else(|| self.regex_level(metadata.target()))
+                        .unwrap_or(levels.default_level)
+            }
+            None => {
+                level
+                    <= module_level(&self.module_levels, metadata.target())
+                        .or_else(|| self.regex_level(metadata.target()))
+                        .unwrap_or(self.default_level)
+            }
+        }
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if self.enabled(record.metadata()) {
+            if let Some(sampler) = &self.sampling[record.level() as usize - 1] {
+                if !sampler.keep() {
+                    return;
+                }
+            }
+            if let Some(limiter) = &self.rate_limits[record.level() as usize - 1] {
+                match limiter.admit() {
+                    None => return self.counters.dropped(),
+                    Some(0) => (),
+                    Some(suppressed) => {
+                        // the summary isn't the bytes of a record logged by log_bytes()
+                        let raw = RAW_MESSAGE.try_with(Cell::take).ok().flatten();
+                        self.emit(
+                            &Record::builder()
+                                .level(record.level())
+                                .target(record.target())
+                                .module_path(record.module_path())
+                                .args(format_args!("{} messages suppressed", suppressed))
+                                .build(),
+                        );
+                        let _result = RAW_MESSAGE.try_with(|current| current.set(raw));
+                    }
+                }
+            }
+            self.emit(record);
+        }
+    }
+
+    fn flush(&self) {
+        let deadline = Instant::now() + FLUSH_TIMEOUT;
+        #[cfg(feature = "tokio")]
+        if let Some(sender) = &self.tokio_sender {
+            sender.flush(deadline);
+        }
+        if let Some(sender) = &self.async_sender {
+            sender.flush(deadline);
+        }
+        let mut pending = self
+            .pending
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        while let Some(entry) = pending.front() {
+            match entry.socket.send_to(&entry.payload, &entry.destination) {
+                Err(err)
+                    if err.kind() == std::io::ErrorKind::WouldBlock
+                        && Instant::now() < deadline =>
+                {
+                    std::thread::sleep(Duration::from_millis(1))
+                }
+                Ok(count) => {
+                    self.counters.sent(count);
+                    pending.pop_front();
                 }
-            };
-            match result {
-                Ok(_) => (),
                 Err(err) => {
-                    println!("error sending payload, err={}", err)
+                    self.recover(&entry.socket, &err);
+                    pending.pop_front();
+                    self.report_error(err);
                 }
-            };
+            }
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    fn flush(&self) {}
+    //
+    // This tests that the time-to-live, and multicast time-to-live, are set on the source
+    // socket, which isn't otherwise observable.
+    #[test]
+    fn ttl() {
+        let logger = UdpLogger::default()
+            .with_ephemeral_source()
+            .with_ttl(8)
+            .with_multicast_ttl(4)
+            .build()
+            .expect("logger");
+        let source = match &*logger.default_source {
+            Socket::Udp(source) => source,
+            other => panic!("expected a UDP source, got {:?}", other),
+        };
+        assert_eq!(source.with(|socket| socket.ttl()).expect("ttl"), 8);
+        assert_eq!(
+            source
+                .with(|socket| socket.multicast_ttl_v4())
+                .expect("multicast ttl"),
+            4
+        );
+    }
 }
@@ -0,0 +1,87 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use udp_logger_rs::{info, WireFmt};
+
+// An allocator, counting allocations, so that the allocations of formatting can be measured.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+// Tests count the allocations of the process, and bind the same address, so they run serially.
+static SERIAL: Mutex<()> = Mutex::new(());
+
+fn serialize() -> MutexGuard<'static, ()> {
+    SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+// The allocations of formatting a record with a number of kv pairs.
+fn allocations(logger: &udp_logger_rs::UdpLogger, pairs: usize) -> usize {
+    let kvs: Vec<(&str, log::kv::Value<'_>)> = (0..pairs)
+        .map(|index| ("key", log::kv::Value::from(index as u64)))
+        .collect();
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    info!(logger: logger, target: "MyApp", kvs: &kvs, "allocations");
+    ALLOCATIONS.load(Ordering::Relaxed) - before
+}
+
+//
+// This tests that formatting kv pairs doesn't allocate for each pair.
+#[test]
+fn kv_pair_allocations() {
+    let _serial = serialize();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_null_sink()
+        .build()
+        .expect("logger");
+    // the thread's buffers grow to fit the first records
+    allocations(&logger, 20);
+    let base = allocations(&logger, 0);
+    let with_pairs = allocations(&logger, 20);
+    assert!(
+        with_pairs <= base,
+        "{} allocations formatting 20 kv pairs, {} without",
+        with_pairs,
+        base
+    );
+}
+
+//
+// This tests that, once the thread's buffers have grown, formatting a record doesn't allocate.
+// Timestamps are omitted, as chrono formats them in a string of its own.
+#[test]
+fn steady_state_allocations() {
+    let _serial = serialize();
+    for wire_fmt in [WireFmt::Uncompressed, WireFmt::ByteBuffer] {
+        let logger = udp_logger_rs::UdpLogger::default()
+            .with_wire_fmt(wire_fmt)
+            .without_timestamp()
+            .with_null_sink()
+            .build()
+            .expect("logger");
+        allocations(&logger, 20);
+        let count: usize = (0..100).map(|_| allocations(&logger, 20)).sum();
+        assert_eq!(count, 0, "allocations logging 100 records");
+    }
+}
@@ -23,7 +23,7 @@ impl log::Log for ProxyLogger {
         if let Some(logger) = &*guard {
             return logger.enabled(metadata);
         }
-        return false;
+        false
     }
     fn log(&self, record: &log::Record<'_>) {
         let guard = self.logger.lock().unwrap();
@@ -31,11 +31,27 @@ impl log::Log for ProxyLogger {
             logger.log(record);
         }
     }
-    fn flush(&self) {}
+    fn flush(&self) {
+        let guard = self.logger.lock().unwrap();
+        if let Some(logger) = &*guard {
+            logger.flush();
+        }
+    }
 }
 
 lazy_static! {
     static ref PROXY_LOGGER: ProxyLogger = ProxyLogger::default();
+    static ref SERIAL: std::sync::Mutex<()> = std::sync::Mutex::new(());
+}
+
+// Tests share the proxy logger and the default source port, so they take turns, each
+// starting with the previous test's logger (and its sockets) dropped.
+fn serialize() -> std::sync::MutexGuard<'static, ()> {
+    let guard = SERIAL
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    *PROXY_LOGGER.logger.lock().unwrap() = None;
+    guard
 }
 
 #[derive(Default)]
@@ -54,7 +70,7 @@ impl UdpClient {
         assert_eq!(source, src_addr);
         assert!(byte_count > 0);
         let filled_buf = &mut buf[..byte_count];
-        let str = std::str::from_utf8(&filled_buf).unwrap();
+        let str = std::str::from_utf8(filled_buf).unwrap();
         let (time, contents) = str.split_at(23);
         //println!("time={} contents={}", time, contents);
         // 2021-08-09 18:41:50.336 INFO  [test] logging Info w/ kv as HashMap key1=Value1 Key2=Value2
@@ -63,7 +79,7 @@ impl UdpClient {
         if !cmp_result {
             println!("mismatch got={}", contents);
         }
-        return parse_result.is_ok() && cmp_result;
+        parse_result.is_ok() && cmp_result
     }
 }
 
@@ -71,6 +87,7 @@ impl UdpClient {
 // This is a basic test which confirms default logging is going out properly.
 #[test]
 fn test_macro() {
+    let _serial = serialize();
     let _result = log::set_logger(PROXY_LOGGER.log_interface());
     let udp_logger = udp_logger_rs::UdpLogger::default();
     PROXY_LOGGER.set_logger(udp_logger);
@@ -91,7 +108,7 @@ fn test_macro() {
     assert!(byte_count > 0);
 
     let filled_buf = &mut buf[..byte_count];
-    let str = std::str::from_utf8(&filled_buf).unwrap();
+    let str = std::str::from_utf8(filled_buf).unwrap();
     let (time, ctx) = str.split_at(23);
     // 2021-08-09 18:41:50.336 INFO  [test] logging Info w/ kv as HashMap key1=Value1 Key2=Value2
     let _dt = chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.3f").unwrap();
@@ -119,6 +136,7 @@ fn test_macro() {
 // honors those changes.
 #[test]
 fn non_default_log() {
+    let _serial = serialize();
     let _result = log::set_logger(PROXY_LOGGER.log_interface());
     let udp_logger = udp_logger_rs::UdpLogger::default()
         .with_source("127.0.0.1:4040")
@@ -134,9 +152,7 @@ fn non_default_log() {
         ("key1".into(), "Value1".into()),
         ("Key2".into(), "Value2".into()),
     ];
-    let mut test = TestLogContext::default();
-    test.logctx = kvs;
-    let test = test;
+    let test = TestLogContext { logctx: kvs };
 
     log!(target: "MyApp", Level::Error, "logging Error w/ target");
     assert!(UdpClient::pkt_eq(
@@ -186,6 +202,7 @@ fn non_default_log() {
 // the level, the correct source and destiation are used in sending.
 #[test]
 fn multi_socket() {
+    let _serial = serialize();
     let _result = log::set_logger(PROXY_LOGGER.log_interface());
     let udp_logger = udp_logger_rs::UdpLogger::default()
         .with_source("127.0.0.1:4060")
@@ -209,9 +226,7 @@ fn multi_socket() {
         ("key1".into(), "Value1".into()),
         ("Key2".into(), "Value2".into()),
     ];
-    let mut test = TestLogContext::default();
-    test.logctx = kvs;
-    let test = test;
+    let test = TestLogContext { logctx: kvs };
 
     trace!(kvs: &test.logctx, "trace logging w/ kv");
     assert!(UdpClient::pkt_eq(
@@ -248,3 +263,3460 @@ fn multi_socket() {
         " ERROR [MyApp] error logging w/ target and kv key1=Value1 Key2=Value2"
     ));
 }
+
+//
+// This tests that a failure to send is reported to the error handler, rather than printed.
+#[test]
+fn error_handler() {
+    let _serial = serialize();
+    let errors = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = errors.clone();
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4080")
+        .with_destination("[::1]:4081")
+        .with_error_handler(Box::new(move |_err| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }))
+        .partial_init();
+
+    log::Log::log(
+        &udp_logger,
+        &log::Record::builder()
+            .level(Level::Error)
+            .target("test")
+            .args(format_args!("unsendable"))
+            .build(),
+    );
+    assert_eq!(errors.load(std::sync::atomic::Ordering::SeqCst), 1);
+    assert_eq!(udp_logger.stats().dropped, 1);
+    assert_eq!(udp_logger.stats().sent, 0);
+}
+
+//
+// This tests that the gzip wire format decompresses to the uncompressed payload.
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_wire_fmt() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4090")
+        .with_destination("127.0.0.1:4091")
+        .with_wire_fmt(udp_logger_rs::WireFmt::GzipUncompressed(6));
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4091").expect("unable to bind");
+    socket
+        .set_nonblocking(true)
+        .expect("unable to set nonblocking");
+
+    warn!(target: "MyApp", "compressed logging w/ target");
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+
+    let mut decoder = flate2::read::GzDecoder::new(&buf[..byte_count]);
+    let mut payload = String::new();
+    std::io::Read::read_to_string(&mut decoder, &mut payload).expect("gzip payload");
+    let (time, contents) = payload.split_at(23);
+    assert!(chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S%.3f").is_ok());
+    assert_eq!(contents, " WARN  [MyApp] compressed logging w/ target");
+}
+
+//
+// This tests that once flushed, a logged message has been delivered.
+#[test]
+fn flush_delivers() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4100")
+        .with_destination("127.0.0.1:4101");
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4101").expect("unable to bind");
+    socket
+        .set_nonblocking(true)
+        .expect("unable to set nonblocking");
+
+    error!(target: "MyApp", "logging Error before exit");
+    log::logger().flush();
+
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    let payload = std::str::from_utf8(&buf[..byte_count]).unwrap();
+    assert!(payload.ends_with(" ERROR [MyApp] logging Error before exit"));
+}
+
+//
+// This tests that the timestamp format can be overridden.
+#[test]
+fn timestamp_format() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4110")
+        .with_destination("127.0.0.1:4111")
+        .with_timestamp_format("%Y-%m-%dT%H:%M:%S%.6f%z");
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4111").expect("unable to bind");
+    socket
+        .set_nonblocking(true)
+        .expect("unable to set nonblocking");
+
+    info!(target: "MyApp", "logging Info w/ RFC3339 timestamp");
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    let payload = std::str::from_utf8(&buf[..byte_count]).unwrap();
+    // 2021-08-09T18:41:50.336123+0000 INFO  [MyApp] logging Info w/ RFC3339 timestamp
+    let (time, contents) = payload.split_at(31);
+    assert!(chrono::DateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S%.6f%z").is_ok());
+    assert_eq!(contents, " INFO  [MyApp] logging Info w/ RFC3339 timestamp");
+}
+
+//
+// This tests that, with local time, timestamps carry the local offset, rather than UTC's.
+#[test]
+fn local_time() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_local_time()
+        .with_timestamp_format("%z")
+        .build()
+        .expect("logger");
+
+    info!(logger: &logger, target: "MyApp", "logging Info w/ local offset");
+    let offset = chrono::Local::now().offset().local_minus_utc();
+    let expected = format!(
+        "{}{:02}{:02} INFO  [MyApp] logging Info w/ local offset",
+        if offset < 0 { '-' } else { '+' },
+        offset.abs() / 3600,
+        offset.abs() / 60 % 60
+    );
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(std::str::from_utf8(&payloads[0].0).unwrap(), expected);
+}
+
+//
+// This tests that module levels apply to the module and its sub-modules, but not to modules
+// which merely share a prefix.
+#[test]
+fn module_boundaries() {
+    let _serial = serialize();
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4120")
+        .with_level(udp_logger_rs::LevelFilter::Off)
+        .with_module_level("foo", udp_logger_rs::LevelFilter::Info)
+        .partial_init();
+    let enabled = |target: &str| {
+        log::Log::enabled(
+            &udp_logger,
+            &log::Metadata::builder()
+                .level(Level::Info)
+                .target(target)
+                .build(),
+        )
+    };
+
+    assert!(enabled("foo"));
+    assert!(enabled("foo::bar"));
+    assert!(!enabled("foobar"));
+    assert!(!enabled("foobar::foo"));
+}
+
+//
+// This tests that RUST_LOG directives set both the default level and module levels.
+#[test]
+fn env_directives() {
+    let _serial = serialize();
+    std::env::set_var(
+        "RUST_LOG",
+        "warn,myapp::net=trace,malformed=loud,,bare::module",
+    );
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4130")
+        .env()
+        .partial_init();
+    std::env::remove_var("RUST_LOG");
+    let enabled = |target: &str, level: Level| {
+        log::Log::enabled(
+            &udp_logger,
+            &log::Metadata::builder().level(level).target(target).build(),
+        )
+    };
+
+    assert!(enabled("myapp", Level::Warn));
+    assert!(!enabled("myapp", Level::Info));
+    assert!(enabled("myapp::net", Level::Trace));
+    assert!(!enabled("malformed", Level::Info));
+    assert!(enabled("bare::module", Level::Trace));
+    assert!(!enabled("bare", Level::Info));
+}
+
+// A clock which is stuck at the epoch.
+#[derive(Debug)]
+struct EpochClock;
+
+impl udp_logger_rs::Clock for EpochClock {
+    fn now_millis(&self) -> i64 {
+        0
+    }
+    fn format_now(&self, fmt: &str) -> String {
+        chrono::DateTime::<chrono::Utc>::from_timestamp(0, 0)
+            .unwrap()
+            .format(fmt)
+            .to_string()
+    }
+}
+
+//
+// This tests that a provided clock is used for timestamps, allowing exact payloads.
+#[test]
+fn clock() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4140")
+        .with_destination("127.0.0.1:4141")
+        .with_clock(std::sync::Arc::new(EpochClock));
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4141").expect("unable to bind");
+
+    info!(target: "MyApp", "logging Info at the epoch");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] logging Info at the epoch"
+    );
+}
+
+//
+// This tests that the ByteBuffer wire format carries kv pairs with their types.
+#[test]
+fn byte_buffer_kvs() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4150")
+        .with_destination("127.0.0.1:4151")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4151").expect("unable to bind");
+
+    let kvs: [(&str, log::kv::Value<'_>); 4] = [
+        ("count", log::kv::Value::from(-3i64)),
+        ("ratio", log::kv::Value::from(0.5f64)),
+        ("ok", log::kv::Value::from(true)),
+        ("name", log::kv::Value::from("nori")),
+    ];
+    info!(target: "MyApp", kvs: &kvs, "typed kvs");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+
+    let mut expected: Vec<u8> = vec![0x55, 0x4C, udp_logger_rs::BYTE_BUFFER_VERSION, 3];
+    expected.extend_from_slice(&0i64.to_be_bytes());
+    for text in ["MyApp", "typed kvs"] {
+        expected.extend_from_slice(&(text.len() as u32).to_be_bytes());
+        expected.extend_from_slice(text.as_bytes());
+    }
+    expected.extend_from_slice(&4u16.to_be_bytes());
+    let key = |expected: &mut Vec<u8>, key: &str, tag: u8| {
+        expected.extend_from_slice(&(key.len() as u32).to_be_bytes());
+        expected.extend_from_slice(key.as_bytes());
+        expected.push(tag);
+    };
+    key(&mut expected, "count", 1);
+    expected.extend_from_slice(&(-3i64).to_be_bytes());
+    key(&mut expected, "ratio", 2);
+    expected.extend_from_slice(&0.5f64.to_bits().to_be_bytes());
+    key(&mut expected, "ok", 3);
+    expected.push(1);
+    key(&mut expected, "name", 4);
+    expected.extend_from_slice(&4u32.to_be_bytes());
+    expected.extend_from_slice(b"nori");
+    assert_eq!(&buf[..byte_count], &expected[..]);
+}
+
+//
+// This tests that a ByteBuffer checksum matches the payload, and detects a damaged payload.
+#[test]
+fn byte_buffer_checksum() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4160")
+        .with_destination("127.0.0.1:4161")
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .with_checksum(true);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4161").expect("unable to bind");
+
+    error!(target: "MyApp", "checksummed logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+
+    let (payload, checksum) = buf[..byte_count].split_at_mut(byte_count - 4);
+    let checksum = u32::from_be_bytes([checksum[0], checksum[1], checksum[2], checksum[3]]);
+    assert_eq!(udp_logger_rs::crc32(payload), checksum);
+    payload[4] ^= 0x01;
+    assert_ne!(udp_logger_rs::crc32(payload), checksum);
+}
+
+//
+// This tests that an oversized Uncompressed payload is truncated to fit in a datagram.
+#[test]
+fn oversize_truncate() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4170")
+        .with_destination("127.0.0.1:4171")
+        .with_oversize_policy(udp_logger_rs::OversizePolicy::Truncate);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4171").expect("unable to bind");
+
+    info!(target: "MyApp", "{}", "x".repeat(70_000));
+    log::logger().flush();
+    let mut buf = vec![0; 70_000];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(byte_count, 65_507);
+    assert!(buf[..byte_count].ends_with(b"xxx...[truncated]"));
+}
+
+//
+// This tests that a payload split into fragments can be reassembled.
+#[test]
+fn oversize_fragment() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4180")
+        .with_destination("127.0.0.1:4181")
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .with_oversize_policy(udp_logger_rs::OversizePolicy::Fragment)
+        .with_fragment_size(64);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4181").expect("unable to bind");
+
+    // 22 bytes of header, lengths and kv count, and 108 bytes of text, make 3 fragments of 54
+    // bytes
+    let message = "y".repeat(103);
+    info!(target: "MyApp", "{}", message);
+    log::logger().flush();
+
+    let mut payload = Vec::new();
+    let mut message_id = None;
+    for index in 0..3u16 {
+        let mut buf = [0; 4096];
+        let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+        assert!(byte_count <= 64);
+        assert_eq!(&buf[..2], &[0x55, 0x46]);
+        let id = u32::from_be_bytes([buf[2], buf[3], buf[4], buf[5]]);
+        assert_eq!(*message_id.get_or_insert(id), id);
+        assert_eq!(u16::from_be_bytes([buf[6], buf[7]]), index);
+        assert_eq!(u16::from_be_bytes([buf[8], buf[9]]), 3);
+        payload.extend_from_slice(&buf[10..byte_count]);
+    }
+
+    assert_eq!(payload.len(), 130);
+    assert_eq!(&payload[..2], &[0x55, 0x4C]);
+    assert_eq!(&payload[16..21], b"MyApp");
+    assert_eq!(&payload[25..128], message.as_bytes());
+}
+
+//
+// This tests that logging through the background sender is delivered once flushed.
+#[test]
+fn async_sender() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4190")
+        .with_destination("127.0.0.1:4191")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_async_sender(16);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4191").expect("unable to bind");
+
+    for count in 0..3 {
+        info!(target: "MyApp", "background logging {}", count);
+    }
+    log::logger().flush();
+    for count in 0..3 {
+        let mut buf = [0; 4096];
+        let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+        assert_eq!(
+            std::str::from_utf8(&buf[..byte_count]).unwrap(),
+            format!(
+                "1970-01-01 00:00:00.000 INFO  [MyApp] background logging {}",
+                count
+            )
+        );
+    }
+}
+
+//
+// This tests that levels can be changed through the handle, once the logger is initialized.
+#[test]
+fn handle_levels() {
+    let _serial = serialize();
+    let (udp_logger, handle) = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4200")
+        .with_level(udp_logger_rs::LevelFilter::Info)
+        .partial_init_with_handle();
+    let enabled = |target: &str, level: Level| {
+        log::Log::enabled(
+            &udp_logger,
+            &log::Metadata::builder().level(level).target(target).build(),
+        )
+    };
+
+    assert!(!enabled("myapp::net", Level::Debug));
+    handle.set_module_level("myapp::net", udp_logger_rs::LevelFilter::Debug);
+    assert!(enabled("myapp::net", Level::Debug));
+    assert!(!enabled("myapp", Level::Debug));
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Debug);
+
+    handle.set_level(udp_logger_rs::LevelFilter::Error);
+    assert!(!enabled("myapp", Level::Warn));
+    handle.set_module_level("myapp::net", udp_logger_rs::LevelFilter::Off);
+    assert!(!enabled("myapp::net", Level::Error));
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Error);
+}
+
+//
+// This tests that raising, then lowering, a module level restores the max level, so that
+// records of the raised level are rejected by the log macros once more.
+#[test]
+fn handle_max_level_decreases() {
+    let _serial = serialize();
+    let (udp_logger, handle) = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(MemoryTransport::default()))
+        .with_level(udp_logger_rs::LevelFilter::Warn)
+        .with_module_level("myapp", udp_logger_rs::LevelFilter::Info)
+        .partial_init_with_handle();
+    let enabled = |target: &str, level: Level| {
+        log::Log::enabled(
+            &udp_logger,
+            &log::Metadata::builder().level(level).target(target).build(),
+        )
+    };
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Info);
+
+    handle.set_module_level("myapp::db", udp_logger_rs::LevelFilter::Trace);
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Trace);
+    handle.set_module_level("myapp::db", udp_logger_rs::LevelFilter::Error);
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Info);
+    assert!(!enabled("myapp::db", Level::Info));
+    assert!(enabled("myapp::db", Level::Error));
+    assert!(enabled("myapp::net", Level::Info));
+
+    handle.set_module_level("myapp", udp_logger_rs::LevelFilter::Off);
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Warn);
+}
+
+//
+// This tests that a module level can be raised through the handle, and then cleared,
+// restoring the level of its parent module, or the level the module was built with.
+#[test]
+fn handle_clear_module_level() {
+    let _serial = serialize();
+    let (udp_logger, handle) = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(MemoryTransport::default()))
+        .with_level(udp_logger_rs::LevelFilter::Info)
+        .with_module_level("myapp", udp_logger_rs::LevelFilter::Warn)
+        .partial_init_with_handle();
+    let enabled = |target: &str, level: Level| {
+        log::Log::enabled(
+            &udp_logger,
+            &log::Metadata::builder().level(level).target(target).build(),
+        )
+    };
+
+    handle.set_module_level("myapp::db", udp_logger_rs::LevelFilter::Trace);
+    assert!(enabled("myapp::db::pool", Level::Trace));
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Trace);
+
+    handle.clear_module_level("myapp::db");
+    assert!(!enabled("myapp::db::pool", Level::Info));
+    assert!(enabled("myapp::db::pool", Level::Warn));
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Info);
+
+    handle.set_module_level("myapp", udp_logger_rs::LevelFilter::Trace);
+    assert!(enabled("myapp::db", Level::Trace));
+    handle.clear_module_level("myapp");
+    assert!(!enabled("myapp::db", Level::Info));
+    assert!(enabled("myapp::db", Level::Warn));
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Info);
+}
+
+//
+// This tests that the Syslog5424 wire format produces an RFC 5424 message.
+#[cfg(feature = "syslog")]
+#[test]
+fn syslog_5424_wire_fmt() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4210")
+        .with_destination("127.0.0.1:4211")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_wire_fmt(udp_logger_rs::WireFmt::Syslog5424)
+        .with_facility(udp_logger_rs::Facility::Local0)
+        .with_hostname("host")
+        .with_app_name("app");
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4211").expect("unable to bind");
+
+    let kvs: std::vec::Vec<(String, String)> = vec![
+        ("cat".into(), "nori".into()),
+        ("quote".into(), "say \"hi\"".into()),
+    ];
+    warn!(target: "MyApp", kvs: &kvs, "syslog logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        format!(
+            "<132>1 1970-01-01T00:00:00.000000+00:00 host app {} - [kv@32473 cat=\"nori\" quote=\"say \\\"hi\\\"\"] [MyApp] syslog logging",
+            std::process::id()
+        )
+    );
+}
+
+//
+// This tests that the Logfmt wire format quotes and escapes values.
+#[test]
+fn logfmt_wire_fmt() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4220")
+        .with_destination("127.0.0.1:4221")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_timestamp_format("%Y-%m-%dT%H:%M:%SZ")
+        .with_wire_fmt(udp_logger_rs::WireFmt::Logfmt);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4221").expect("unable to bind");
+
+    let kvs: std::vec::Vec<(String, String)> = vec![
+        ("cat".into(), "nori".into()),
+        ("quote".into(), "say \"hi\"".into()),
+        ("eq".into(), "a=b".into()),
+    ];
+    info!(target: "MyApp", kvs: &kvs, "logfmt logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "ts=1970-01-01T00:00:00Z level=info target=MyApp msg=\"logfmt logging\" cat=nori quote=\"say \\\"hi\\\"\" eq=\"a=b\""
+    );
+}
+
+//
+// This tests that the hostname follows the level of Uncompressed payloads.
+#[test]
+fn hostname() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4230")
+        .with_destination("127.0.0.1:4231")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_hostname("web-1");
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4231").expect("unable to bind");
+
+    info!(target: "MyApp", "hostname logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  web-1 [MyApp] hostname logging"
+    );
+}
+
+//
+// This tests that the app name and process id follow the level of Uncompressed payloads.
+#[test]
+fn app_name_and_pid() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4240")
+        .with_destination("127.0.0.1:4241")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_hostname("web-1")
+        .with_app_name("svc")
+        .with_pid();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4241").expect("unable to bind");
+
+    info!(target: "MyApp", "pid logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        format!(
+            "1970-01-01 00:00:00.000 INFO  web-1 svc[{}] [MyApp] pid logging",
+            std::process::id()
+        )
+    );
+}
+
+//
+// This tests that the thread name follows the level of Uncompressed payloads.
+#[test]
+fn thread_names() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4250")
+        .with_destination("127.0.0.1:4251")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_thread_names();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4251").expect("unable to bind");
+
+    std::thread::Builder::new()
+        .name("worker".to_string())
+        .spawn(|| info!(target: "MyApp", "thread logging"))
+        .expect("spawn")
+        .join()
+        .expect("join");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  (worker) [MyApp] thread logging"
+    );
+}
+
+//
+// This tests that the source location follows the message of Uncompressed payloads.
+#[test]
+fn source_location() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4260")
+        .with_destination("127.0.0.1:4261")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_source_location();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4261").expect("unable to bind");
+
+    let line = line!() + 1;
+    info!(target: "MyApp", "location logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        format!(
+            "1970-01-01 00:00:00.000 INFO  [MyApp] location logging ({}:{})",
+            file!(),
+            line
+        )
+    );
+}
+
+//
+// This tests that copying records to stderr doesn't change what is sent.
+#[test]
+fn stderr_tee() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4402")
+        .with_destination("127.0.0.1:4403")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_stderr_tee();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4403").expect("unable to bind");
+
+    info!(target: "MyApp", "tee logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] tee logging"
+    );
+}
+
+//
+// This tests that sorted kv pairs are formatted in a single, canonical order.
+#[test]
+fn sorted_kv() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4270")
+        .with_destination("127.0.0.1:4271")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_sorted_kv();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4271").expect("unable to bind");
+
+    let mut kvs: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    kvs.insert("key1".into(), "Value1".into());
+    kvs.insert("Key2".into(), "Value2".into());
+    kvs.insert("key0".into(), "Value0".into());
+    info!(target: "MyApp", kvs: &kvs, "sorted logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] sorted logging Key2=Value2 key0=Value0 key1=Value1"
+    );
+}
+
+//
+// This tests that kv values of Uncompressed payloads are quoted, and can be parsed back.
+#[test]
+fn escaped_kv() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4280")
+        .with_destination("127.0.0.1:4281")
+        .with_clock(std::sync::Arc::new(EpochClock));
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4281").expect("unable to bind");
+
+    let kvs: std::vec::Vec<(String, String)> = vec![
+        ("note".into(), "hello \"big\" world".into()),
+        ("my key".into(), "a=b".into()),
+    ];
+    info!(target: "MyApp", kvs: &kvs, "escaped logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    let payload = std::str::from_utf8(&buf[..byte_count]).unwrap();
+    assert_eq!(
+        payload,
+        "1970-01-01 00:00:00.000 INFO  [MyApp] escaped logging note=\"hello \\\"big\\\" world\" my_key=\"a=b\""
+    );
+
+    let quoted = payload
+        .split_once("note=\"")
+        .and_then(|(_, rest)| rest.split_once("\" my_key="))
+        .map(|(value, _)| value)
+        .expect("quoted note");
+    assert_eq!(quoted.replace("\\\"", "\""), "hello \"big\" world");
+}
+
+//
+// This tests that kv pairs are appended with the separators of the KvStyle.
+#[test]
+fn kv_style() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4290")
+        .with_destination("127.0.0.1:4291")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_kv_style(udp_logger_rs::KvStyle {
+            pair_sep: "\t",
+            kv_sep: ":",
+        });
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4291").expect("unable to bind");
+
+    let kvs: std::vec::Vec<(String, String)> = vec![
+        ("key1".into(), "Value1".into()),
+        ("url".into(), "http://host".into()),
+    ];
+    info!(target: "MyApp", kvs: &kvs, "style logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] style logging\tkey1:Value1\turl:\"http://host\""
+    );
+}
+
+//
+// This tests that global fields precede kv pairs, which take precedence over them.
+#[test]
+fn global_fields() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4300")
+        .with_destination("127.0.0.1:4301")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_global_field("service", "checkout")
+        .with_global_field("env", "prod");
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4301").expect("unable to bind");
+
+    let kvs: std::vec::Vec<(String, String)> = vec![
+        ("key1".into(), "Value1".into()),
+        ("env".into(), "test".into()),
+    ];
+    info!(target: "MyApp", kvs: &kvs, "global logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] global logging service=checkout key1=Value1 env=test"
+    );
+}
+
+//
+// This tests that context fields follow global fields, and precede kv pairs.
+#[test]
+fn context_fields() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4310")
+        .with_destination("127.0.0.1:4311")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_global_field("service", "checkout")
+        .with_global_field("user", "nobody");
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4311").expect("unable to bind");
+
+    let kvs: std::vec::Vec<(String, String)> = vec![("key1".into(), "Value1".into())];
+    {
+        let _context = udp_logger_rs::UdpLogger::scoped_context("request_id", "42");
+        udp_logger_rs::UdpLogger::push_context("user", "bruce");
+        info!(target: "MyApp", kvs: &kvs, "context logging");
+        assert_eq!(
+            udp_logger_rs::UdpLogger::pop_context(),
+            Some(("user".to_string(), "bruce".to_string()))
+        );
+    }
+    info!(target: "MyApp", "no context logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] context logging service=checkout request_id=\"42\" user=bruce key1=Value1"
+    );
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] no context logging service=checkout user=nobody"
+    );
+}
+
+//
+// This tests that log messages are sent to every additional destination.
+#[test]
+fn additional_destinations() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4320")
+        .with_destination("127.0.0.1:4321")
+        .with_additional_destination("127.0.0.1:4322")
+        .with_additional_destination("127.0.0.1:4323")
+        .with_clock(std::sync::Arc::new(EpochClock));
+    let stats = udp_logger.stats_handle();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let sockets: Vec<std::net::UdpSocket> = ["127.0.0.1:4321", "127.0.0.1:4322", "127.0.0.1:4323"]
+        .iter()
+        .map(|addr| std::net::UdpSocket::bind(addr).expect("unable to bind"))
+        .collect();
+
+    info!(target: "MyApp", "fan out logging");
+    log::logger().flush();
+    for socket in &sockets {
+        let mut buf = [0; 4096];
+        let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+        assert_eq!(
+            std::str::from_utf8(&buf[..byte_count]).unwrap(),
+            "1970-01-01 00:00:00.000 INFO  [MyApp] fan out logging"
+        );
+    }
+    assert_eq!(stats.stats().sent, 3);
+}
+
+//
+// This tests that log messages are routed by the least verbose level which admits them.
+#[test]
+fn level_routing() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4332")
+        .with_source_level("127.0.0.1:4331", log::LevelFilter::Info)
+        .with_source_level("127.0.0.1:4330", log::LevelFilter::Error)
+        .with_destination("127.0.0.1:4335")
+        .with_destination_level("127.0.0.1:4334", log::LevelFilter::Debug)
+        .with_destination_level("127.0.0.1:4333", log::LevelFilter::Warn);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let sockets: Vec<std::net::UdpSocket> = ["127.0.0.1:4333", "127.0.0.1:4334", "127.0.0.1:4335"]
+        .iter()
+        .map(|addr| std::net::UdpSocket::bind(addr).expect("unable to bind"))
+        .collect();
+
+    let expected = [
+        (Level::Error, "127.0.0.1:4330", 0),
+        (Level::Warn, "127.0.0.1:4331", 0),
+        (Level::Info, "127.0.0.1:4331", 1),
+        (Level::Debug, "127.0.0.1:4332", 1),
+        (Level::Trace, "127.0.0.1:4332", 2),
+    ];
+    for (level, source, destination) in expected.iter() {
+        log!(*level, "routed logging");
+        log::logger().flush();
+        let mut buf = [0; 4096];
+        let (_byte_count, src_addr) = sockets[*destination]
+            .recv_from(&mut buf)
+            .expect("udp datagram");
+        assert_eq!(src_addr.to_string(), *source, "source of {}", level);
+    }
+}
+
+//
+// This tests that log messages are routed by target before level.
+#[test]
+fn target_routing() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4340")
+        .with_destination("127.0.0.1:4341")
+        .with_destination_level("127.0.0.1:4342", log::LevelFilter::Error)
+        .with_target_destination("audit", "127.0.0.1:4343")
+        .with_target_destination("audit::login", "127.0.0.1:4344");
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let sockets: Vec<std::net::UdpSocket> = [
+        "127.0.0.1:4341",
+        "127.0.0.1:4342",
+        "127.0.0.1:4343",
+        "127.0.0.1:4344",
+    ]
+    .iter()
+    .map(|addr| std::net::UdpSocket::bind(addr).expect("unable to bind"))
+    .collect();
+
+    let expected = [
+        ("audit", Level::Error, 2),
+        ("audit::logout", Level::Info, 2),
+        ("audit::login::failed", Level::Warn, 3),
+        ("auditor", Level::Error, 1),
+        ("app", Level::Info, 0),
+    ];
+    for (target, level, destination) in expected.iter() {
+        log!(target: target, *level, "routed logging");
+        log::logger().flush();
+        let mut buf = [0; 4096];
+        let (byte_count, _src_addr) = sockets[*destination]
+            .recv_from(&mut buf)
+            .expect("udp datagram");
+        let payload = std::str::from_utf8(&buf[..byte_count]).unwrap();
+        assert!(payload.ends_with(&format!("[{}] routed logging", target)));
+    }
+}
+
+//
+// This tests logging to a Unix domain datagram socket.
+#[cfg(unix)]
+#[test]
+fn unix_destination() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let path = std::env::temp_dir().join(format!("udp-logger-{}.sock", std::process::id()));
+    let _removed = std::fs::remove_file(&path);
+    let socket = std::os::unix::net::UnixDatagram::bind(&path).expect("unable to bind");
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_unix_destination(path.to_str().unwrap())
+        .with_clock(std::sync::Arc::new(EpochClock));
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    info!(target: "MyApp", "unix logging");
+    log::logger().flush();
+    let mut buf = [0; 4096];
+    let byte_count = socket.recv(&mut buf).expect("datagram");
+    let _removed = std::fs::remove_file(&path);
+    assert_eq!(
+        std::str::from_utf8(&buf[..byte_count]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] unix logging"
+    );
+}
+
+// Payloads, and their destinations, captured by a MemoryTransport.
+type Captured = std::sync::Arc<std::sync::Mutex<Vec<(Vec<u8>, String)>>>;
+
+// A transport, capturing payloads, and their destinations, in memory.
+#[derive(Debug, Default, Clone)]
+struct MemoryTransport(Captured);
+
+impl udp_logger_rs::Transport for MemoryTransport {
+    fn send(&self, bytes: &[u8], dest: &str) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .unwrap()
+            .push((bytes.to_vec(), dest.to_string()));
+        Ok(bytes.len())
+    }
+}
+
+//
+// This tests that payloads are sent with the provided transport.
+#[test]
+fn transport() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let transport = MemoryTransport::default();
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_destination("collector")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()));
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    info!(target: "MyApp", "transport logging");
+    assert_eq!(
+        *transport.0.lock().unwrap(),
+        vec![(
+            b"1970-01-01 00:00:00.000 INFO  [MyApp] transport logging".to_vec(),
+            "collector".to_string()
+        )]
+    );
+}
+
+//
+// This tests that payloads are sent as length prefixed frames to a TCP destination.
+#[test]
+fn tcp_destination() {
+    use std::io::Read;
+
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let listener = std::net::TcpListener::bind("127.0.0.1:4350").expect("unable to bind");
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_tcp_destination("127.0.0.1:4350")
+        .with_clock(std::sync::Arc::new(EpochClock));
+    let stats = udp_logger.stats_handle();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    info!(target: "MyApp", "tcp logging");
+    info!(target: "MyApp", "more tcp logging");
+    let (mut stream, _addr) = listener.accept().expect("connection");
+    for expected in [
+        "1970-01-01 00:00:00.000 INFO  [MyApp] tcp logging",
+        "1970-01-01 00:00:00.000 INFO  [MyApp] more tcp logging",
+    ]
+    .iter()
+    {
+        let mut len = [0; 4];
+        stream.read_exact(&mut len).expect("frame length");
+        let mut payload = vec![0; u32::from_be_bytes(len) as usize];
+        stream.read_exact(&mut payload).expect("frame");
+        assert_eq!(std::str::from_utf8(&payload).unwrap(), *expected);
+    }
+    assert_eq!(stats.stats().sent, 2);
+}
+
+//
+// This tests that frames held for a TCP destination which refuses the connection aren't
+// counted as sent.
+#[test]
+fn tcp_destination_unavailable() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let dest = std::net::TcpListener::bind("127.0.0.1:0")
+        .and_then(|listener| listener.local_addr())
+        .expect("unable to bind")
+        .to_string();
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_tcp_destination(&dest)
+        .with_clock(std::sync::Arc::new(EpochClock));
+    let stats = udp_logger.stats_handle();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    info!(target: "MyApp", "held tcp logging");
+    assert_eq!(stats.stats().sent, 0);
+    assert_eq!(stats.stats().dropped, 0);
+}
+
+//
+// This tests that a destination hostname is resolved, to an address of the source's family.
+#[test]
+fn resolve_destination() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4360")
+        .with_destination("localhost:4361")
+        .with_resolve_interval(std::time::Duration::from_millis(1))
+        .with_clock(std::sync::Arc::new(EpochClock));
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4361").expect("unable to bind");
+
+    for _ in 0..2 {
+        info!(target: "MyApp", "resolved logging");
+        log::logger().flush();
+        let mut buf = [0; 4096];
+        let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+        assert_eq!(
+            std::str::from_utf8(&buf[..byte_count]).unwrap(),
+            "1970-01-01 00:00:00.000 INFO  [MyApp] resolved logging"
+        );
+        std::thread::sleep(std::time::Duration::from_millis(2));
+    }
+}
+
+//
+// This tests that a malformed destination fails at init, rather than when sending.
+#[test]
+#[should_panic(expected = "unable to resolve destination 127.0.0.1;4010")]
+fn malformed_destination() {
+    let _serial = serialize();
+    let _udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4370")
+        .with_destination("127.0.0.1;4010")
+        .partial_init();
+}
+
+//
+// This tests that init() returns the errors encountered by the builder.
+#[test]
+fn init_errors() {
+    let _serial = serialize();
+    let _bound = std::net::UdpSocket::bind("127.0.0.1:4380").expect("unable to bind");
+    let result = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4380")
+        .init();
+    match result {
+        Err(err @ udp_logger_rs::UdpLoggerError::Bind(..)) => assert!(err
+            .to_string()
+            .starts_with("unable to bind to socket 127.0.0.1:4380, err=")),
+        result => panic!("unexpected {:?}", result),
+    }
+
+    let result = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4381")
+        .with_destination("127.0.0.1;4010")
+        .init();
+    match result {
+        Err(udp_logger_rs::UdpLoggerError::Resolve(_err, destination)) => {
+            assert_eq!(destination, "127.0.0.1;4010")
+        }
+        other => panic!("expected a resolve error, got {:?}", other),
+    }
+}
+
+//
+// This tests that records beyond the rate limit are suppressed, and summarized.
+#[test]
+fn rate_limit() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let transport = MemoryTransport::default();
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_rate_limit(Level::Debug, 2);
+    let stats = udp_logger.stats_handle();
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    for _ in 0..5 {
+        debug!(target: "MyApp", "limited logging");
+    }
+    info!(target: "MyApp", "unlimited logging");
+    std::thread::sleep(std::time::Duration::from_millis(600));
+    debug!(target: "MyApp", "limited logging");
+
+    let payloads: Vec<String> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(payload, _dest)| String::from_utf8(payload.clone()).unwrap())
+        .collect();
+    assert_eq!(
+        payloads,
+        vec![
+            "1970-01-01 00:00:00.000 DEBUG [MyApp] limited logging",
+            "1970-01-01 00:00:00.000 DEBUG [MyApp] limited logging",
+            "1970-01-01 00:00:00.000 INFO  [MyApp] unlimited logging",
+            "1970-01-01 00:00:00.000 DEBUG [MyApp] 3 messages suppressed",
+            "1970-01-01 00:00:00.000 DEBUG [MyApp] limited logging",
+        ]
+    );
+    assert_eq!(stats.stats().dropped, 3);
+}
+
+//
+// This tests that sampling keeps every nth record, of the sampled level only.
+#[test]
+fn sampling() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let transport = MemoryTransport::default();
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_sampling(Level::Info, 0.25);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    for index in 0..10 {
+        info!(target: "MyApp", "sampled {}", index);
+        warn!(target: "MyApp", "unsampled {}", index);
+    }
+
+    let payloads = transport.0.lock().unwrap();
+    let sampled: Vec<&str> = payloads
+        .iter()
+        .map(|(payload, _dest)| std::str::from_utf8(payload).unwrap())
+        .filter(|payload| payload.contains("INFO"))
+        .collect();
+    assert_eq!(
+        sampled,
+        vec![
+            "1970-01-01 00:00:00.000 INFO  [MyApp] sampled 0",
+            "1970-01-01 00:00:00.000 INFO  [MyApp] sampled 4",
+            "1970-01-01 00:00:00.000 INFO  [MyApp] sampled 8",
+        ]
+    );
+    assert_eq!(payloads.len(), 13);
+}
+
+//
+// This tests that exact level routes take precedence over level routes.
+#[test]
+fn exact_level_routing() {
+    let _serial = serialize();
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let transport = MemoryTransport::default();
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_destination("default")
+        .with_destination_level("info", log::LevelFilter::Info)
+        .with_destination_exact_level("warn", Level::Warn)
+        .with_target_destination("audit", "audit");
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    error!(target: "MyApp", "routed logging");
+    warn!(target: "MyApp", "routed logging");
+    info!(target: "MyApp", "routed logging");
+    debug!(target: "MyApp", "routed logging");
+    warn!(target: "audit", "routed logging");
+
+    let destinations: Vec<String> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_payload, dest)| dest.clone())
+        .collect();
+    assert_eq!(
+        destinations,
+        vec!["info", "warn", "info", "default", "audit"]
+    );
+}
+
+//
+// This tests logging to loggers which aren't the global logger.
+#[test]
+fn non_global_loggers() {
+    let _serial = serialize();
+    let audit_transport = MemoryTransport::default();
+    let audit = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(audit_transport.clone()))
+        .build()
+        .expect("audit logger");
+    let app_transport = MemoryTransport::default();
+    let app = udp_logger_rs::UdpLogger::default()
+        .with_level(log::LevelFilter::Warn)
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(app_transport.clone()))
+        .build()
+        .expect("app logger");
+
+    let kvs: std::vec::Vec<(String, String)> = vec![("user".into(), "nori".into())];
+    info!(logger: &audit, target: "audit", kvs: &kvs, "logged in");
+    info!(logger: &app, "filtered");
+    error!(logger: &app, target: "MyApp", "failed");
+
+    let payloads = |transport: &MemoryTransport| -> Vec<String> {
+        transport
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(payload, _dest)| String::from_utf8(payload.clone()).unwrap())
+            .collect()
+    };
+    assert_eq!(
+        payloads(&audit_transport),
+        vec!["1970-01-01 00:00:00.000 INFO  [audit] logged in user=nori"]
+    );
+    assert_eq!(
+        payloads(&app_transport),
+        vec!["1970-01-01 00:00:00.000 ERROR [MyApp] failed"]
+    );
+}
+
+//
+// This tests a custom formatter.
+#[test]
+fn custom_formatter() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_wire_fmt(udp_logger_rs::WireFmt::Logfmt)
+        .with_global_field("env", "prod")
+        .with_transport(Box::new(transport.clone()))
+        .with_formatter(Box::new(|record, kvs| {
+            let mut line = format!("{}|{}|{}", record.level(), record.target(), record.args());
+            for (key, value) in kvs {
+                line.push_str(&format!("|{}:{}", key, value));
+            }
+            line.into_bytes()
+        }))
+        .build()
+        .expect("logger");
+
+    let kvs: std::vec::Vec<(String, String)> = vec![("user".into(), "nori".into())];
+    warn!(logger: &logger, target: "MyApp", kvs: &kvs, "custom");
+
+    let payloads: Vec<Vec<u8>> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(payload, _dest)| payload.clone())
+        .collect();
+    assert_eq!(
+        payloads,
+        vec![b"WARN|MyApp|custom|env:prod|user:nori".to_vec()]
+    );
+}
+
+//
+// This tests that the types of kv values are preserved in the text formats.
+#[test]
+fn typed_kv() {
+    let _serial = serialize();
+    for (wire_fmt, expected) in [
+        (
+            udp_logger_rs::WireFmt::Uncompressed,
+            "1970-01-01 00:00:00.000 INFO  [MyApp] typed count=42 ratio=0.5 ok=true name=nori \
+             code=\"42\" flag=\"false\" missing=\"null\"",
+        ),
+        (
+            udp_logger_rs::WireFmt::Logfmt,
+            "ts=\"1970-01-01 00:00:00.000\" level=info target=MyApp msg=typed count=42 ratio=0.5 \
+             ok=true name=nori code=\"42\" flag=\"false\" missing=\"null\"",
+        ),
+    ] {
+        let transport = MemoryTransport::default();
+        let logger = udp_logger_rs::UdpLogger::default()
+            .with_wire_fmt(wire_fmt)
+            .with_clock(std::sync::Arc::new(EpochClock))
+            .with_transport(Box::new(transport.clone()))
+            .build()
+            .expect("logger");
+
+        let kvs = [
+            ("count", log::kv::Value::from(42)),
+            ("ratio", log::kv::Value::from(0.5)),
+            ("ok", log::kv::Value::from(true)),
+            ("name", log::kv::Value::from("nori")),
+            ("code", log::kv::Value::from("42")),
+            ("flag", log::kv::Value::from("false")),
+            ("missing", log::kv::Value::from("null")),
+        ];
+        info!(logger: &logger, target: "MyApp", kvs: &kvs, "typed");
+
+        let payloads: Vec<String> = transport
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(payload, _dest)| String::from_utf8(payload.clone()).unwrap())
+            .collect();
+        assert_eq!(payloads, vec![expected]);
+    }
+}
+
+//
+// This tests that a null sink formats, but doesn't send, records.
+#[test]
+fn null_sink() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let formatted = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = formatted.clone();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_destination("unresolvable.invalid:4390")
+        .with_transport(Box::new(transport.clone()))
+        .with_formatter(Box::new(move |record, _kvs| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+            record.args().to_string().into_bytes()
+        }))
+        .with_null_sink()
+        .build()
+        .expect("logger");
+    let stats = logger.stats_handle();
+
+    info!(logger: &logger, "discarded");
+    warn!(logger: &logger, "discarded");
+
+    assert_eq!(formatted.load(std::sync::atomic::Ordering::Relaxed), 2);
+    assert!(transport.0.lock().unwrap().is_empty());
+    assert_eq!(stats.stats(), udp_logger_rs::LogStats::default());
+}
+
+//
+// This tests coloring the level of Uncompressed payloads.
+#[test]
+fn colors() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_colors()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "green");
+    error!(logger: &logger, target: "MyApp", "red");
+
+    let recolored = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_level_colors(["1;41", "33", "0", "0", "0"])
+        .build()
+        .expect("logger");
+    error!(logger: &recolored, target: "MyApp", "alarm");
+
+    let payloads: Vec<String> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(payload, _dest)| String::from_utf8(payload.clone()).unwrap())
+        .collect();
+    assert_eq!(
+        payloads,
+        vec![
+            "1970-01-01 00:00:00.000 \x1b[32mINFO\x1b[0m  [MyApp] green",
+            "1970-01-01 00:00:00.000 \x1b[31mERROR\x1b[0m [MyApp] red",
+            "1970-01-01 00:00:00.000 \x1b[1;41mERROR\x1b[0m [MyApp] alarm",
+        ]
+    );
+}
+
+//
+// This tests the LevelStyle of Uncompressed payloads.
+#[test]
+fn level_style() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_level_style(udp_logger_rs::LevelStyle {
+            lowercase: true,
+            width: 7,
+        })
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "lowercase");
+    error!(logger: &logger, target: "MyApp", "lowercase");
+
+    let payloads: Vec<String> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(payload, _dest)| String::from_utf8(payload.clone()).unwrap())
+        .collect();
+    assert_eq!(
+        payloads,
+        vec![
+            "1970-01-01 00:00:00.000 info    [MyApp] lowercase",
+            "1970-01-01 00:00:00.000 error   [MyApp] lowercase",
+        ]
+    );
+}
+
+//
+// This tests the line terminator of Uncompressed payloads, including truncated payloads.
+#[test]
+fn line_terminator() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_oversize_policy(udp_logger_rs::OversizePolicy::Truncate)
+        .with_line_terminator(udp_logger_rs::Terminator::Crlf)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "line");
+    info!(logger: &logger, target: "MyApp", "{}", "x".repeat(70_000));
+
+    let payloads: Vec<Vec<u8>> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(payload, _dest)| payload.clone())
+        .collect();
+    assert_eq!(payloads.len(), 2);
+    assert_eq!(
+        payloads[0],
+        b"1970-01-01 00:00:00.000 INFO  [MyApp] line\r\n".to_vec()
+    );
+    assert_eq!(payloads[1].len(), 65_507);
+    assert!(payloads[1].ends_with(b"xxx...[truncated]\r\n"));
+}
+
+// A transport, which would block for a number of sends before it accepts payloads.
+#[derive(Debug, Default, Clone)]
+struct BusyTransport(std::sync::Arc<std::sync::atomic::AtomicU32>);
+
+impl udp_logger_rs::Transport for BusyTransport {
+    fn send(&self, bytes: &[u8], _dest: &str) -> std::io::Result<usize> {
+        let busy = self.0.load(std::sync::atomic::Ordering::Relaxed);
+        if busy > 0 {
+            self.0.store(busy - 1, std::sync::atomic::Ordering::Relaxed);
+            return Err(std::io::ErrorKind::WouldBlock.into());
+        }
+        Ok(bytes.len())
+    }
+}
+
+//
+// This tests that sends which would block are retried.
+#[test]
+fn send_retries() {
+    let _serial = serialize();
+    let transport = BusyTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_send_retries(3)
+        .build()
+        .expect("logger");
+    let stats = logger.stats_handle();
+
+    transport.0.store(3, std::sync::atomic::Ordering::Relaxed);
+    info!(logger: &logger, "retried");
+    assert_eq!(stats.stats().sent, 1);
+
+    // once the retries are exhausted, the payload is held until flushed
+    transport.0.store(4, std::sync::atomic::Ordering::Relaxed);
+    info!(logger: &logger, "held");
+    assert_eq!(stats.stats().sent, 1);
+    log::Log::flush(&logger);
+    assert_eq!(stats.stats().sent, 2);
+    assert_eq!(stats.stats().dropped, 0);
+}
+
+//
+// This tests that a burst of payloads, larger than the send buffer, is sent without being
+// held, or dropped, by blocking sockets.
+#[test]
+fn blocking() {
+    let _serial = serialize();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4390")
+        .with_destination("127.0.0.1:4391")
+        .with_send_buffer_size(4096)
+        .with_blocking()
+        .build()
+        .expect("logger");
+    let stats = logger.stats_handle();
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4391").expect("unable to bind");
+    for _ in 0..100 {
+        info!(logger: &logger, "{}", "b".repeat(1000));
+    }
+    assert_eq!(stats.stats().sent, 100);
+    assert_eq!(stats.stats().dropped, 0);
+    let mut buf = [0; 4096];
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert!(buf[..byte_count].ends_with(b"bbb"));
+}
+
+//
+// This tests that failures of a destination, rather than of the source socket, are reported
+// without rebinding the socket, which keeps sending from its address.
+#[test]
+fn rebind_source() {
+    let _serial = serialize();
+    let errors = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = errors.clone();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4392")
+        .with_destination("127.0.0.1:4393")
+        .with_target_destination("unreachable", "[::1]:4393")
+        .with_error_handler(Box::new(move |_err| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }))
+        .build()
+        .expect("logger");
+
+    let socket = std::net::UdpSocket::bind("127.0.0.1:4393").expect("unable to bind");
+    for _ in 0..3 {
+        info!(logger: &logger, target: "unreachable", "fails");
+    }
+    assert_eq!(errors.load(std::sync::atomic::Ordering::Relaxed), 3);
+
+    info!(logger: &logger, target: "MyApp", "not rebound");
+    let mut buf = [0; 4096];
+    let (byte_count, src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(src_addr, "127.0.0.1:4392".parse().unwrap());
+    assert!(buf[..byte_count].ends_with(b"[MyApp] not rebound"));
+}
+
+//
+// This tests checking whether a level is enabled, for a logger and for the global logger.
+#[test]
+fn level_enabled() {
+    let _serial = serialize();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_level(log::LevelFilter::Error)
+        .with_transport(Box::new(MemoryTransport::default()))
+        .build()
+        .expect("logger");
+    assert!(logger.level_enabled(Level::Error, "MyApp"));
+    assert!(!logger.level_enabled(Level::Warn, "MyApp"));
+    drop(logger);
+
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_level(log::LevelFilter::Warn)
+        .with_module_level("chatty", log::LevelFilter::Debug);
+    PROXY_LOGGER.set_logger(udp_logger);
+
+    assert!(udp_logger_rs::level_enabled(Level::Warn, "MyApp"));
+    assert!(!udp_logger_rs::level_enabled(Level::Info, "MyApp"));
+    assert!(udp_logger_rs::level_enabled(Level::Debug, "chatty::module"));
+    assert!(!udp_logger_rs::level_enabled(
+        Level::Trace,
+        "chatty::module"
+    ));
+    assert!(udp_logger_rs::log_enabled!(target: "chatty", Level::Debug));
+}
+
+//
+// This tests that the last level provided for a module is taken.
+#[test]
+fn module_level_replaced() {
+    let _serial = serialize();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_level(log::LevelFilter::Error)
+        .with_module_level("x", log::LevelFilter::Warn)
+        .with_module_level("x::y", log::LevelFilter::Trace)
+        .with_module_level("x", log::LevelFilter::Debug)
+        .with_transport(Box::new(MemoryTransport::default()))
+        .build()
+        .expect("logger");
+    assert!(logger.level_enabled(Level::Debug, "x"));
+    assert!(!logger.level_enabled(Level::Trace, "x"));
+    assert!(logger.level_enabled(Level::Trace, "x::y"));
+    assert!(!logger.level_enabled(Level::Warn, "z"));
+}
+
+//
+// This tests that RUST_LOG directives tolerate case and whitespace, accept numeric levels,
+// and that invalid directives are reported.
+#[test]
+fn env_tolerant_directives() {
+    let _serial = serialize();
+    let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let reported = errors.clone();
+    std::env::set_var(
+        "UDP_LOG_TOLERANT",
+        " Info , myapp::net = 5,myapp::db=2, typo=lound,",
+    );
+    let udp_logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(MemoryTransport::default()))
+        .with_error_handler(Box::new(move |err| {
+            reported.lock().unwrap().push(err.to_string());
+        }))
+        .env_with_var("UDP_LOG_TOLERANT")
+        .build()
+        .expect("logger");
+    std::env::remove_var("UDP_LOG_TOLERANT");
+
+    assert!(udp_logger.level_enabled(Level::Info, "myapp"));
+    assert!(!udp_logger.level_enabled(Level::Debug, "myapp"));
+    assert!(udp_logger.level_enabled(Level::Trace, "myapp::net"));
+    assert!(!udp_logger.level_enabled(Level::Info, "myapp::db"));
+    assert_eq!(
+        *errors.lock().unwrap(),
+        vec!["invalid directive \"typo=lound\" in UDP_LOG_TOLERANT"]
+    );
+}
+
+//
+// This tests that env_or() falls back to its level when RUST_LOG doesn't provide one.
+#[test]
+fn env_or() {
+    let _serial = serialize();
+    let level_of = |rust_log: Option<&str>| {
+        match rust_log {
+            Some(rust_log) => std::env::set_var("RUST_LOG", rust_log),
+            None => std::env::remove_var("RUST_LOG"),
+        }
+        let logger = udp_logger_rs::UdpLogger::default()
+            .with_transport(Box::new(MemoryTransport::default()))
+            .env_or(log::LevelFilter::Info)
+            .build()
+            .expect("logger");
+        std::env::remove_var("RUST_LOG");
+        [Level::Trace, Level::Debug, Level::Info, Level::Warn]
+            .iter()
+            .find(|level| logger.level_enabled(**level, "myapp"))
+            .copied()
+    };
+
+    assert_eq!(level_of(None), Some(Level::Info));
+    assert_eq!(level_of(Some("verbose")), Some(Level::Info));
+    assert_eq!(level_of(Some("myapp::net=trace")), Some(Level::Info));
+    assert_eq!(level_of(Some("debug")), Some(Level::Debug));
+}
+
+//
+// This tests that RUST_LOG=off disables logging.
+#[test]
+fn env_off() {
+    let _serial = serialize();
+    std::env::set_var("RUST_LOG", "off");
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .env()
+        .build()
+        .expect("logger");
+    std::env::remove_var("RUST_LOG");
+
+    assert!(!logger.level_enabled(Level::Error, "myapp"));
+    error!(logger: &logger, "silenced");
+    log::Log::log(
+        &logger,
+        &log::Record::builder()
+            .level(Level::Error)
+            .target("myapp")
+            .args(format_args!("silenced"))
+            .build(),
+    );
+    assert!(transport.0.lock().unwrap().is_empty());
+}
+
+//
+// This tests that no datagram exceeds the max datagram size, including its checksum.
+#[test]
+fn max_datagram_size() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let truncating = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_max_datagram_size(100)
+        .with_oversize_policy(udp_logger_rs::OversizePolicy::Truncate)
+        .build()
+        .expect("logger");
+    info!(logger: &truncating, target: "MyApp", "{}", "t".repeat(200));
+    drop(truncating);
+
+    let fragmenting = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .with_checksum(true)
+        .with_max_datagram_size(100)
+        .with_oversize_policy(udp_logger_rs::OversizePolicy::Fragment)
+        .build()
+        .expect("logger");
+    info!(logger: &fragmenting, target: "MyApp", "{}", "f".repeat(200));
+    drop(fragmenting);
+
+    let errors = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = errors.clone();
+    let dropping = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_max_datagram_size(100)
+        .with_error_handler(Box::new(move |_err| {
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }))
+        .build()
+        .expect("logger");
+    info!(logger: &dropping, target: "MyApp", "{}", "d".repeat(200));
+    assert_eq!(errors.load(std::sync::atomic::Ordering::Relaxed), 1);
+
+    let payloads = transport.0.lock().unwrap();
+    // one truncated payload, followed by fragments of 100 bytes and a final fragment
+    assert_eq!(payloads.len(), 4);
+    assert_eq!(payloads[0].0.len(), 100);
+    assert!(payloads[0].0.ends_with(b"ttt...[truncated]"));
+    assert!(payloads[1..]
+        .iter()
+        .all(|(payload, _dest)| payload.len() <= 100 && payload.starts_with(&[0x55, 0x46])));
+}
+
+//
+// This tests the Protobuf wire format, against a LogRecord encoded by hand.
+#[cfg(feature = "protobuf")]
+#[test]
+fn protobuf_wire_fmt() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_wire_fmt(udp_logger_rs::WireFmt::Protobuf)
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .build()
+        .expect("logger");
+    let kvs = [
+        ("count", log::kv::Value::from(42)),
+        ("cat", log::kv::Value::from("nori")),
+    ];
+    info!(logger: &logger, target: "MyApp", kvs: &kvs, "pb");
+
+    let mut expected = vec![0x08, 0x00, 0x10, 0x03];
+    expected.extend_from_slice(b"\x1a\x05MyApp\x22\x02pb");
+    expected.extend_from_slice(b"\x2a\x09\x0a\x05count\x10\x2a");
+    expected.extend_from_slice(b"\x2a\x0b\x0a\x03cat\x2a\x04nori");
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(payloads[0].0, expected);
+}
+
+//
+// This tests that ByteBuffer payloads are signed, and that the truncated tag verifies.
+#[cfg(feature = "signing")]
+#[test]
+fn hmac_signing() {
+    use hmac::Mac;
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .with_transport(Box::new(transport.clone()))
+        .with_hmac_key(b"shared secret")
+        .with_hmac_len(16)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "signed logging");
+
+    let mut payloads = transport.0.lock().unwrap();
+    let datagram = &mut payloads[0].0;
+    let tag_offset = datagram.len() - 16;
+    let (payload, tag) = datagram.split_at_mut(tag_offset);
+    let mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(b"shared secret").unwrap();
+    let mut verifier = mac.clone();
+    verifier.update(payload);
+    assert!(verifier.verify_truncated_left(tag).is_ok());
+    payload[4] ^= 0x01;
+    let mut verifier = mac;
+    verifier.update(payload);
+    assert!(verifier.verify_truncated_left(tag).is_err());
+}
+
+//
+// This tests that a failed DTLS handshake is reported, and not retried until after a backoff.
+#[cfg(feature = "dtls")]
+#[test]
+fn dtls_handshake_backoff() {
+    let _serial = serialize();
+    let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let captured = errors.clone();
+    let connector = openssl::ssl::SslConnector::builder(openssl::ssl::SslMethod::dtls())
+        .expect("connector")
+        .build();
+    let config = udp_logger_rs::DtlsConfig::new(connector, "localhost")
+        .with_handshake_timeout(std::time::Duration::from_millis(200));
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_destination("127.0.0.1:4394")
+        .with_dtls(config)
+        .with_error_handler(Box::new(move |err| {
+            captured.lock().unwrap().push(err.kind());
+        }))
+        .build()
+        .expect("logger");
+    let started = std::time::Instant::now();
+    info!(logger: &logger, target: "MyApp", "confidential logging");
+    info!(logger: &logger, target: "MyApp", "more confidential logging");
+    assert!(started.elapsed() < std::time::Duration::from_secs(1));
+
+    let errors = errors.lock().unwrap();
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[1], std::io::ErrorKind::NotConnected);
+}
+
+//
+// This tests that records are numbered, in the Uncompressed prefix and as a Logfmt field.
+#[test]
+fn sequence_numbers() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_sequence_numbers()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "first");
+    info!(logger: &logger, target: "MyApp", "second");
+    drop(logger);
+
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::Logfmt)
+        .with_sequence_numbers()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "third");
+
+    let payloads: Vec<String> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(payload, _dest)| String::from_utf8(payload.clone()).unwrap())
+        .collect();
+    assert_eq!(
+        payloads,
+        vec![
+            "#1 1970-01-01 00:00:00.000 INFO  [MyApp] first",
+            "#2 1970-01-01 00:00:00.000 INFO  [MyApp] second",
+            "ts=\"1970-01-01 00:00:00.000\" level=info seq=1 target=MyApp msg=third",
+        ]
+    );
+}
+
+// A clock, fixed at 1234567 nanoseconds after the Unix epoch.
+#[derive(Debug)]
+struct NanosClock;
+
+impl udp_logger_rs::Clock for NanosClock {
+    fn now_millis(&self) -> i64 {
+        1
+    }
+    fn now_nanos(&self) -> i64 {
+        1_234_567
+    }
+    fn format_now(&self, fmt: &str) -> String {
+        chrono::DateTime::<chrono::Utc>::from_timestamp(0, 1_234_567)
+            .unwrap()
+            .format(fmt)
+            .to_string()
+    }
+}
+
+//
+// This tests that the timestamp precision applies to the format, and ByteBuffer timestamps.
+#[test]
+fn timestamp_precision() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(NanosClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_timestamp_precision(udp_logger_rs::TimestampPrecision::Micros)
+        .with_timestamp_format("%H:%M:%S%.3f")
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "micros");
+    drop(logger);
+
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(NanosClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .with_timestamp_precision(udp_logger_rs::TimestampPrecision::Nanos)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "nanos");
+
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(
+        payloads[0].0,
+        b"00:00:00.001234 INFO  [MyApp] micros".to_vec()
+    );
+    assert_eq!(payloads[1].0[4..12], 1_234_567i64.to_be_bytes());
+}
+
+//
+// This tests that payloads without a timestamp begin with the level.
+#[test]
+fn without_timestamp() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .without_timestamp()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "untimed");
+    drop(logger);
+
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .without_timestamp()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "untimed");
+
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(payloads[0].0, b"INFO  [MyApp] untimed".to_vec());
+    let mut expected = udp_logger_rs::BYTE_BUFFER_MAGIC.to_vec();
+    expected.push(udp_logger_rs::BYTE_BUFFER_VERSION_NO_TIMESTAMP);
+    expected.push(3);
+    expected.extend_from_slice(&5u32.to_be_bytes());
+    expected.extend_from_slice(b"MyApp");
+    expected.extend_from_slice(&7u32.to_be_bytes());
+    expected.extend_from_slice(b"untimed");
+    expected.extend_from_slice(&0u16.to_be_bytes());
+    assert_eq!(payloads[1].0, expected);
+}
+
+//
+// This tests that relative timestamps are the seconds elapsed since the logger was created.
+#[test]
+fn relative_time() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_relative_time()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "first");
+    std::thread::sleep(std::time::Duration::from_millis(5));
+    info!(logger: &logger, target: "MyApp", "second");
+
+    let elapsed: Vec<f64> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(payload, _dest)| {
+            let line = String::from_utf8(payload.clone()).unwrap();
+            line.split(' ').next().unwrap().parse().unwrap()
+        })
+        .collect();
+    assert!(elapsed[0] < 60.0);
+    assert!(elapsed[1] > elapsed[0]);
+}
+
+//
+// This tests that payloads, encoded by the logger, are decoded.
+#[test]
+fn decode_round_trip() {
+    use udp_logger_rs::decode::{self, DecodedValue};
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let kvs = [
+        ("count", log::kv::Value::from(42)),
+        ("ratio", log::kv::Value::from(0.5)),
+        ("cat", log::kv::Value::from("nori")),
+    ];
+    for wire_fmt in [
+        udp_logger_rs::WireFmt::ByteBuffer,
+        udp_logger_rs::WireFmt::Uncompressed,
+        udp_logger_rs::WireFmt::Logfmt,
+    ] {
+        let logger = udp_logger_rs::UdpLogger::default()
+            .with_clock(std::sync::Arc::new(EpochClock))
+            .with_transport(Box::new(transport.clone()))
+            .with_wire_fmt(wire_fmt)
+            .with_checksum(true)
+            .with_app_name("cats")
+            .with_sequence_numbers()
+            .build()
+            .expect("logger");
+        warn!(logger: &logger, target: "MyApp", kvs: &kvs, "say \"hi\"");
+    }
+
+    let payloads = transport.0.lock().unwrap();
+    let payload = decode::verify_checksum(&payloads[0].0).expect("checksum");
+    let record = decode::decode_bytebuffer(payload).expect("record");
+    assert_eq!(record.level, log::Level::Warn);
+    assert_eq!(record.timestamp, Some(0));
+    assert_eq!(record.target, "MyApp");
+    assert_eq!(record.message, "say \"hi\"");
+    assert_eq!(
+        record.kvs,
+        vec![
+            ("seq".to_string(), DecodedValue::I64(1)),
+            ("app".to_string(), DecodedValue::String("cats".to_string())),
+            ("count".to_string(), DecodedValue::I64(42)),
+            ("ratio".to_string(), DecodedValue::F64(0.5)),
+            ("cat".to_string(), DecodedValue::String("nori".to_string())),
+        ]
+    );
+    assert_eq!(
+        decode::decode_bytebuffer(&payloads[0].0),
+        Err(decode::DecodeError::TrailingBytes(4))
+    );
+
+    let line = std::str::from_utf8(&payloads[1].0).unwrap();
+    let record = decode::parse_uncompressed(line).expect("record");
+    assert_eq!(record.sequence, Some(1));
+    assert_eq!(record.timestamp, "1970-01-01 00:00:00.000");
+    assert_eq!(record.level, log::Level::Warn);
+    assert_eq!(record.origin, "cats");
+    assert_eq!(record.target, "MyApp");
+    assert_eq!(record.message, "say \"hi\" count=42 ratio=0.5 cat=nori");
+
+    let line = std::str::from_utf8(&payloads[2].0).unwrap();
+    let fields = decode::parse_logfmt(line).expect("fields");
+    assert_eq!(
+        fields,
+        vec![
+            (
+                "ts".to_string(),
+                DecodedValue::String("1970-01-01 00:00:00.000".to_string())
+            ),
+            (
+                "level".to_string(),
+                DecodedValue::String("warn".to_string())
+            ),
+            ("seq".to_string(), DecodedValue::I64(1)),
+            ("app".to_string(), DecodedValue::String("cats".to_string())),
+            (
+                "target".to_string(),
+                DecodedValue::String("MyApp".to_string())
+            ),
+            (
+                "msg".to_string(),
+                DecodedValue::String("say \"hi\"".to_string())
+            ),
+            ("count".to_string(), DecodedValue::I64(42)),
+            ("ratio".to_string(), DecodedValue::F64(0.5)),
+            ("cat".to_string(), DecodedValue::String("nori".to_string())),
+        ]
+    );
+}
+
+//
+// This tests that level codes provided to the logger are encoded in ByteBuffer payloads, and
+// that a repeated code decodes as the most severe level with it.
+#[test]
+fn level_codes() {
+    use udp_logger_rs::decode;
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .with_level_codes([3, 4, 6, 7, 7])
+        .build()
+        .expect("logger");
+    error!(logger: &logger, target: "MyApp", "error");
+    warn!(logger: &logger, target: "MyApp", "warn");
+    info!(logger: &logger, target: "MyApp", "info");
+    debug!(logger: &logger, target: "MyApp", "debug");
+    trace!(logger: &logger, target: "MyApp", "trace");
+
+    let payloads = transport.0.lock().unwrap();
+    // the level code follows the magic bytes and the layout version
+    let codes: Vec<u8> = payloads.iter().map(|(payload, _dest)| payload[3]).collect();
+    assert_eq!(codes, [3, 4, 6, 7, 7]);
+    let levels: Vec<Level> = payloads
+        .iter()
+        .map(|(payload, _dest)| {
+            decode::decode_bytebuffer_with_codes(payload, [3, 4, 6, 7, 7])
+                .expect("record")
+                .level
+        })
+        .collect();
+    assert_eq!(
+        levels,
+        [
+            Level::Error,
+            Level::Warn,
+            Level::Info,
+            Level::Debug,
+            Level::Debug
+        ]
+    );
+}
+
+//
+// This tests that the ByteBuffer target and message are decoded as separate fields, whatever
+// they contain, and that payloads of the earlier layout, without a target field, decode.
+#[test]
+fn decode_byte_buffer_target() {
+    use udp_logger_rs::decode;
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .without_timestamp()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "My] App", "[not] a target");
+
+    let payloads = transport.0.lock().unwrap();
+    let record = decode::decode_bytebuffer(&payloads[0].0).expect("record");
+    assert_eq!(record.target, "My] App");
+    assert_eq!(record.message, "[not] a target");
+
+    let mut legacy = udp_logger_rs::BYTE_BUFFER_MAGIC.to_vec();
+    legacy.extend_from_slice(&[2, 3]);
+    legacy.extend_from_slice(&15u32.to_be_bytes());
+    legacy.extend_from_slice(b"[MyApp] untimed");
+    legacy.extend_from_slice(&0u16.to_be_bytes());
+    let record = decode::decode_bytebuffer(&legacy).expect("legacy record");
+    assert_eq!(record.target, "MyApp");
+    assert_eq!(record.message, "untimed");
+    assert_eq!(record.timestamp, None);
+}
+
+//
+// This tests that ByteBuffer kv pairs round trip as their own fields, leaving the message as
+// logged, with and without kv pairs.
+#[test]
+fn byte_buffer_kv_round_trip() {
+    use udp_logger_rs::decode::{self, DecodedValue};
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .build()
+        .expect("logger");
+    let kvs = [
+        ("id", log::kv::Value::from(7)),
+        ("user", log::kv::Value::from("nori cat")),
+    ];
+    info!(logger: &logger, target: "MyApp", kvs: &kvs, "signed in");
+    info!(logger: &logger, target: "MyApp", "signed out id=7");
+
+    let payloads = transport.0.lock().unwrap();
+    let record = decode::decode_bytebuffer(&payloads[0].0).expect("record");
+    assert_eq!(record.message, "signed in");
+    assert_eq!(
+        record.kvs,
+        vec![
+            ("id".to_string(), DecodedValue::I64(7)),
+            (
+                "user".to_string(),
+                DecodedValue::String("nori cat".to_string())
+            ),
+        ]
+    );
+    let record = decode::decode_bytebuffer(&payloads[1].0).expect("record");
+    assert_eq!(record.message, "signed out id=7");
+    assert!(record.kvs.is_empty());
+}
+
+//
+// This tests that a category is carried as a field of its own, preceding any kv pairs.
+#[test]
+fn category() {
+    use udp_logger_rs::decode::{self, DecodedValue};
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .build()
+        .expect("logger");
+    let kvs = [("amount", log::kv::Value::from(42))];
+    info!(logger: &logger, target: "MyApp", category: "billing", kvs: &kvs, "charged");
+    warn!(logger: &logger, target: "MyApp", category: "auth", "denied");
+    drop(logger);
+
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, category: "billing", kvs: &kvs, "charged");
+
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(
+        payloads[0].0,
+        b"1970-01-01 00:00:00.000 INFO  [MyApp] charged category=billing amount=42".to_vec()
+    );
+    assert_eq!(
+        payloads[1].0,
+        b"1970-01-01 00:00:00.000 WARN  [MyApp] denied category=auth".to_vec()
+    );
+    let record = decode::decode_bytebuffer(&payloads[2].0).expect("record");
+    assert_eq!(record.message, "charged");
+    assert_eq!(
+        record.kvs,
+        vec![
+            (
+                "category".to_string(),
+                DecodedValue::String("billing".to_string())
+            ),
+            ("amount".to_string(), DecodedValue::I64(42)),
+        ]
+    );
+}
+
+//
+// This tests that a record logged with a timestamp is sent with it, rather than the time of
+// the logger's clock, and that following records aren't.
+#[test]
+fn timestamp_override() {
+    use chrono::TimeZone;
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .build()
+        .expect("logger");
+    let event_time = chrono::Utc.with_ymd_and_hms(2021, 3, 4, 5, 6, 7).unwrap();
+    info!(timestamp: event_time, logger: &logger, target: "MyApp", "replayed");
+    info!(logger: &logger, target: "MyApp", "live");
+    drop(logger);
+
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .build()
+        .expect("logger");
+    info!(timestamp: event_time, logger: &logger, target: "MyApp", "replayed");
+
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(
+        payloads[0].0,
+        b"2021-03-04 05:06:07.000 INFO  [MyApp] replayed".to_vec()
+    );
+    assert_eq!(
+        payloads[1].0,
+        b"1970-01-01 00:00:00.000 INFO  [MyApp] live".to_vec()
+    );
+    let record = udp_logger_rs::decode::decode_bytebuffer(&payloads[2].0).expect("record");
+    assert_eq!(record.timestamp, Some(event_time.timestamp_millis()));
+}
+
+//
+// This tests that loggers with ephemeral sources don't collide, and send from the port
+// they report.
+#[test]
+fn ephemeral_source() {
+    let _serial = serialize();
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").expect("unable to bind");
+    let destination = socket.local_addr().expect("local addr").to_string();
+    let loggers: Vec<udp_logger_rs::UdpLogger> = (0..2)
+        .map(|_logger| {
+            udp_logger_rs::UdpLogger::default()
+                .with_ephemeral_source()
+                .with_destination(&destination)
+                .build()
+                .expect("logger")
+        })
+        .collect();
+    let first = loggers[0].source_addr().expect("source addr");
+    let second = loggers[1].source_addr().expect("source addr");
+    assert_ne!(first.port(), 0);
+    assert_ne!(first, second);
+
+    info!(logger: &loggers[1], target: "MyApp", "ephemeral");
+    let mut buf = [0; 4096];
+    let (_byte_count, src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(src_addr, second);
+}
+
+//
+// This tests that a connected socket sends to the default destination, reports the
+// destination being unreachable, and isn't connected when records are routed.
+#[test]
+fn connected_socket() {
+    let _serial = serialize();
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").expect("unable to bind");
+    let destination = socket.local_addr().expect("local addr").to_string();
+    let errors = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let reported = errors.clone();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_ephemeral_source()
+        .with_destination(&destination)
+        .with_connected_socket()
+        .with_error_handler(Box::new(move |err| {
+            reported.lock().unwrap().push(err.kind());
+        }))
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "connected");
+    let mut buf = [0; 4096];
+    let (byte_count, src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert!(buf[..byte_count].ends_with(b"[MyApp] connected"));
+    assert_eq!(src_addr, logger.source_addr().expect("source addr"));
+
+    // the ICMP port unreachable of a send fails the following send
+    drop(socket);
+    info!(logger: &logger, target: "MyApp", "unreachable");
+    std::thread::sleep(std::time::Duration::from_millis(50));
+    info!(logger: &logger, target: "MyApp", "unreachable");
+    assert!(errors
+        .lock()
+        .unwrap()
+        .contains(&std::io::ErrorKind::ConnectionRefused));
+    drop(logger);
+
+    // routed records are sent from an unconnected socket
+    let socket = std::net::UdpSocket::bind("127.0.0.1:0").expect("unable to bind");
+    let routed = std::net::UdpSocket::bind("127.0.0.1:0").expect("unable to bind");
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_ephemeral_source()
+        .with_destination(&socket.local_addr().expect("local addr").to_string())
+        .with_destination_level(
+            &routed.local_addr().expect("local addr").to_string(),
+            udp_logger_rs::LevelFilter::Error,
+        )
+        .with_connected_socket()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "default");
+    error!(logger: &logger, target: "MyApp", "routed");
+    let (byte_count, _src_addr) = socket.recv_from(&mut buf).expect("udp datagram");
+    assert!(buf[..byte_count].ends_with(b"[MyApp] default"));
+    let (byte_count, _src_addr) = routed.recv_from(&mut buf).expect("udp datagram");
+    assert!(buf[..byte_count].ends_with(b"[MyApp] routed"));
+}
+
+#[test]
+fn level_source_addr() {
+    let _serial = serialize();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_ephemeral_source()
+        .with_source_level("127.0.0.1:0", udp_logger_rs::LevelFilter::Warn)
+        .build()
+        .expect("logger");
+    let default = logger.source_addr().expect("source addr");
+    let warn = logger
+        .source_addr_for(log::Level::Warn)
+        .expect("source addr");
+    assert_ne!(default, warn);
+    assert_eq!(logger.source_addr_for(log::Level::Error).unwrap(), warn);
+    assert_eq!(logger.source_addr_for(log::Level::Info).unwrap(), default);
+
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(MemoryTransport::default()))
+        .build()
+        .expect("logger");
+    assert_eq!(
+        logger.source_addr().unwrap_err().kind(),
+        std::io::ErrorKind::Unsupported
+    );
+}
+
+//
+// This tests that the receiver reassembles, verifies and decodes ByteBuffer payloads.
+#[cfg(feature = "receiver")]
+#[test]
+fn udp_log_receiver() {
+    use udp_logger_rs::decode::{Received, UdpLogReceiver};
+    let _serial = serialize();
+    let mut receiver = UdpLogReceiver::bind("127.0.0.1:4395", udp_logger_rs::WireFmt::ByteBuffer)
+        .expect("receiver")
+        .with_checksum();
+    receiver
+        .set_read_timeout(Some(std::time::Duration::from_secs(1)))
+        .expect("timeout");
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_destination("127.0.0.1:4395")
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .with_checksum(true)
+        .with_oversize_policy(udp_logger_rs::OversizePolicy::Fragment)
+        .with_max_datagram_size(100)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "{}", "r".repeat(300));
+    error!(logger: &logger, target: "MyApp", "received");
+
+    let records: Vec<_> = receiver
+        .records()
+        .take(2)
+        .map(|received| match received {
+            Ok(Received::Record(record)) => (record.level, record.message),
+            other => panic!("unexpected {:?}", other),
+        })
+        .collect();
+    assert_eq!(
+        records,
+        vec![
+            (log::Level::Info, "r".repeat(300)),
+            (log::Level::Error, "received".to_string()),
+        ]
+    );
+}
+
+//
+// This tests that the values of redacted keys never appear in payloads.
+#[test]
+fn redacted_keys() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let kvs = [
+        ("user", log::kv::Value::from("nori")),
+        ("password", log::kv::Value::from("hunter2")),
+        ("Token", log::kv::Value::from("s3cr3t")),
+    ];
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_redacted_keys(&["password", "token"])
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", kvs: &kvs, "login");
+    drop(logger);
+
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_global_field("token", "global")
+        .with_redacted_keys(&["password", "token"])
+        .with_redaction(udp_logger_rs::Redaction {
+            drop: true,
+            ignore_case: true,
+        })
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", kvs: &kvs, "login");
+
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(
+        payloads[0].0,
+        b"1970-01-01 00:00:00.000 INFO  [MyApp] login user=nori password=[REDACTED] Token=s3cr3t"
+            .to_vec()
+    );
+    assert_eq!(
+        payloads[1].0,
+        b"1970-01-01 00:00:00.000 INFO  [MyApp] login user=nori".to_vec()
+    );
+}
+
+//
+// This tests that kv pairs beyond the limit are omitted, and counted.
+#[test]
+fn max_kv_pairs() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let kvs: Vec<(String, String)> = (0..5000)
+        .map(|index| (format!("k{}", index), format!("v{}", index)))
+        .collect();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .with_max_kv_pairs(3)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", kvs: &kvs, "many");
+    drop(logger);
+
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .with_max_kv_pairs(2)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", kvs: &kvs, "many");
+
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(
+        payloads[0].0,
+        b"1970-01-01 00:00:00.000 INFO  [MyApp] many k0=v0 k1=v1 k2=v2 ...(+4997 more)".to_vec()
+    );
+    let record = udp_logger_rs::decode::decode_bytebuffer(&payloads[1].0).expect("record");
+    assert_eq!(
+        record.kvs.last(),
+        Some(&(
+            "kvs_omitted".to_string(),
+            udp_logger_rs::decode::DecodedValue::I64(4998)
+        ))
+    );
+    assert_eq!(record.kvs.len(), 3);
+}
+
+// A clock, counting the times it's read.
+#[derive(Debug, Default)]
+struct CountingClock(std::sync::atomic::AtomicUsize);
+
+impl udp_logger_rs::Clock for CountingClock {
+    fn now_millis(&self) -> i64 {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        0
+    }
+    fn format_now(&self, _fmt: &str) -> String {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        String::new()
+    }
+}
+
+// A message argument, and kv pairs, counting the times they're formatted and visited.
+#[derive(Default)]
+struct Counted(std::sync::atomic::AtomicUsize);
+
+impl std::fmt::Display for Counted {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        f.write_str("counted")
+    }
+}
+
+impl log::kv::Source for Counted {
+    fn visit<'kvs>(
+        &'kvs self,
+        visitor: &mut dyn log::kv::Visitor<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        self.0.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        visitor.visit_pair("key".into(), "value".into())
+    }
+}
+
+//
+// This tests that a record below the level is rejected before any formatting, reading
+// of the clock, or visiting of kv pairs.
+#[test]
+fn disabled_records() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let clock = std::sync::Arc::new(CountingClock::default());
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_level(log::LevelFilter::Info)
+        .with_module_level("MyApp::verbose", log::LevelFilter::Debug)
+        .with_clock(clock.clone())
+        .with_transport(Box::new(transport.clone()))
+        .build()
+        .expect("logger");
+    let counted = Counted::default();
+    for _ in 0..1000 {
+        trace!(logger: &logger, target: "MyApp", kvs: &counted, "{}", counted);
+        debug!(logger: &logger, target: "MyApp", kvs: &counted, "{}", counted);
+    }
+    assert_eq!(counted.0.load(std::sync::atomic::Ordering::Relaxed), 0);
+    assert_eq!(clock.0.load(std::sync::atomic::Ordering::Relaxed), 0);
+    assert!(transport.0.lock().unwrap().is_empty());
+
+    debug!(logger: &logger, target: "MyApp::verbose", kvs: &counted, "{}", counted);
+    assert_eq!(transport.0.lock().unwrap().len(), 1);
+}
+
+//
+// This tests that, with a tokio runtime, payloads are sent from a task on the runtime.
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_sender() {
+    let _serial = serialize();
+    let runtime = tokio::runtime::Runtime::new().expect("runtime");
+    let receiver = std::net::UdpSocket::bind("127.0.0.1:4397").expect("receiver");
+    receiver
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_source("127.0.0.1:4396")
+        .with_destination("127.0.0.1:4397")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_tokio(runtime.handle().clone(), 16)
+        .build()
+        .expect("logger");
+    for index in 0..3 {
+        info!(logger: &logger, target: "MyApp", "from the runtime {}", index);
+    }
+    log::Log::flush(&logger);
+    let mut buf = [0; 1024];
+    for index in 0..3 {
+        let (len, src_addr) = receiver.recv_from(&mut buf).expect("udp datagram");
+        assert_eq!(src_addr.to_string(), "127.0.0.1:4396");
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            format!(
+                "1970-01-01 00:00:00.000 INFO  [MyApp] from the runtime {}",
+                index
+            )
+        );
+    }
+    assert_eq!(logger.stats().sent, 3);
+}
+
+//
+// This tests that dropping a logger sends the payloads it holds, and that a handle's
+// shutdown leaves the logger inert.
+#[test]
+fn shutdown() {
+    let _serial = serialize();
+    let transport = BusyTransport::default();
+    transport.0.store(1, std::sync::atomic::Ordering::Relaxed);
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport))
+        .build()
+        .expect("logger");
+    let stats = logger.stats_handle();
+    info!(logger: &logger, target: "MyApp", "held while the transport is busy");
+    assert_eq!(stats.stats().sent, 0);
+    logger.shutdown();
+    assert_eq!(stats.stats().sent, 1);
+
+    let _result = log::set_logger(PROXY_LOGGER.log_interface());
+    let transport = MemoryTransport::default();
+    let (udp_logger, handle) = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .partial_init_with_handle();
+    PROXY_LOGGER.set_logger(udp_logger);
+    info!(target: "MyApp", "before shutdown");
+    handle.shutdown();
+    info!(target: "MyApp", "after shutdown");
+    handle.set_level(udp_logger_rs::LevelFilter::Trace);
+    error!(target: "MyApp", "after shutdown");
+    assert_eq!(log::max_level(), udp_logger_rs::LevelFilter::Off);
+    assert_eq!(transport.0.lock().unwrap().len(), 1);
+}
+
+//
+// This tests that the wire format can be changed through the handle.
+#[test]
+fn handle_wire_fmt() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let (logger, handle) = udp_logger_rs::UdpLogger::default()
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_transport(Box::new(transport.clone()))
+        .partial_init_with_handle();
+    info!(logger: &logger, target: "MyApp", "uncompressed");
+    handle.set_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer);
+    info!(logger: &logger, target: "MyApp", "byte buffer");
+
+    let captured = transport.0.lock().unwrap();
+    assert_eq!(
+        captured[0].0,
+        b"1970-01-01 00:00:00.000 INFO  [MyApp] uncompressed"
+    );
+    let record = udp_logger_rs::decode::decode_bytebuffer(&captured[1].0).expect("ByteBuffer");
+    assert_eq!(record.message, "byte buffer");
+}
+
+//
+// This tests that globs match modules, and their sub-modules, with the most specific taken.
+#[test]
+fn module_globs() {
+    let _serial = serialize();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(MemoryTransport::default()))
+        .with_level(udp_logger_rs::LevelFilter::Info)
+        .with_module_glob("*::internal", udp_logger_rs::LevelFilter::Off)
+        .with_module_glob("app::*::db", udp_logger_rs::LevelFilter::Trace)
+        .with_module_glob("app::*", udp_logger_rs::LevelFilter::Warn)
+        .with_module_level("app::net", udp_logger_rs::LevelFilter::Debug)
+        .with_module_glob("*cache*", udp_logger_rs::LevelFilter::Error)
+        .build()
+        .expect("logger");
+    let level = |target: &str| {
+        [
+            Level::Trace,
+            Level::Debug,
+            Level::Info,
+            Level::Warn,
+            Level::Error,
+        ]
+        .iter()
+        .copied()
+        .find(|level| {
+            log::Log::enabled(
+                &logger,
+                &log::Metadata::builder()
+                    .level(*level)
+                    .target(target)
+                    .build(),
+            )
+        })
+    };
+    // a leading wildcard, matching sub-modules too
+    assert_eq!(level("store::internal"), None);
+    assert_eq!(level("store::internal::io"), None);
+    assert_eq!(level("internal"), Some(Level::Info));
+    // an inner wildcard, spanning modules
+    assert_eq!(level("app::users::db"), Some(Level::Trace));
+    assert_eq!(level("app::users::pool::db"), Some(Level::Trace));
+    assert_eq!(level("app::users::dbx"), Some(Level::Warn));
+    // a trailing wildcard, matching only sub-modules
+    assert_eq!(level("app::users"), Some(Level::Warn));
+    assert_eq!(level("app"), Some(Level::Info));
+    // a module more specific than a glob
+    assert_eq!(level("app::net"), Some(Level::Debug));
+    // wildcards on both sides
+    assert_eq!(level("lru_cache_map"), Some(Level::Error));
+}
+
+//
+// This tests that a regular expression can enable two crates, but not a third, and that
+// modules take precedence over it.
+#[cfg(feature = "regex")]
+#[test]
+fn regex_levels() {
+    let _serial = serialize();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(MemoryTransport::default()))
+        .with_level(udp_logger_rs::LevelFilter::Warn)
+        .with_regex_level(
+            regex::Regex::new("^(auth|billing)::").unwrap(),
+            udp_logger_rs::LevelFilter::Debug,
+        )
+        .with_module_level("billing::ledger", udp_logger_rs::LevelFilter::Error)
+        .build()
+        .expect("logger");
+    let enabled = |level: Level, target: &str| {
+        log::Log::enabled(
+            &logger,
+            &log::Metadata::builder().level(level).target(target).build(),
+        )
+    };
+    assert!(enabled(Level::Debug, "auth::session"));
+    assert!(enabled(Level::Debug, "billing::invoice"));
+    assert!(!enabled(Level::Trace, "billing::invoice"));
+    assert!(!enabled(Level::Debug, "shipping::label"));
+    assert!(enabled(Level::Warn, "shipping::label"));
+    assert!(!enabled(Level::Warn, "billing::ledger"));
+}
+
+//
+// This tests that module levels, set together, replace those set before.
+#[test]
+fn replace_module_levels() {
+    let _serial = serialize();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(MemoryTransport::default()))
+        .with_level(udp_logger_rs::LevelFilter::Info)
+        .with_module_level("stale", udp_logger_rs::LevelFilter::Off)
+        .with_module_levels(vec![
+            ("quiet".to_string(), udp_logger_rs::LevelFilter::Debug),
+            ("quiet".to_string(), udp_logger_rs::LevelFilter::Error),
+            ("quiet::loud".to_string(), udp_logger_rs::LevelFilter::Trace),
+        ])
+        .build()
+        .expect("logger");
+    let enabled = |level: Level, target: &str| {
+        log::Log::enabled(
+            &logger,
+            &log::Metadata::builder().level(level).target(target).build(),
+        )
+    };
+    assert!(enabled(Level::Info, "stale"));
+    assert!(!enabled(Level::Debug, "stale"));
+    assert!(enabled(Level::Error, "quiet"));
+    assert!(!enabled(Level::Warn, "quiet"));
+    assert!(enabled(Level::Trace, "quiet::loud"));
+}
+
+//
+// This tests that level specific sources, and destinations, can be provided together.
+#[test]
+fn bulk_sources_and_destinations() {
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_destinations(vec![
+            (
+                udp_logger_rs::LevelFilter::Info,
+                "info-collector".to_string(),
+            ),
+            (
+                udp_logger_rs::LevelFilter::Error,
+                "error-collector".to_string(),
+            ),
+        ])
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "info");
+    error!(logger: &logger, target: "MyApp", "error");
+    debug!(logger: &logger, target: "MyApp", "debug");
+    let destinations: Vec<String> = transport
+        .0
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_payload, destination)| destination.clone())
+        .collect();
+    assert_eq!(
+        destinations,
+        ["info-collector", "error-collector", "127.0.0.1:4010"]
+    );
+    drop(logger);
+
+    let result = udp_logger_rs::UdpLogger::default()
+        .with_sources(vec![
+            (udp_logger_rs::LevelFilter::Info, "127.0.0.1:4398"),
+            (udp_logger_rs::LevelFilter::Error, "not an address"),
+        ])
+        .build();
+    assert!(matches!(
+        result,
+        Err(udp_logger_rs::UdpLoggerError::Bind(_, addr)) if addr == "not an address"
+    ));
+}
+
+//
+// This tests that a configuration file is deserialized into a working logger.
+#[cfg(feature = "serde")]
+#[test]
+fn from_config() {
+    use udp_logger_rs::{LevelFilter, UdpLoggerConfig, WireFmt};
+    let _serial = serialize();
+    let config: UdpLoggerConfig = serde_json::from_str(
+        r#"{
+            "level": "info",
+            "module_levels": [["MyApp::net", "DEBUG"]],
+            "source": "127.0.0.1:4399",
+            "destination": "127.0.0.1:4400",
+            "destinations": [["error", "127.0.0.1:4401"]],
+            "wire_fmt": "ByteBuffer"
+        }"#,
+    )
+    .expect("config");
+    assert_eq!(
+        config,
+        UdpLoggerConfig {
+            level: LevelFilter::Info,
+            module_levels: vec![("MyApp::net".to_string(), LevelFilter::Debug)],
+            source: Some("127.0.0.1:4399".to_string()),
+            destination: Some("127.0.0.1:4400".to_string()),
+            destinations: vec![(LevelFilter::Error, "127.0.0.1:4401".to_string())],
+            wire_fmt: WireFmt::ByteBuffer,
+            ..UdpLoggerConfig::default()
+        }
+    );
+
+    let default_collector = std::net::UdpSocket::bind("127.0.0.1:4400").expect("collector");
+    let error_collector = std::net::UdpSocket::bind("127.0.0.1:4401").expect("collector");
+    for collector in [&default_collector, &error_collector] {
+        collector
+            .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+            .unwrap();
+    }
+    let logger = udp_logger_rs::UdpLogger::from_config(config)
+        .build()
+        .expect("logger");
+    debug!(logger: &logger, target: "MyApp", "filtered");
+    debug!(logger: &logger, target: "MyApp::net", "module level");
+    error!(logger: &logger, target: "MyApp", "error destination");
+
+    let mut buf = [0; 1024];
+    for (collector, message) in [
+        (&default_collector, "module level"),
+        (&error_collector, "error destination"),
+    ] {
+        let (len, src_addr) = collector.recv_from(&mut buf).expect("udp datagram");
+        assert_eq!(src_addr.to_string(), "127.0.0.1:4399");
+        let record = udp_logger_rs::decode::decode_bytebuffer(&buf[..len]).expect("ByteBuffer");
+        assert_eq!(record.message, message);
+    }
+}
+
+//
+// This tests that wire formats are parsed, case insensitively, and displayed by name.
+#[test]
+fn wire_fmt_from_str() {
+    use udp_logger_rs::WireFmt;
+    let _serial = serialize();
+    assert_eq!("Uncompressed".parse(), Ok(WireFmt::Uncompressed));
+    assert_eq!("BYTEBUFFER".parse(), Ok(WireFmt::ByteBuffer));
+    assert_eq!("logfmt".parse(), Ok(WireFmt::Logfmt));
+    for wire_fmt in [WireFmt::Uncompressed, WireFmt::ByteBuffer, WireFmt::Logfmt] {
+        assert_eq!(wire_fmt.to_string().parse(), Ok(wire_fmt));
+    }
+    #[cfg(feature = "gzip")]
+    {
+        assert_eq!("gzip".parse(), Ok(WireFmt::GzipUncompressed(6)));
+        assert_eq!("Gzip:9".parse(), Ok(WireFmt::GzipUncompressed(9)));
+        assert_eq!(WireFmt::GzipUncompressed(1).to_string(), "gzip:1");
+        assert!("gzip:10".parse::<WireFmt>().is_err());
+    }
+    let err = "xml".parse::<WireFmt>().unwrap_err();
+    assert!(err
+        .to_string()
+        .starts_with("unknown wire format \"xml\", expected one of uncompressed, bytebuffer"));
+    assert!("logfmt:1".parse::<WireFmt>().is_err());
+}
+
+//
+// This tests that bytes logged by log_bytes are the ByteBuffer message field, decoded as raw,
+// and are base64 encoded as the message of an Uncompressed payload.
+#[test]
+fn log_bytes() {
+    use udp_logger_rs::decode;
+    let _serial = serialize();
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(udp_logger_rs::WireFmt::ByteBuffer)
+        .without_timestamp()
+        .build()
+        .expect("logger");
+    logger.log_bytes(
+        udp_logger_rs::Level::Info,
+        "frames",
+        &[0x00, 0xFF, 0x80, 0x41],
+    );
+    info!(logger: &logger, target: "frames", "text");
+
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(
+        payloads[0].0[2],
+        udp_logger_rs::BYTE_BUFFER_VERSION_RAW_NO_TIMESTAMP
+    );
+    let record = decode::decode_bytebuffer(&payloads[0].0).expect("raw record");
+    assert_eq!(record.target, "frames");
+    assert_eq!(record.raw, Some(vec![0x00, 0xFF, 0x80, 0x41]));
+    assert_eq!(record.message, "AP+AQQ==");
+    let record = decode::decode_bytebuffer(&payloads[1].0).expect("text record");
+    assert_eq!(record.raw, None);
+    assert_eq!(record.message, "text");
+    drop(payloads);
+
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_level(udp_logger_rs::LevelFilter::Info)
+        .without_timestamp()
+        .build()
+        .expect("logger");
+    logger.log_bytes(udp_logger_rs::Level::Info, "frames", b"ab");
+    logger.log_bytes(udp_logger_rs::Level::Trace, "frames", b"dropped");
+    let payloads = transport.0.lock().unwrap();
+    assert_eq!(payloads.len(), 1);
+    assert_eq!(
+        String::from_utf8_lossy(&payloads[0].0),
+        "INFO  [frames] YWI="
+    );
+}
+
+//
+// This tests that ByteBufferBase64 payloads are a line of base64, which decodes to the
+// ByteBuffer payload, checksum included, and which the receiver decodes.
+#[test]
+fn byte_buffer_base64() {
+    use udp_logger_rs::{decode, WireFmt};
+    let _serial = serialize();
+    assert_eq!("ByteBufferBase64".parse(), Ok(WireFmt::ByteBufferBase64));
+    assert_eq!(WireFmt::ByteBufferBase64.to_string(), "bytebufferbase64");
+
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_wire_fmt(WireFmt::ByteBufferBase64)
+        .with_checksum(true)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "over text");
+    logger.log_bytes(udp_logger_rs::Level::Warn, "MyApp", &[0xFF, 0x00]);
+
+    let payloads = transport.0.lock().unwrap();
+    let line = &payloads[0].0;
+    assert_eq!(line.last(), Some(&b'\n'));
+    assert!(line[..line.len() - 1]
+        .iter()
+        .all(|byte| byte.is_ascii_alphanumeric() || b"+/=".contains(byte)));
+    let payload = decode::decode_base64(line).expect("base64");
+    let record = decode::verify_checksum(&payload)
+        .and_then(decode::decode_bytebuffer)
+        .expect("record");
+    assert_eq!(record.target, "MyApp");
+    assert_eq!(record.message, "over text");
+    let payload = decode::decode_base64(&payloads[1].0).expect("base64");
+    let record = decode::verify_checksum(&payload)
+        .and_then(decode::decode_bytebuffer)
+        .expect("raw record");
+    assert_eq!(record.raw, Some(vec![0xFF, 0x00]));
+    assert!(decode::decode_base64(b"VUw").is_err());
+    assert!(decode::decode_base64(b"V=Uw").is_err());
+    drop(payloads);
+
+    #[cfg(feature = "receiver")]
+    {
+        use udp_logger_rs::decode::{Received, UdpLogReceiver};
+        let mut receiver = UdpLogReceiver::bind("127.0.0.1:0", WireFmt::ByteBufferBase64)
+            .expect("receiver")
+            .with_checksum();
+        receiver
+            .set_read_timeout(Some(std::time::Duration::from_secs(1)))
+            .expect("timeout");
+        let logger = udp_logger_rs::UdpLogger::default()
+            .with_ephemeral_source()
+            .with_destination(&receiver.local_addr().expect("addr").to_string())
+            .with_wire_fmt(WireFmt::ByteBufferBase64)
+            .with_checksum(true)
+            .build()
+            .expect("logger");
+        info!(logger: &logger, target: "MyApp", "received");
+        match receiver.recv().expect("datagram") {
+            Ok(Received::Record(record)) => assert_eq!(record.message, "received"),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+}
+
+//
+// This tests that, with broadcast enabled, an IPv6 destination, which can't be a broadcast
+// address, is returned as an error naming it, rather than panicking.
+#[test]
+fn broadcast_destination_error() {
+    let _serial = serialize();
+    let result = udp_logger_rs::UdpLogger::default()
+        .with_ephemeral_source()
+        .with_destination("255.255.255.255:4010")
+        .with_destination_level("[::1]:4010", udp_logger_rs::LevelFilter::Error)
+        .with_broadcast()
+        .build();
+    match result {
+        Err(err @ udp_logger_rs::UdpLoggerError::NotBroadcast(..)) => {
+            assert_eq!(
+                err.to_string(),
+                "destination [::1]:4010 is not a broadcast address"
+            )
+        }
+        other => panic!("expected a broadcast error, got {:?}", other),
+    }
+}
+
+//
+// This tests that, with broadcast enabled, payloads are sent to the directed broadcast
+// address of the loopback network, which fails without it. Linux delivers loopback
+// broadcasts to sockets bound to the wildcard address.
+#[cfg(target_os = "linux")]
+#[test]
+fn broadcast_send() {
+    let _serial = serialize();
+    let receiver = std::net::UdpSocket::bind("0.0.0.0:0").expect("unable to bind");
+    receiver
+        .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+        .unwrap();
+    let destination = format!(
+        "127.255.255.255:{}",
+        receiver.local_addr().expect("local addr").port()
+    );
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_ephemeral_source()
+        .with_destination(&destination)
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .with_broadcast()
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "broadcast logging");
+    let mut buf = [0; 1024];
+    let (len, _src_addr) = receiver.recv_from(&mut buf).expect("udp datagram");
+    assert_eq!(
+        std::str::from_utf8(&buf[..len]).unwrap(),
+        "1970-01-01 00:00:00.000 INFO  [MyApp] broadcast logging"
+    );
+    assert_eq!(logger.stats().sent, 1);
+
+    let errors = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let counter = errors.clone();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_ephemeral_source()
+        .with_destination(&destination)
+        .with_error_handler(Box::new(move |err| {
+            assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+            counter.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }))
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "not broadcast");
+    assert_eq!(errors.load(std::sync::atomic::Ordering::Relaxed), 1);
+}
+
+//
+// This tests that a malformed, or non-multicast, group, and a malformed interface, are
+// returned by build(), rather than panicking in with_multicast().
+#[test]
+fn multicast_errors() {
+    use udp_logger_rs::UdpLoggerError;
+    let _serial = serialize();
+    for (group, interface, expected) in [
+        ("239.255.0.1;4010", "0.0.0.0", "239.255.0.1;4010"),
+        ("10.1.2.3:4010", "0.0.0.0", "10.1.2.3:4010"),
+        ("239.255.0.1:4010", "eth0", "eth0"),
+    ] {
+        let result = udp_logger_rs::UdpLogger::default()
+            .with_ephemeral_source()
+            .with_multicast(group, interface)
+            .build();
+        match result {
+            Err(UdpLoggerError::Resolve(_err, addr)) | Err(UdpLoggerError::Bind(_err, addr)) => {
+                assert_eq!(addr, expected)
+            }
+            other => panic!("expected an error for {}, got {:?}", expected, other),
+        }
+    }
+}
+
+//
+// This tests that payloads sent to a multicast group, through the loopback interface, are
+// looped back to each socket on this host which joined the group.
+#[cfg(target_os = "linux")]
+#[test]
+fn multicast_loopback() {
+    let _serial = serialize();
+    let group = std::net::Ipv4Addr::new(239, 255, 0, 44);
+    let receivers: Vec<std::net::UdpSocket> = (0..2)
+        .map(|_| {
+            // both receivers share the group's port
+            let socket = socket2::Socket::new(socket2::Domain::IPV4, socket2::Type::DGRAM, None)
+                .expect("socket");
+            socket.set_reuse_address(true).expect("reuse address");
+            let addr: std::net::SocketAddr = "0.0.0.0:4410".parse().unwrap();
+            socket.bind(&addr.into()).expect("unable to bind");
+            let socket: std::net::UdpSocket = socket.into();
+            socket
+                .join_multicast_v4(&group, &std::net::Ipv4Addr::LOCALHOST)
+                .expect("join group");
+            socket
+                .set_read_timeout(Some(std::time::Duration::from_secs(5)))
+                .unwrap();
+            socket
+        })
+        .collect();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_source("0.0.0.0:0")
+        .with_multicast("239.255.0.44:4410", "127.0.0.1")
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .build()
+        .expect("logger");
+
+    info!(logger: &logger, target: "MyApp", "multicast logging");
+    for receiver in &receivers {
+        let mut buf = [0; 1024];
+        let (len, _src_addr) = receiver.recv_from(&mut buf).expect("udp datagram");
+        assert_eq!(
+            std::str::from_utf8(&buf[..len]).unwrap(),
+            "1970-01-01 00:00:00.000 INFO  [MyApp] multicast logging"
+        );
+    }
+}
+
+//
+// This tests that the payloads held for flush, when sends would block, are bounded, the
+// oldest being dropped, counted and reported.
+#[test]
+fn pending_payloads_bounded() {
+    let _serial = serialize();
+    let transport = BusyTransport::default();
+    let errors = std::sync::Arc::new(std::sync::atomic::AtomicU32::new(0));
+    let reported = errors.clone();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_error_handler(Box::new(move |_err| {
+            reported.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        }))
+        .build()
+        .expect("logger");
+    let stats = logger.stats_handle();
+
+    transport
+        .0
+        .store(u32::MAX, std::sync::atomic::Ordering::Relaxed);
+    for index in 0..1026 {
+        info!(logger: &logger, "held {}", index);
+    }
+    assert_eq!(stats.stats().dropped, 2);
+    assert_eq!(errors.load(std::sync::atomic::Ordering::Relaxed), 2);
+
+    transport.0.store(0, std::sync::atomic::Ordering::Relaxed);
+    log::Log::flush(&logger);
+    assert_eq!(stats.stats().sent, 1024);
+}
+
+//
+// This tests that a held payload is sent before the next payload, without a flush, so that
+// payloads are sent in order.
+#[test]
+fn pending_payloads_in_order() {
+    #[derive(Clone, Debug, Default)]
+    struct OnceBusyTransport(std::sync::Arc<std::sync::Mutex<(bool, Vec<String>)>>);
+
+    impl udp_logger_rs::Transport for OnceBusyTransport {
+        fn send(&self, bytes: &[u8], _dest: &str) -> std::io::Result<usize> {
+            let mut state = self.0.lock().unwrap();
+            if !state.0 {
+                state.0 = true;
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            state.1.push(String::from_utf8_lossy(bytes).into_owned());
+            Ok(bytes.len())
+        }
+    }
+
+    let _serial = serialize();
+    let transport = OnceBusyTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_clock(std::sync::Arc::new(EpochClock))
+        .build()
+        .expect("logger");
+    let stats = logger.stats_handle();
+
+    info!(logger: &logger, target: "MyApp", "held");
+    assert_eq!(stats.stats().sent, 0);
+    info!(logger: &logger, target: "MyApp", "after held");
+    assert_eq!(stats.stats().sent, 2);
+    assert_eq!(
+        transport.0.lock().unwrap().1,
+        [
+            "1970-01-01 00:00:00.000 INFO  [MyApp] held",
+            "1970-01-01 00:00:00.000 INFO  [MyApp] after held",
+        ]
+    );
+}
+
+//
+// This tests that a sampling fraction out of range, or NaN, is returned by build(), and that
+// the smallest fractions keep the first record.
+#[test]
+fn sampling_errors() {
+    let _serial = serialize();
+    for fraction in [0.0, -0.5, 1.5, f64::NAN] {
+        let result = udp_logger_rs::UdpLogger::default()
+            .with_null_sink()
+            .with_sampling(Level::Info, fraction)
+            .build();
+        match result {
+            Err(udp_logger_rs::UdpLoggerError::Sampling(_fraction)) => (),
+            other => panic!(
+                "expected a sampling error for {}, got {:?}",
+                fraction, other
+            ),
+        }
+    }
+
+    let transport = MemoryTransport::default();
+    let logger = udp_logger_rs::UdpLogger::default()
+        .with_transport(Box::new(transport.clone()))
+        .with_sampling(Level::Info, f64::MIN_POSITIVE)
+        .build()
+        .expect("logger");
+    info!(logger: &logger, target: "MyApp", "kept");
+    info!(logger: &logger, target: "MyApp", "sampled out");
+    assert_eq!(transport.0.lock().unwrap().len(), 1);
+}
+
+//
+// This tests that a fragment size which doesn't exceed the fragment header is returned by
+// build().
+#[test]
+fn fragment_size_error() {
+    let _serial = serialize();
+    let result = udp_logger_rs::UdpLogger::default()
+        .with_null_sink()
+        .with_oversize_policy(udp_logger_rs::OversizePolicy::Fragment)
+        .with_fragment_size(10)
+        .build();
+    match result {
+        Err(udp_logger_rs::UdpLoggerError::FragmentSize(10)) => (),
+        other => panic!("expected a fragment size error, got {:?}", other),
+    }
+}
+
+//
+// This tests that a max datagram size of less than 64 bytes is returned by build().
+#[test]
+fn datagram_size_error() {
+    let _serial = serialize();
+    let result = udp_logger_rs::UdpLogger::default()
+        .with_null_sink()
+        .with_max_datagram_size(63)
+        .build();
+    match result {
+        Err(udp_logger_rs::UdpLoggerError::DatagramSize(63)) => (),
+        other => panic!("expected a datagram size error, got {:?}", other),
+    }
+}
+
+//
+// This tests that a background sender queue capacity of 0 is returned by build().
+#[test]
+fn queue_capacity_error() {
+    let _serial = serialize();
+    let result = udp_logger_rs::UdpLogger::default()
+        .with_null_sink()
+        .with_async_sender(0)
+        .build();
+    match result {
+        Err(udp_logger_rs::UdpLoggerError::QueueCapacity(0)) => (),
+        other => panic!("expected a queue capacity error, got {:?}", other),
+    }
+}
+
+//
+// This tests that a tokio sender queue capacity of 0 is returned by build().
+#[cfg(feature = "tokio")]
+#[test]
+fn tokio_queue_capacity_error() {
+    let _serial = serialize();
+    let runtime = tokio::runtime::Runtime::new().expect("runtime");
+    let result = udp_logger_rs::UdpLogger::default()
+        .with_null_sink()
+        .with_tokio(runtime.handle().clone(), 0)
+        .build();
+    match result {
+        Err(udp_logger_rs::UdpLoggerError::QueueCapacity(0)) => (),
+        other => panic!("expected a queue capacity error, got {:?}", other),
+    }
+}
+
+//
+// This tests that an HMAC tag length outside of 16 to 32 bytes is returned by build().
+#[cfg(feature = "signing")]
+#[test]
+fn hmac_len_error() {
+    let _serial = serialize();
+    for bytes in [15, 33] {
+        let result = udp_logger_rs::UdpLogger::default()
+            .with_null_sink()
+            .with_hmac_key(b"shared secret")
+            .with_hmac_len(bytes)
+            .build();
+        match result {
+            Err(udp_logger_rs::UdpLoggerError::HmacLen(len)) if len == bytes => (),
+            other => panic!(
+                "expected an hmac length error for {}, got {:?}",
+                bytes, other
+            ),
+        }
+    }
+}